@@ -0,0 +1,205 @@
+use std::{fs, fs::File};
+
+/// Embedded as the window and tray icon by `include_bytes!`.
+const ICON_PATH: &str = "src/assets/ytune.png";
+const MAX_ICON_BYTES: u64 = 1024 * 1024;
+/// Injected into the pages by `include_str!`.
+const SCRIPT_PATHS: [&str; 3] = ["src/js/init.js", "src/js/queue_panel.js", "src/js/video.js"];
+const MAX_SCRIPT_BYTES: usize = 256 * 1024;
+/// Stands for an open `${` on the bracket stack; its `}` resumes the template.
+const INTERPOLATION: char = '$';
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let mut errors = Vec::new();
+    println!("cargo:rerun-if-changed={}", ICON_PATH);
+    if let Err(e) = check_icon(ICON_PATH) {
+        errors.push(format!("{}: {}", ICON_PATH, e));
+    }
+    for path in SCRIPT_PATHS {
+        println!("cargo:rerun-if-changed={}", path);
+        if let Err(e) = check_script(path) {
+            errors.push(format!("{}: {}", path, e));
+        }
+    }
+    if !errors.is_empty() {
+        panic!("bundled assets failed validation:\n  {}", errors.join("\n  "));
+    }
+
+    #[cfg(feature = "grpc")]
+    {
+        // The vendored protoc means building doesn't need one installed.
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no bundled protoc for this platform");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::compile_protos("proto/ytune.proto").expect("failed to compile proto/ytune.proto");
+    }
+}
+
+/// The icon must exist, stay small, and decode as a PNG.
+fn check_icon(path: &str) -> Result<(), String> {
+    let size = fs::metadata(path).map_err(|e| e.to_string())?.len();
+    if size > MAX_ICON_BYTES {
+        return Err(format!("{} bytes, larger than the {} byte limit", size, MAX_ICON_BYTES));
+    }
+
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut reader = png::Decoder::new(file)
+        .read_info()
+        .map_err(|e| format!("not a valid PNG: {}", e))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    reader
+        .next_frame(&mut buffer)
+        .map_err(|e| format!("not a valid PNG: {}", e))?;
+    Ok(())
+}
+
+/// Not a parser, but catches the usual editing accidents: an empty or huge
+/// file, and brackets left unclosed or closed twice.
+fn check_script(path: &str) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    if source.trim().is_empty() {
+        return Err("empty script".to_string());
+    }
+    if source.len() > MAX_SCRIPT_BYTES {
+        return Err(format!("{} bytes, larger than the {} byte limit", source.len(), MAX_SCRIPT_BYTES));
+    }
+    check_brackets(&source)
+}
+
+/// Matches `()`, `[]` and `{}` outside strings, comments and regex literals,
+/// following `${...}` into template literals.
+fn check_brackets(source: &str) -> Result<(), String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut stack: Vec<(char, usize)> = Vec::new();
+    let mut line = 1;
+    // Whether a `/` here would start a regex rather than divide.
+    let mut regex_allowed = true;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '\n' => line += 1,
+            _ if c.is_whitespace() => {}
+            '/' if next == Some('/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if next == Some('*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err(format!("unterminated comment at line {}", line));
+                }
+                i += 2;
+                continue;
+            }
+            '/' if regex_allowed => {
+                let start = line;
+                let mut in_class = false;
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None | Some('\n') => return Err(format!("unterminated regex at line {}", start)),
+                        Some('\\') => i += 1,
+                        Some('[') => in_class = true,
+                        Some(']') => in_class = false,
+                        Some('/') if !in_class => break,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                regex_allowed = false;
+            }
+            '\'' | '"' => {
+                let start = line;
+                i += 1;
+                while chars.get(i) != Some(&c) {
+                    match chars.get(i) {
+                        None | Some('\n') => return Err(format!("unterminated string at line {}", start)),
+                        Some('\\') => i += 1,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                regex_allowed = false;
+            }
+            '`' => {
+                i = skip_template(&chars, i + 1, &mut line, &mut stack)?;
+                regex_allowed = false;
+                continue;
+            }
+            '(' | '[' | '{' => {
+                stack.push((c, line));
+                regex_allowed = true;
+            }
+            ')' | ']' | '}' => {
+                let expected = match c {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                match stack.pop() {
+                    Some((INTERPOLATION, _)) if c == '}' => {
+                        i = skip_template(&chars, i + 1, &mut line, &mut stack)?;
+                        regex_allowed = false;
+                        continue;
+                    }
+                    Some((open, _)) if open == expected => {}
+                    Some((open, open_line)) => {
+                        return Err(format!(
+                            "'{}' at line {} does not close '{}' from line {}",
+                            c, line, open, open_line
+                        ))
+                    }
+                    None => return Err(format!("unmatched '{}' at line {}", c, line)),
+                }
+                // Division is far more common after these than a regex.
+                regex_allowed = false;
+            }
+            _ => regex_allowed = !(c.is_alphanumeric() || c == '_' || c == '$' || c == '.'),
+        }
+        i += 1;
+    }
+
+    match stack.pop() {
+        Some((INTERPOLATION, open_line)) => Err(format!("unterminated template literal at line {}", open_line)),
+        Some((open, open_line)) => Err(format!("unclosed '{}' from line {}", open, open_line)),
+        None => Ok(()),
+    }
+}
+
+/// Skips template literal text from `i` up to the closing backtick, or into
+/// a `${` interpolation, which is pushed on the stack. Returns the index
+/// after where it stopped.
+fn skip_template(
+    chars: &[char],
+    mut i: usize,
+    line: &mut usize,
+    stack: &mut Vec<(char, usize)>,
+) -> Result<usize, String> {
+    let start = *line;
+    loop {
+        match chars.get(i) {
+            None => return Err(format!("unterminated template literal at line {}", start)),
+            Some('`') => return Ok(i + 1),
+            Some('$') if chars.get(i + 1) == Some(&'{') => {
+                stack.push((INTERPOLATION, *line));
+                return Ok(i + 2);
+            }
+            Some('\\') => i += 1,
+            Some('\n') => *line += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+}
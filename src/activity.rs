@@ -0,0 +1,290 @@
+use crate::{
+    config::{self, DiscordActivityType, Mode},
+    template, youtube_url, LastTrackInfo,
+};
+
+const DISCORD_TEXT_MIN_CHARS: usize = 2;
+const DISCORD_TEXT_MAX_CHARS: usize = 128;
+/// The presence lines `compact_presence` uses.
+const COMPACT_DETAILS_TEMPLATE: &str = "{title} - {artist}";
+const COMPACT_STATE_TEMPLATE: &str = "{album}";
+
+/// The SET_ACTIVITY payload for `track` at unix time `now`, or `None` when
+/// there is nothing to show.
+pub fn build_activity(track: &LastTrackInfo, config: &config::Config, now: u64) -> Option<serde_json::Value> {
+    let clean_title = track.title.as_deref().unwrap_or("");
+    let clean_artist = track.artist.as_deref().unwrap_or("");
+    let clean_album_art = youtube_url::resize_youtube_art_url(
+        track.album_art.as_deref().unwrap_or(""),
+        config.discord_album_art_size,
+    );
+
+    if clean_title.is_empty() && clean_artist.is_empty() {
+        return None;
+    }
+
+    // Anchor to when the track actually started so the bar stays right after
+    // seeks and late first scrapes.
+    let position = track.position_sec.unwrap_or(0);
+    let start_time = now.saturating_sub(position);
+
+    // Discord counts down to an end and up from a start alone. A duration
+    // shorter than the position is a misread, and would put the end in the
+    // past; show elapsed time instead.
+    let end_time = track
+        .duration_sec
+        .filter(|&d| config.discord_show_time_remaining && d >= position)
+        .map(|d| start_time + d);
+    let timestamp_json = if !track.is_playing {
+        serde_json::Value::Null
+    } else if let Some(end) = end_time {
+        serde_json::json!({ "start": start_time, "end": end })
+    } else {
+        serde_json::json!({ "start": start_time })
+    };
+
+    let activity_type = if (config.watching_for_videos || config.mode == Mode::Video) && track.is_video {
+        DiscordActivityType::Watching
+    } else {
+        config.discord_activity_type
+    };
+
+    let (details_template, state_template) = if config.compact_presence {
+        (COMPACT_DETAILS_TEMPLATE, COMPACT_STATE_TEMPLATE)
+    } else {
+        (config.details_template.as_str(), config.state_template.as_str())
+    };
+    let details = match &config.explicit_suffix {
+        // A custom status is the state line alone.
+        _ if activity_type == DiscordActivityType::Custom => None,
+        Some(suffix) if track.explicit => format_presence_text(&format!("{}{}", details_template, suffix), track),
+        _ => format_presence_text(details_template, track),
+    };
+    // Checked when the config is loaded.
+    let url = match activity_type {
+        DiscordActivityType::Streaming => config.streaming_url.clone(),
+        _ => None,
+    };
+    let state = format_presence_text(state_template, track);
+    let large_text = format_presence_text(&config.discord_large_text_format, track);
+
+    // Discord renders this as "(3 of 12)" after the state line.
+    let party_json = match (track.queue_index, track.queue_total) {
+        (Some(index), Some(total)) if config.show_queue && index <= total => {
+            serde_json::json!({ "id": "ytune-queue", "size": [index, total] })
+        }
+        _ => serde_json::Value::Null,
+    };
+
+    let mut activity = serde_json::json!({
+        "timestamps": timestamp_json,
+        "assets": {
+            "large_image": if clean_album_art.is_empty() { serde_json::Value::Null } else { clean_album_art.into() },
+            "large_text": large_text,
+            "small_image": "ytune",
+            "small_text": "ytune"
+        },
+        "details": details,
+        "state": state,
+        "party": party_json,
+        "type": u32::from(activity_type),
+        "url": url,
+        "name": "ytune",
+        "buttons": [
+            {
+                "label": "ytune",
+                "url": "https://github.com/yctwhy/ytune"
+            }
+        ]
+    });
+    if let Some(activity) = activity.as_object_mut() {
+        // Not even a null: the activity then shows no elapsed or remaining time.
+        if !config.show_timestamps {
+            activity.remove("timestamps");
+        }
+        // Discord takes an activity without a state line, e.g. a compact one
+        // for a track without an album.
+        if activity["state"].is_null() {
+            activity.remove("state");
+        }
+    }
+    Some(activity)
+}
+
+/// Renders a presence template and fits the result into Discord's 2–128
+/// character limit for presence text fields.
+fn format_presence_text(template: &str, track: &LastTrackInfo) -> Option<String> {
+    let text = template::render_if_filled(template, track)?;
+    let text = trim_dangling_separator(&text);
+
+    let length = text.chars().count();
+    if length < DISCORD_TEXT_MIN_CHARS {
+        None
+    } else if length > DISCORD_TEXT_MAX_CHARS {
+        let truncated: String = text.chars().take(DISCORD_TEXT_MAX_CHARS - 1).collect();
+        Some(format!("{}…", truncated.trim_end()))
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// Drops a " - " (or " — ", " • ") left at either end when the token on
+/// that side of it was empty. A dash that is part of a title has no space
+/// between it and the title, and stays.
+fn trim_dangling_separator(text: &str) -> &str {
+    let mut text = text.trim();
+    for separator in ['-', '—', '•'] {
+        if let Some(rest) = text.strip_prefix(separator).filter(|rest| rest.starts_with(char::is_whitespace)) {
+            text = rest.trim_start();
+        }
+        if let Some(rest) = text.strip_suffix(separator).filter(|rest| rest.ends_with(char::is_whitespace)) {
+            text = rest.trim_end();
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn text() -> impl Strategy<Value = Option<String>> {
+        proptest::option::of("\\PC{0,200}")
+    }
+
+    /// An artist that leaves something after "by " once trimmed.
+    fn artist() -> impl Strategy<Value = Option<String>> {
+        proptest::option::of("[a-zA-Z0-9]\\PC{0,200}")
+    }
+
+    fn album_art() -> impl Strategy<Value = Option<String>> {
+        prop_oneof![
+            Just(None),
+            Just(Some(String::new())),
+            Just(Some("https://lh3.googleusercontent.com/abc=w60-h60-l90-rj".to_string())),
+        ]
+    }
+
+    prop_compose! {
+        fn track()(
+            (title, artist, album, album_art) in (text(), artist(), text(), album_art()),
+            duration_sec in proptest::option::of(0..20_000u64),
+            position_sec in proptest::option::of(0..20_000u64),
+            (is_playing, is_video, explicit) in any::<(bool, bool, bool)>(),
+            queue_index in proptest::option::of(0..50u32),
+            queue_total in proptest::option::of(0..50u32),
+        ) -> LastTrackInfo {
+            LastTrackInfo {
+                title,
+                artist,
+                album,
+                album_art,
+                duration_sec,
+                position_sec,
+                is_playing,
+                is_video,
+                explicit,
+                queue_index,
+                queue_total,
+                ..LastTrackInfo::default()
+            }
+        }
+    }
+
+    prop_compose! {
+        fn presence_config()(
+            (compact_presence, show_timestamps, discord_show_time_remaining, show_queue) in any::<(bool, bool, bool, bool)>(),
+            explicit_suffix in proptest::option::of(" \\PC{0,10}"),
+        ) -> config::Config {
+            config::Config {
+                compact_presence,
+                show_timestamps,
+                discord_show_time_remaining,
+                show_queue,
+                explicit_suffix,
+                ..config::Config::default()
+            }
+        }
+    }
+
+    fn char_count(value: &serde_json::Value) -> usize {
+        value.as_str().expect("presence text is a string").chars().count()
+    }
+
+    proptest! {
+        #[test]
+        fn payload_round_trips_as_json(track in track(), config in presence_config()) {
+            if let Some(activity) = build_activity(&track, &config, NOW) {
+                let json = serde_json::to_string(&activity).unwrap();
+                prop_assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), activity);
+            }
+        }
+
+        #[test]
+        fn text_fields_fit_discord_limits(track in track(), config in presence_config()) {
+            let Some(activity) = build_activity(&track, &config, NOW) else {
+                return Ok(());
+            };
+            for text in [&activity["details"], &activity["state"], &activity["assets"]["large_text"]] {
+                if !text.is_null() {
+                    prop_assert!((DISCORD_TEXT_MIN_CHARS..=DISCORD_TEXT_MAX_CHARS).contains(&char_count(text)));
+                }
+            }
+            // Left out rather than sent as null.
+            prop_assert!(activity.get("state").is_none_or(|state| state.is_string()));
+        }
+
+        #[test]
+        fn state_names_the_artist(track in track(), config in presence_config()) {
+            prop_assume!(!config.compact_presence);
+            let Some(activity) = build_activity(&track, &config, NOW) else {
+                return Ok(());
+            };
+            if track.artist.is_some() {
+                let state = activity["state"].as_str().unwrap();
+                prop_assert!(state.starts_with("by "), "state {:?}", state);
+            } else {
+                prop_assert!(activity.get("state").is_none());
+            }
+        }
+
+        #[test]
+        fn no_large_image_without_art(track in track(), config in presence_config()) {
+            let Some(activity) = build_activity(&track, &config, NOW) else {
+                return Ok(());
+            };
+            let has_art = track.album_art.as_deref().is_some_and(|art| !art.is_empty());
+            prop_assert_eq!(activity["assets"]["large_image"].is_null(), !has_art);
+        }
+
+        #[test]
+        fn timestamps_never_end_before_they_start(track in track(), config in presence_config()) {
+            let Some(activity) = build_activity(&track, &config, NOW) else {
+                return Ok(());
+            };
+            if let Some(end) = activity["timestamps"]["end"].as_u64() {
+                prop_assert!(activity["timestamps"]["start"].as_u64().unwrap() <= end);
+            }
+        }
+    }
+
+    #[test]
+    fn no_payload_without_title_or_artist() {
+        let track = LastTrackInfo {
+            album: Some("Album".to_string()),
+            ..LastTrackInfo::default()
+        };
+        assert_eq!(build_activity(&track, &config::Config::default(), NOW), None);
+    }
+
+    #[test]
+    fn dangling_separators_are_trimmed_but_title_dashes_kept() {
+        assert_eq!(trim_dangling_separator(" - Artist"), "Artist");
+        assert_eq!(trim_dangling_separator("Title — "), "Title");
+        assert_eq!(trim_dangling_separator("-Intro-"), "-Intro-");
+        assert_eq!(trim_dangling_separator("Title - Artist"), "Title - Artist");
+    }
+}
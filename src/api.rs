@@ -0,0 +1,1001 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tiny_http::{Header, Method, Request, Response, ResponseBox, Server};
+use wry::webview::Url;
+
+use crate::{
+    api::{
+        long_poll::{TrackWatch, Waiter},
+        types::{NowPlaying, VersionInfo},
+    },
+    art_cache::ArtCache,
+    config::{self, ApiConfig},
+    control::{Adjustment, PlayerCommand},
+    event_stream::{self, EventStream},
+    metrics::Metrics,
+    share_card,
+    youtube_url::{self, PlayTarget},
+    status::{AppStatus, DiscordStatus},
+    template, LastTrackInfo, UserEvent,
+};
+
+pub mod long_poll;
+pub mod types;
+
+const ROUTES: [&str; 27] = [
+    "/art.jpg",
+    "/refresh",
+    "/remote",
+    "/api/events",
+    "/api/health",
+    "/api/version",
+    "/api/now-playing",
+    "/api/album-art",
+    "/metrics",
+    "/api/play",
+    "/api/pause",
+    "/api/next",
+    "/api/previous",
+    "/api/open",
+    "/api/play-url",
+    "/api/seek",
+    "/api/volume",
+    "/api/copy-track",
+    "/api/copy-link",
+    "/api/share-card",
+    "/actions/play",
+    "/actions/pause",
+    "/actions/playpause",
+    "/actions/next",
+    "/actions/previous",
+    "/actions/like",
+    NOW_PLAYING_TEXT_PATH,
+];
+/// GET-only shortcuts over the JSON API for Stream Deck and the like, which
+/// often can't send a body or headers: the token goes in `?token=` and the
+/// answer is plain text.
+const ACTIONS: [(&str, PlayerCommand); 6] = [
+    ("/actions/play", PlayerCommand::Play),
+    ("/actions/pause", PlayerCommand::Pause),
+    ("/actions/playpause", PlayerCommand::PlayPause),
+    ("/actions/next", PlayerCommand::Next),
+    ("/actions/previous", PlayerCommand::Previous),
+    ("/actions/like", PlayerCommand::Like),
+];
+const NOW_PLAYING_TEXT_PATH: &str = "/actions/nowplaying.txt";
+/// Requests one address may make under `/actions/` per second. Buttons are
+/// pressed by hand and text sources poll about once a second, so more than
+/// this is a runaway client the webview shouldn't have to keep up with.
+const ACTION_RATE_LIMIT: u32 = 5;
+const ACTION_RATE_WINDOW: Duration = Duration::from_secs(1);
+/// A URL to open is all `/api/open` takes; anything longer isn't one.
+const MAX_OPEN_BODY_BYTES: u64 = 4096;
+/// Longest `?wait=` a `/api/play-url` caller may ask for, in seconds.
+const MAX_PLAY_WAIT_SECS: u64 = 30;
+/// How often a waiting `/api/play-url` looks at what the scraper reported.
+const PLAY_WAIT_POLL: Duration = Duration::from_millis(250);
+/// Longest `?wait=` a `/api/now-playing` caller may ask for, in seconds.
+const MAX_NOW_PLAYING_WAIT_SECS: u64 = 60;
+/// Seek and volume bodies are a single small JSON object.
+const MAX_ADJUST_BODY_BYTES: u64 = 1024;
+const TOKEN_BYTES: usize = 32;
+/// How long browsers may reuse a preflight answer, in seconds.
+const PREFLIGHT_MAX_AGE: &str = "600";
+const REMOTE_HTML: &str = include_str!("assets/remote.html");
+
+/// Shared state the request handlers read from.
+pub struct ApiContext {
+    pub art_cache: Option<Arc<ArtCache>>,
+    pub last_track: Arc<Mutex<LastTrackInfo>>,
+    pub discord_status: Arc<Mutex<DiscordStatus>>,
+    /// For the scrape metrics `/api/health` reports.
+    pub app_status: Arc<Mutex<AppStatus>>,
+    pub event_stream: Arc<EventStream>,
+    /// The only host `/api/open` loads pages from, that of the current mode.
+    pub site_host: &'static str,
+    /// Forwards commands to the webview on the event loop; false once it
+    /// has stopped.
+    pub send_event: Box<dyn Fn(UserEvent) -> bool + Send>,
+    pub token: String,
+    pub require_token_for_reads: bool,
+    pub allowed_origins: Vec<String>,
+    /// The `.local` name mDNS advertises the API under, if it does.
+    pub mdns_host: Option<String>,
+    /// `/actions/nowplaying.txt` renders the file output's template and idle text.
+    pub now_playing_template: String,
+    pub now_playing_idle_text: String,
+    pub action_limiter: RateLimiter,
+    pub metrics: Arc<Metrics>,
+    /// Wakes `/api/now-playing` requests waiting for a change.
+    pub track_watch: Arc<TrackWatch>,
+}
+
+/// Counts requests per address over fixed windows of `ACTION_RATE_WINDOW`.
+#[derive(Default)]
+pub struct RateLimiter {
+    windows: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    fn allow(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        windows.retain(|_, (started, _)| now.duration_since(*started) < ACTION_RATE_WINDOW);
+        let (_, count) = windows.entry(ip).or_insert((now, 0));
+        *count += 1;
+        *count <= ACTION_RATE_LIMIT
+    }
+}
+
+/// The configured API token, or a new random one stored in the config so
+/// clients can be set up with it once.
+pub fn load_or_create_token(config: &ApiConfig) -> io::Result<String> {
+    if let Some(token) = config.token.as_deref().filter(|token| !token.is_empty()) {
+        return Ok(token.to_string());
+    }
+
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::getrandom(&mut bytes).map_err(|e| io::Error::other(e.to_string()))?;
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    config::store_secret("api.token", &token)?;
+    log::info!("Generated a local API token; it is stored as api.token in config.toml");
+    Ok(token)
+}
+
+/// Starts the HTTP server on a background thread and returns the address it
+/// listens on. Anything but a loopback `bind` exposes it to the network.
+pub fn start(bind: IpAddr, port: u16, context: ApiContext) -> io::Result<SocketAddr> {
+    let server = Server::http((bind, port)).map_err(|e| io::Error::new(io::ErrorKind::AddrInUse, e))?;
+    // Port 0 picks a free one.
+    let address = server.server_addr().to_ip().unwrap_or(SocketAddr::from((bind, port)));
+    log::info!("Local API listening on http://{}", address);
+
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            handle(request, &context);
+        }
+    });
+    Ok(address)
+}
+
+/// Where a phone can open the remote control page. For a wildcard bind that
+/// is the address of the interface with the default route.
+pub fn remote_url(address: SocketAddr) -> String {
+    let host = if address.ip().is_unspecified() {
+        lan_address().unwrap_or(address.ip())
+    } else {
+        address.ip()
+    };
+    format!("http://{}/remote", SocketAddr::from((host, address.port())))
+}
+
+/// The address other machines reach this one at. Connecting a UDP socket
+/// only picks a route; nothing is sent.
+pub fn lan_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).ok()?;
+    socket.local_addr().ok().map(|address| address.ip())
+}
+
+fn handle(mut request: Request, context: &ApiContext) {
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    let origin = header_value(&request, "Origin");
+    let allowed_origin = origin
+        .as_deref()
+        .filter(|origin| {
+            context.allowed_origins.iter().any(|allowed| allowed == origin)
+                || is_same_origin(&request, origin, context.mdns_host.as_deref())
+        })
+        .map(str::to_string);
+    let response = if origin.is_some() && allowed_origin.is_none() {
+        // Browsers would hide the answer from other pages, but simple POSTs
+        // still go through, so those pages get nothing at all.
+        Response::empty(403).boxed()
+    } else if path.starts_with("/actions/")
+        && request.remote_addr().is_some_and(|address| !context.action_limiter.allow(address.ip()))
+    {
+        Response::from_string("Too many requests")
+            .with_status_code(429)
+            .with_header(header("Retry-After", "1"))
+            .boxed()
+    } else if let Err(response) = authorize(&request, &path, &context.token, context.require_token_for_reads) {
+        response
+    } else if *request.method() == Method::Get && path == "/api/events" {
+        match upgrade_to_event_stream(request, context) {
+            Ok(()) => return,
+            Err(rejected) => {
+                request = *rejected;
+                Response::from_string("Expected a WebSocket upgrade")
+                    .with_status_code(426)
+                    .with_header(header("Upgrade", "websocket"))
+                    .boxed()
+            }
+        }
+    } else if *request.method() == Method::Get && path == "/api/now-playing" {
+        match poll_now_playing(&request, context) {
+            NowPlayingPoll::Answered(response) => response,
+            NowPlayingPoll::Waiting { etag, timeout, waiter } => {
+                // Answered from its own thread so other requests aren't held up.
+                let last_track = Arc::clone(&context.last_track);
+                thread::spawn(move || {
+                    let response = match waiter.wait(&last_track, &etag, timeout) {
+                        Some(now_playing) => now_playing_response(&now_playing),
+                        None => not_modified(&etag),
+                    };
+                    drop(waiter);
+                    respond(request, response, allowed_origin, &path);
+                });
+                return;
+            }
+        }
+    } else if *request.method() == Method::Post && path == "/api/play-url" {
+        match play_url(&mut request, context) {
+            PlayUrl::Answered(response) => response,
+            PlayUrl::Waiting { video_id, timeout } => {
+                // Answered from its own thread so other requests aren't held up.
+                let last_track = Arc::clone(&context.last_track);
+                thread::spawn(move || {
+                    let response = wait_for_video(&last_track, &video_id, timeout);
+                    respond(request, response, allowed_origin, &path);
+                });
+                return;
+            }
+        }
+    } else {
+        route(&mut request, &path, context)
+    };
+
+    respond(request, response, allowed_origin, &path);
+}
+
+fn respond(request: Request, response: ResponseBox, allowed_origin: Option<String>, path: &str) {
+    let response = match allowed_origin {
+        Some(origin) => response
+            .with_header(header("Access-Control-Allow-Origin", &origin))
+            .with_header(header("Vary", "Origin")),
+        None => response,
+    };
+    if let Err(e) = request.respond(response) {
+        log::debug!("Failed to answer API request for {}: {}", path, e);
+    }
+}
+
+fn route(request: &mut Request, path: &str, context: &ApiContext) -> ResponseBox {
+    let method = request.method().clone();
+    match (&method, path) {
+        (Method::Get, "/art.jpg") => serve_art(context),
+        (Method::Get, "/api/album-art") => {
+            art_response(context).unwrap_or_else(|| json_error(404, "no album art available"))
+        }
+        (Method::Get, "/remote") => Response::from_string(REMOTE_HTML)
+            .with_header(header("Content-Type", "text/html; charset=utf-8"))
+            .boxed(),
+        (Method::Get, "/api/health") => serve_health(context),
+        (Method::Get, "/api/version") => serve_version(context),
+        (Method::Get, "/metrics") => serve_metrics(context),
+        (Method::Post, "/refresh") => send_command(context, PlayerCommand::Refresh),
+        (Method::Post, "/api/play") => send_command(context, PlayerCommand::Play),
+        (Method::Post, "/api/pause") => send_command(context, PlayerCommand::Pause),
+        (Method::Post, "/api/next") => send_command(context, PlayerCommand::Next),
+        (Method::Post, "/api/previous") => send_command(context, PlayerCommand::Previous),
+        (Method::Post, "/api/open") => open_url(request, context),
+        (Method::Post, "/api/seek") => adjust(request, context, "position", PlayerCommand::Seek),
+        (Method::Get, "/api/volume") => serve_volume(context),
+        (Method::Post, "/api/volume") => adjust(request, context, "level", PlayerCommand::Volume),
+        // The clipboard belongs to the event loop thread.
+        (Method::Post, "/api/copy-track") => send_event(context, UserEvent::CopyTrack),
+        (Method::Post, "/api/copy-link") => send_event(context, UserEvent::CopyLink),
+        (Method::Post, "/api/share-card") => share_card(request, context),
+        (Method::Get, NOW_PLAYING_TEXT_PATH) => serve_now_playing_text(context),
+        (Method::Get, path) if ACTIONS.iter().any(|(action, _)| *action == path) => {
+            let (_, command) = ACTIONS.iter().find(|(action, _)| *action == path).expect("action exists");
+            run_action(context, *command)
+        }
+        (Method::Options, path) if ROUTES.contains(&path) => Response::empty(204)
+            .with_header(header("Access-Control-Allow-Methods", "GET, POST"))
+            .with_header(header("Access-Control-Allow-Headers", "Authorization, Content-Type"))
+            .with_header(header("Access-Control-Max-Age", PREFLIGHT_MAX_AGE))
+            .boxed(),
+        (_, path) if ROUTES.contains(&path) => Response::empty(405).boxed(),
+        _ => Response::empty(404).boxed(),
+    }
+}
+
+fn header_value(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str().to_string())
+}
+
+/// Whether `origin` is the API itself, as for the remote control page. Only
+/// for hosts given as an address, `localhost` or the advertised `mdns_host`,
+/// so a page that rebinds its own domain to this machine still counts as
+/// foreign.
+fn is_same_origin(request: &Request, origin: &str, mdns_host: Option<&str>) -> bool {
+    let Some(host) = header_value(request, "Host") else {
+        return false;
+    };
+    let hostname = match host.rsplit_once(':') {
+        Some((hostname, port)) if port.bytes().all(|b| b.is_ascii_digit()) => hostname,
+        _ => host.as_str(),
+    };
+    let is_known_host = hostname == "localhost"
+        || hostname.parse::<IpAddr>().is_ok()
+        || hostname
+            .strip_prefix('[')
+            .and_then(|hostname| hostname.strip_suffix(']'))
+            .is_some_and(|hostname| hostname.parse::<IpAddr>().is_ok())
+        || mdns_host.is_some_and(|mdns_host| hostname.eq_ignore_ascii_case(mdns_host));
+    is_known_host && origin == format!("http://{}", host)
+}
+
+/// Lets `request` through to routing when it needs no token or has the
+/// right one, and gives the 401 to answer otherwise.
+fn authorize(request: &Request, path: &str, token: &str, require_token_for_reads: bool) -> Result<(), ResponseBox> {
+    let needs_token = match request.method() {
+        // The page holds no data; it asks for the token itself.
+        Method::Get if path == "/remote" => false,
+        Method::Get if ACTIONS.iter().any(|(action, _)| *action == path) => true,
+        Method::Get => require_token_for_reads,
+        Method::Options => false,
+        _ => true,
+    };
+    if needs_token && !is_authorized(request, path, token) {
+        return Err(Response::empty(401)
+            .with_header(header("WWW-Authenticate", "Bearer"))
+            .boxed());
+    }
+    Ok(())
+}
+
+/// Checks `Authorization: Bearer <token>` without letting the time taken
+/// reveal how much of the token matched. Browsers can't set headers on a
+/// WebSocket, so the event stream also takes `?token=`.
+fn is_authorized(request: &Request, path: &str, token: &str) -> bool {
+    let given = match header_value(request, "Authorization") {
+        Some(value) => value.strip_prefix("Bearer ").map(|given| given.trim().to_string()),
+        None if path == "/api/events" || path.starts_with("/actions/") => query_value(request, "token"),
+        None => None,
+    };
+    let Some(given) = given else {
+        return false;
+    };
+    let (given, expected) = (given.as_bytes(), token.as_bytes());
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn query_value(request: &Request, key: &str) -> Option<String> {
+    let (_, query) = request.url().split_once('?')?;
+    query.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        (name == key).then(|| value.to_string())
+    })
+}
+
+/// Answers the WebSocket handshake and hands the connection to the event
+/// stream. Gives the request back when it isn't a WebSocket upgrade.
+fn upgrade_to_event_stream(request: Request, context: &ApiContext) -> Result<(), Box<Request>> {
+    let is_upgrade = header_value(&request, "Upgrade")
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    let Some(key) = header_value(&request, "Sec-WebSocket-Key").filter(|_| is_upgrade) else {
+        return Err(Box::new(request));
+    };
+
+    let response = Response::empty(101)
+        .with_header(header("Upgrade", "websocket"))
+        .with_header(header("Connection", "Upgrade"))
+        .with_header(header("Sec-WebSocket-Accept", &event_stream::accept_key(&key)));
+    let stream = request.upgrade("websocket", response);
+    context.event_stream.add_client(stream, now_playing(context));
+    Ok(())
+}
+
+fn serve_art(context: &ApiContext) -> ResponseBox {
+    art_response(context).unwrap_or_else(|| Response::empty(404).boxed())
+}
+
+/// The cached art of the current track, or None while there is none.
+fn art_response(context: &ApiContext) -> Option<ResponseBox> {
+    let art = context.art_cache.as_ref()?.current()?;
+    let response = Response::from_data(art.bytes.as_slice())
+        .with_header(header("Content-Type", &art.content_type))
+        // The URL stays the same across tracks, so it must never be cached.
+        .with_header(header("Cache-Control", "no-store"))
+        .boxed();
+    Some(response)
+}
+
+fn serve_health(context: &ApiContext) -> ResponseBox {
+    let discord = context.discord_status.lock().unwrap().clone();
+    let (metrics, player_error) = {
+        let status = context.app_status.lock().unwrap();
+        (status.scrape_metrics.clone(), status.player_error.clone())
+    };
+    let body = serde_json::json!({
+        "ok": true,
+        "status": if player_error.is_some() { "degraded" } else { "ok" },
+        "player_error": player_error,
+        "discord": discord,
+        "scrape": metrics,
+    });
+    Response::from_string(body.to_string())
+        .with_header(header("Content-Type", "application/json"))
+        .with_header(header("Cache-Control", "no-store"))
+        .boxed()
+}
+
+fn serve_version(context: &ApiContext) -> ResponseBox {
+    let mut features = vec!["now-playing", "events", "commands", "open", "play-url", "seek", "volume", "remote", "metrics"];
+    if context.art_cache.is_some() {
+        features.push("art");
+    }
+    let body = serde_json::to_string(&VersionInfo::new(features)).unwrap_or_default();
+    Response::from_string(body)
+        .with_header(header("Content-Type", "application/json"))
+        .with_header(header("Cache-Control", "no-store"))
+        .boxed()
+}
+
+enum NowPlayingPoll {
+    Answered(ResponseBox),
+    /// The client has the current state; the answer waits for a change.
+    Waiting {
+        etag: String,
+        timeout: Duration,
+        waiter: Waiter,
+    },
+}
+
+/// Answers with the current track, or with `?etag=` of the state the client
+/// has, once that changes: right away when it already has, otherwise after
+/// up to `?wait=` seconds, with 304 when nothing changed by then.
+fn poll_now_playing(request: &Request, context: &ApiContext) -> NowPlayingPoll {
+    let now_playing = now_playing(context);
+    let Some(etag) = query_value(request, "etag") else {
+        return NowPlayingPoll::Answered(now_playing_response(&now_playing));
+    };
+    let etag = etag.trim_matches('"').to_string();
+    if etag != long_poll::etag(&now_playing) {
+        return NowPlayingPoll::Answered(now_playing_response(&now_playing));
+    }
+
+    let timeout = match query_value(request, "wait").map(|wait| wait.parse::<u64>()) {
+        None => return NowPlayingPoll::Answered(not_modified(&etag)),
+        Some(Ok(seconds)) => Duration::from_secs(seconds.min(MAX_NOW_PLAYING_WAIT_SECS)),
+        Some(Err(_)) => return NowPlayingPoll::Answered(json_error(400, "wait must be a whole number of seconds")),
+    };
+    match context.track_watch.join() {
+        Some(waiter) => NowPlayingPoll::Waiting { etag, timeout, waiter },
+        None => NowPlayingPoll::Answered(
+            json_error(503, "Too many requests are waiting for a change").with_header(header("Retry-After", "5")),
+        ),
+    }
+}
+
+fn now_playing_response(now_playing: &NowPlaying) -> ResponseBox {
+    Response::from_string(serde_json::to_string(now_playing).unwrap_or_default())
+        .with_header(header("Content-Type", "application/json"))
+        .with_header(header("Cache-Control", "no-store"))
+        .with_header(header("ETag", &format!("\"{}\"", long_poll::etag(now_playing))))
+        .boxed()
+}
+
+fn not_modified(etag: &str) -> ResponseBox {
+    Response::empty(304)
+        .with_header(header("Cache-Control", "no-store"))
+        .with_header(header("ETag", &format!("\"{}\"", etag)))
+        .boxed()
+}
+
+fn serve_metrics(context: &ApiContext) -> ResponseBox {
+    let body = context.metrics.render(&context.last_track.lock().unwrap());
+    Response::from_string(body)
+        .with_header(header("Content-Type", "text/plain; version=0.0.4; charset=utf-8"))
+        .with_header(header("Cache-Control", "no-store"))
+        .boxed()
+}
+
+fn now_playing(context: &ApiContext) -> NowPlaying {
+    NowPlaying::from(context.last_track.lock().unwrap().clone())
+}
+
+fn send_command(context: &ApiContext, command: PlayerCommand) -> ResponseBox {
+    send_event(context, UserEvent::PlayerCommand(command))
+}
+
+/// Opens a YouTube Music URL, given as the plain-text body, in the player.
+fn open_url(request: &mut Request, context: &ApiContext) -> ResponseBox {
+    let mut body = String::new();
+    if request
+        .as_reader()
+        .take(MAX_OPEN_BODY_BYTES)
+        .read_to_string(&mut body)
+        .is_err()
+    {
+        return Response::from_string("Body must be a UTF-8 URL").with_status_code(400).boxed();
+    }
+    let url = match Url::parse(body.trim()) {
+        Ok(url) => url,
+        Err(e) => {
+            return Response::from_string(format!("Invalid URL: {}", e)).with_status_code(400).boxed();
+        }
+    };
+    if url.scheme() != "https" || url.host_str() != Some(context.site_host) {
+        let message = format!("Only https://{} URLs can be opened", context.site_host);
+        return Response::from_string(message).with_status_code(422).boxed();
+    }
+    send_event(context, UserEvent::OpenUrl(url))
+}
+
+enum PlayUrl {
+    Answered(ResponseBox),
+    /// The page is loading; the answer waits until the scraper sees the video.
+    Waiting { video_id: String, timeout: Duration },
+}
+
+/// Takes a video ID or a watch, playlist or album URL as the body and loads
+/// the page that plays it. With `?wait=<seconds>` and a video to check for,
+/// answers only once that video plays, or with 504 when it doesn't in time.
+fn play_url(request: &mut Request, context: &ApiContext) -> PlayUrl {
+    let mut body = String::new();
+    if request
+        .as_reader()
+        .take(MAX_OPEN_BODY_BYTES)
+        .read_to_string(&mut body)
+        .is_err()
+    {
+        return PlayUrl::Answered(json_error(400, "Body must be a UTF-8 URL or video ID"));
+    }
+    let wait = match query_value(request, "wait").map(|wait| wait.parse::<u64>()) {
+        None => None,
+        Some(Ok(seconds)) => Some(Duration::from_secs(seconds.min(MAX_PLAY_WAIT_SECS))),
+        Some(Err(_)) => return PlayUrl::Answered(json_error(400, "wait must be a whole number of seconds")),
+    };
+    // Checked before anything is loaded.
+    let PlayTarget { url, video_id } = match youtube_url::play_target(&body, context.site_host) {
+        Ok(target) => target,
+        Err(e) => return PlayUrl::Answered(json_error(400, &e)),
+    };
+
+    let answer = serde_json::json!({ "url": url.as_str(), "video_id": video_id });
+    if !(context.send_event)(UserEvent::OpenUrl(url)) {
+        return PlayUrl::Answered(json_error(503, "ytune is shutting down"));
+    }
+    match (wait, video_id) {
+        (Some(timeout), Some(video_id)) => PlayUrl::Waiting { video_id, timeout },
+        _ => PlayUrl::Answered(
+            Response::from_string(answer.to_string())
+                .with_status_code(202)
+                .with_header(header("Content-Type", "application/json"))
+                .boxed(),
+        ),
+    }
+}
+
+fn wait_for_video(last_track: &Mutex<LastTrackInfo>, video_id: &str, timeout: Duration) -> ResponseBox {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let playing = {
+            let track = last_track.lock().unwrap();
+            track.is_playing && track.video_id.as_deref() == Some(video_id)
+        };
+        if playing {
+            return Response::from_string(serde_json::json!({ "video_id": video_id, "playing": true }).to_string())
+                .with_header(header("Content-Type", "application/json"))
+                .boxed();
+        }
+        if Instant::now() >= deadline {
+            return json_error(504, &format!("{} did not start playing in time", video_id));
+        }
+        thread::sleep(PLAY_WAIT_POLL);
+    }
+}
+
+fn serve_volume(context: &ApiContext) -> ResponseBox {
+    if !has_track(context) {
+        return json_error(409, "No track is loaded");
+    }
+    match context.last_track.lock().unwrap().volume {
+        Some(level) => Response::from_string(serde_json::json!({ "level": level }).to_string())
+            .with_header(header("Content-Type", "application/json"))
+            .with_header(header("Cache-Control", "no-store"))
+            .boxed(),
+        None => json_error(409, "The player hasn't reported its volume yet"),
+    }
+}
+
+/// Seek and volume take `{"<absolute>": n}` for a new value or `{"delta": n}`
+/// for a change, and need a track to act on.
+fn adjust(
+    request: &mut Request,
+    context: &ApiContext,
+    absolute: &str,
+    command: fn(Adjustment) -> PlayerCommand,
+) -> ResponseBox {
+    let mut body = String::new();
+    if request
+        .as_reader()
+        .take(MAX_ADJUST_BODY_BYTES)
+        .read_to_string(&mut body)
+        .is_err()
+    {
+        return json_error(400, "Body must be UTF-8 JSON");
+    }
+    let body: serde_json::Value = match serde_json::from_str(&body) {
+        Ok(body) => body,
+        Err(e) => return json_error(400, &format!("Invalid JSON: {}", e)),
+    };
+    let number = |key: &str| body.get(key).and_then(serde_json::Value::as_f64).map(|n| n.round() as i64);
+    let adjustment = match (number(absolute), number("delta")) {
+        (Some(value), None) => Adjustment::To(value),
+        (None, Some(delta)) => Adjustment::By(delta),
+        _ => return json_error(400, &format!("Expected a number in either \"{}\" or \"delta\"", absolute)),
+    };
+
+    if !has_track(context) {
+        return json_error(409, "No track is loaded");
+    }
+    send_command(context, command(adjustment))
+}
+
+/// Saves a share card in the pictures folder, under the `"name"` in the
+/// JSON body when there is one.
+fn share_card(request: &mut Request, context: &ApiContext) -> ResponseBox {
+    let mut body = String::new();
+    if request
+        .as_reader()
+        .take(MAX_ADJUST_BODY_BYTES)
+        .read_to_string(&mut body)
+        .is_err()
+    {
+        return json_error(400, "Body must be UTF-8 JSON");
+    }
+    let name = if body.trim().is_empty() {
+        None
+    } else {
+        let body: serde_json::Value = match serde_json::from_str(&body) {
+            Ok(body) => body,
+            Err(e) => return json_error(400, &format!("Invalid JSON: {}", e)),
+        };
+        body.get("name").and_then(serde_json::Value::as_str).map(str::to_string)
+    };
+    if name.as_deref().is_some_and(|name| !share_card::is_valid_file_name(name)) {
+        return json_error(400, "\"name\" must be a file name ending in .png, without a folder");
+    }
+    send_event(context, UserEvent::ShareCard(name))
+}
+
+fn has_track(context: &ApiContext) -> bool {
+    let track = context.last_track.lock().unwrap();
+    track.title.is_some() || track.artist.is_some()
+}
+
+fn json_error(status: u16, message: &str) -> ResponseBox {
+    Response::from_string(serde_json::json!({ "error": message }).to_string())
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+        .boxed()
+}
+
+fn run_action(context: &ApiContext, command: PlayerCommand) -> ResponseBox {
+    let (status, body) = if (context.send_event)(UserEvent::PlayerCommand(command)) {
+        (200, "OK")
+    } else {
+        (503, "ytune is shutting down")
+    };
+    plain_text(status, body.to_string())
+}
+
+/// One line for a polled text source: the current track while playing, the
+/// idle text otherwise, as the file output writes it.
+fn serve_now_playing_text(context: &ApiContext) -> ResponseBox {
+    let track = context.last_track.lock().unwrap().clone();
+    let text = if track.title.is_some() && track.is_playing {
+        template::render(&context.now_playing_template, &track)
+    } else {
+        context.now_playing_idle_text.clone()
+    };
+    plain_text(200, text)
+}
+
+fn plain_text(status: u16, body: String) -> ResponseBox {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+        .with_header(header("Cache-Control", "no-store"))
+        .boxed()
+}
+
+fn send_event(context: &ApiContext, event: UserEvent) -> ResponseBox {
+    let status = if (context.send_event)(event) {
+        // Queued for the webview; it runs once the event loop gets to it.
+        202
+    } else {
+        // The event loop is gone, ytune is shutting down.
+        503
+    };
+    Response::empty(status).boxed()
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("header is valid ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        net::TcpStream,
+        sync::mpsc::{self, Receiver},
+    };
+
+    use tiny_http::TestRequest;
+
+    use super::*;
+    use crate::control::PlayerCommand;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    fn request(method: Method, path: &str, authorization: Option<&str>) -> Request {
+        let request = TestRequest::new().with_method(method).with_path(path);
+        match authorization {
+            Some(value) => request.with_header(header("Authorization", value)),
+            None => request,
+        }
+        .into()
+    }
+
+    fn authorize_request(request: &Request, require_token_for_reads: bool) -> Result<(), ResponseBox> {
+        let path = request.url().split('?').next().unwrap_or_default().to_string();
+        authorize(request, &path, TOKEN, require_token_for_reads)
+    }
+
+    fn assert_unauthorized(result: Result<(), ResponseBox>) {
+        let response = result.expect_err("request should be refused");
+        assert_eq!(response.status_code(), 401);
+        assert!(response
+            .headers()
+            .iter()
+            .any(|header| header.field.equiv("WWW-Authenticate") && header.value == "Bearer"));
+    }
+
+    #[test]
+    fn mutating_request_without_credentials_is_unauthorized() {
+        assert_unauthorized(authorize_request(&request(Method::Post, "/api/next", None), false));
+    }
+
+    #[test]
+    fn mutating_request_with_wrong_token_is_unauthorized() {
+        let wrong = request(Method::Post, "/api/next", Some("Bearer 0123456789abcdeX"));
+        assert_unauthorized(authorize_request(&wrong, false));
+        let short = request(Method::Post, "/api/next", Some("Bearer 0123"));
+        assert_unauthorized(authorize_request(&short, false));
+        let not_bearer = request(Method::Post, "/api/next", Some(&format!("Basic {}", TOKEN)));
+        assert_unauthorized(authorize_request(&not_bearer, false));
+    }
+
+    #[test]
+    fn mutating_request_with_token_is_routed() {
+        let authorized = request(Method::Post, "/api/next", Some(&format!("Bearer {}", TOKEN)));
+        assert!(authorize_request(&authorized, false).is_ok());
+    }
+
+    #[test]
+    fn reads_need_a_token_only_when_configured() {
+        let read = request(Method::Get, "/api/now-playing", None);
+        assert!(authorize_request(&read, false).is_ok());
+        assert_unauthorized(authorize_request(&read, true));
+        let authorized = request(Method::Get, "/api/now-playing", Some(&format!("Bearer {}", TOKEN)));
+        assert!(authorize_request(&authorized, true).is_ok());
+    }
+
+    #[test]
+    fn remote_page_and_preflight_need_no_token() {
+        assert!(authorize_request(&request(Method::Get, "/remote", None), true).is_ok());
+        assert!(authorize_request(&request(Method::Options, "/api/next", None), true).is_ok());
+    }
+
+    #[test]
+    fn query_token_is_only_taken_where_headers_cannot_be_set() {
+        let action = request(Method::Get, &format!("/actions/next?token={}", TOKEN), None);
+        assert!(authorize_request(&action, false).is_ok());
+        assert_unauthorized(authorize_request(&request(Method::Get, "/actions/next", None), false));
+        let api = request(Method::Post, &format!("/api/next?token={}", TOKEN), None);
+        assert_unauthorized(authorize_request(&api, false));
+    }
+
+    fn from_host(host: &str) -> Request {
+        TestRequest::new()
+            .with_path("/remote")
+            .with_header(header("Host", host))
+            .into()
+    }
+
+    #[test]
+    fn advertised_mdns_host_is_the_same_origin() {
+        let mdns_host = Some("ytune-desk.local");
+        let request = from_host("ytune-desk.local:9863");
+        assert!(is_same_origin(&request, "http://ytune-desk.local:9863", mdns_host));
+        assert!(!is_same_origin(&request, "http://ytune-desk.local:9863", None));
+        assert!(!is_same_origin(&request, "http://evil.example", mdns_host));
+
+        let other = from_host("ytune-other.local:9863");
+        assert!(!is_same_origin(&other, "http://ytune-other.local:9863", mdns_host));
+        let address = from_host("192.168.1.20:9863");
+        assert!(is_same_origin(&address, "http://192.168.1.20:9863", None));
+    }
+
+    const ALLOWED_ORIGIN: &str = "http://allowed.example";
+
+    /// Starts a server on a free loopback port, returning its address and
+    /// the events it forwarded to the event loop.
+    fn start_server() -> (SocketAddr, Receiver<UserEvent>) {
+        let (sender, events) = mpsc::channel();
+        let context = ApiContext {
+            art_cache: None,
+            last_track: Arc::default(),
+            discord_status: Arc::default(),
+            app_status: Arc::default(),
+            event_stream: Arc::default(),
+            site_host: "music.youtube.com",
+            send_event: Box::new(move |event| sender.send(event).is_ok()),
+            token: TOKEN.to_string(),
+            require_token_for_reads: false,
+            allowed_origins: vec![ALLOWED_ORIGIN.to_string()],
+            mdns_host: Some("ytune-desk.local".to_string()),
+            now_playing_template: "{title} - {artist}".to_string(),
+            now_playing_idle_text: String::new(),
+            action_limiter: RateLimiter::default(),
+            metrics: Arc::default(),
+            track_watch: Arc::default(),
+        };
+        (start(IpAddr::from(Ipv4Addr::LOCALHOST), 0, context).unwrap(), events)
+    }
+
+    /// Sends one request and returns the status and the response headers,
+    /// lowercased.
+    fn send(address: SocketAddr, method: &str, path: &str, headers: &[(&str, &str)]) -> (u16, Vec<String>) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut request = format!("{} {} HTTP/1.1\r\nConnection: close\r\nContent-Length: 0\r\n", method, path);
+        if !headers.iter().any(|(name, _)| *name == "Host") {
+            request.push_str(&format!("Host: {}\r\n", address));
+        }
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, _) = response.split_once("\r\n\r\n").unwrap();
+        let mut lines = head.lines();
+        let status = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+        (status, lines.map(str::to_ascii_lowercase).collect())
+    }
+
+    fn has_header(headers: &[String], line: &str) -> bool {
+        headers.iter().any(|header| header == line)
+    }
+
+    #[test]
+    fn server_runs_commands_only_with_the_right_token() {
+        let (address, events) = start_server();
+        let bearer = format!("Bearer {}", TOKEN);
+
+        let (status, headers) = send(address, "POST", "/api/next", &[]);
+        assert_eq!(status, 401);
+        assert!(has_header(&headers, "www-authenticate: bearer"));
+        let (status, _) = send(address, "POST", "/api/next", &[("Authorization", "Bearer 0123456789abcdeX")]);
+        assert_eq!(status, 401);
+        assert!(events.try_recv().is_err());
+
+        let (status, _) = send(address, "POST", "/api/next", &[("Authorization", &bearer)]);
+        assert_eq!(status, 202);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(UserEvent::PlayerCommand(PlayerCommand::Next))
+        ));
+    }
+
+    #[test]
+    fn server_refuses_foreign_origins_even_with_the_token() {
+        let (address, events) = start_server();
+        let bearer = format!("Bearer {}", TOKEN);
+
+        let (status, headers) = send(
+            address,
+            "POST",
+            "/api/pause",
+            &[("Authorization", &bearer), ("Origin", "http://evil.example")],
+        );
+        assert_eq!(status, 403);
+        assert!(!headers.iter().any(|header| header.starts_with("access-control-allow-origin")));
+        assert!(events.try_recv().is_err());
+
+        // A rebound domain pointing here isn't the API's own origin.
+        let (status, _) = send(
+            address,
+            "GET",
+            "/api/version",
+            &[("Host", "evil.example"), ("Origin", "http://evil.example")],
+        );
+        assert_eq!(status, 403);
+    }
+
+    #[test]
+    fn server_allows_listed_and_own_origins() {
+        let (address, events) = start_server();
+        let bearer = format!("Bearer {}", TOKEN);
+
+        let (status, headers) = send(
+            address,
+            "POST",
+            "/api/pause",
+            &[("Authorization", &bearer), ("Origin", ALLOWED_ORIGIN)],
+        );
+        assert_eq!(status, 202);
+        assert!(has_header(&headers, "access-control-allow-origin: http://allowed.example"));
+        assert!(has_header(&headers, "vary: origin"));
+        assert!(events.try_recv().is_ok());
+
+        let own = format!("http://{}", address);
+        let (status, headers) = send(address, "GET", "/api/version", &[("Origin", &own)]);
+        assert_eq!(status, 200);
+        assert!(has_header(&headers, &format!("access-control-allow-origin: {}", own)));
+
+        let mdns_host = format!("ytune-desk.local:{}", address.port());
+        let mdns_origin = format!("http://{}", mdns_host);
+        let (status, _) = send(address, "GET", "/remote", &[("Host", &mdns_host), ("Origin", &mdns_origin)]);
+        assert_eq!(status, 200);
+    }
+
+    #[test]
+    fn server_answers_preflights_for_allowed_origins() {
+        let (address, _events) = start_server();
+        let (status, headers) = send(
+            address,
+            "OPTIONS",
+            "/api/next",
+            &[("Origin", ALLOWED_ORIGIN), ("Access-Control-Request-Method", "POST")],
+        );
+        assert_eq!(status, 204);
+        assert!(has_header(&headers, "access-control-allow-origin: http://allowed.example"));
+        assert!(has_header(&headers, "access-control-allow-methods: get, post"));
+        assert!(has_header(&headers, "access-control-allow-headers: authorization, content-type"));
+        assert!(has_header(&headers, &format!("access-control-max-age: {}", PREFLIGHT_MAX_AGE)));
+
+        let (status, _) = send(address, "OPTIONS", "/api/next", &[("Origin", "http://evil.example")]);
+        assert_eq!(status, 403);
+    }
+
+    #[test]
+    fn server_limits_actions_per_address() {
+        let (address, events) = start_server();
+        let path = format!("/actions/playpause?token={}", TOKEN);
+        for _ in 0..ACTION_RATE_LIMIT {
+            assert_eq!(send(address, "GET", &path, &[]).0, 200);
+        }
+        let (status, headers) = send(address, "GET", &path, &[]);
+        assert_eq!(status, 429);
+        assert!(has_header(&headers, "retry-after: 1"));
+        assert_eq!(events.try_iter().count(), ACTION_RATE_LIMIT as usize);
+
+        // Only /actions/ is limited.
+        assert_eq!(send(address, "GET", "/api/version", &[]).0, 200);
+    }
+}
@@ -0,0 +1,82 @@
+// `GET /api/now-playing?etag=<etag>&wait=<seconds>`, for clients that can't
+// hold a WebSocket. The etag changes with everything in the now-playing JSON
+// except the position, which moves on every scrape; clients that show it
+// count it up themselves between answers.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{api::types::NowPlaying, LastTrackInfo};
+
+/// Each waiter holds a thread, so past this many new ones are turned away.
+const MAX_WAITERS: usize = 64;
+
+/// Wakes waiting requests whenever the shared track is written.
+#[derive(Default)]
+pub struct TrackWatch {
+    generation: Mutex<u64>,
+    changed: Condvar,
+    waiters: AtomicUsize,
+}
+
+impl TrackWatch {
+    /// Called after every write to the shared track.
+    pub fn notify(&self) {
+        *self.generation.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    /// A place among the waiters, or None when all of them are taken.
+    pub fn join(self: &Arc<Self>) -> Option<Waiter> {
+        let joined = self
+            .waiters
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < MAX_WAITERS).then_some(count + 1)
+            })
+            .is_ok();
+        joined.then(|| Waiter(Arc::clone(self)))
+    }
+}
+
+/// Gives its place back when dropped.
+pub struct Waiter(Arc<TrackWatch>);
+
+impl Waiter {
+    /// Blocks until the now-playing state no longer has `etag`, returning
+    /// the new state, or until `timeout` passes, returning None.
+    pub fn wait(&self, last_track: &Mutex<LastTrackInfo>, etag: &str, timeout: Duration) -> Option<NowPlaying> {
+        let deadline = Instant::now() + timeout;
+        let mut generation = self.0.generation.lock().unwrap();
+        loop {
+            // Read under the watch's lock, so a write can't slip in between
+            // this check and the wait.
+            let now_playing = NowPlaying::from(last_track.lock().unwrap().clone());
+            if self::etag(&now_playing) != etag {
+                return Some(now_playing);
+            }
+            let remaining = deadline.checked_duration_since(Instant::now()).filter(|left| !left.is_zero())?;
+            generation = self.0.changed.wait_timeout(generation, remaining).unwrap().0;
+        }
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        self.0.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn etag(now_playing: &NowPlaying) -> String {
+    let mut now_playing = now_playing.clone();
+    now_playing.track.position_sec = None;
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(&now_playing).unwrap_or_default().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
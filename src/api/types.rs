@@ -0,0 +1,341 @@
+// What the local API, its event stream, the control pipe and socket, webhooks
+// and MQTT put on the wire, in one place so the transports can't drift apart.
+//
+// Compatibility: clients must ignore fields they don't know, and ytune
+// ignores unknown fields in requests. Fields are only added within an
+// `API_VERSION`; one that goes away is kept, and listed as deprecated in
+// the README, for a release first.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{events::TrackEventKind, LastTrackInfo};
+
+/// Version of the wire format, sent as `"v"` on the event stream and the
+/// control pipe and socket, and by `GET /api/version`.
+pub const API_VERSION: u64 = 1;
+
+/// `GET /api/version`.
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub app: &'static str,
+    pub app_version: &'static str,
+    pub api_version: u64,
+    /// What this instance offers, e.g. `"art"` only while `serve_art` is on.
+    pub features: Vec<&'static str>,
+}
+
+impl VersionInfo {
+    pub fn new(features: Vec<&'static str>) -> Self {
+        VersionInfo {
+            app: "ytune",
+            app_version: env!("CARGO_PKG_VERSION"),
+            api_version: API_VERSION,
+            features,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    /// Nothing is loaded.
+    Stopped,
+}
+
+/// The current track as `/api/now-playing` and the event stream's snapshot
+/// send it: the track's fields plus `state`.
+#[derive(Clone, Debug, Serialize)]
+pub struct NowPlaying {
+    #[serde(flatten)]
+    pub track: LastTrackInfo,
+    pub state: PlaybackState,
+}
+
+impl From<LastTrackInfo> for NowPlaying {
+    fn from(track: LastTrackInfo) -> Self {
+        let state = if track.title.is_none() {
+            PlaybackState::Stopped
+        } else if track.is_playing {
+            PlaybackState::Playing
+        } else {
+            PlaybackState::Paused
+        };
+        NowPlaying { track, state }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamEvent {
+    /// The first message to every client, carrying a `NowPlaying`.
+    Snapshot,
+    Started,
+    Paused,
+    Resumed,
+    Position,
+    Stopped,
+}
+
+impl From<TrackEventKind> for StreamEvent {
+    fn from(kind: TrackEventKind) -> Self {
+        match kind {
+            TrackEventKind::Started => StreamEvent::Started,
+            TrackEventKind::Paused => StreamEvent::Paused,
+            TrackEventKind::Resumed => StreamEvent::Resumed,
+            TrackEventKind::Position => StreamEvent::Position,
+            TrackEventKind::Stopped => StreamEvent::Stopped,
+        }
+    }
+}
+
+/// A message on the `/api/events` WebSocket. `track` is a `NowPlaying` in
+/// the snapshot and the track as it was at the event otherwise.
+#[derive(Debug, Serialize)]
+pub struct StreamMessage<'a, T: Serialize> {
+    pub v: u64,
+    pub event: StreamEvent,
+    /// Unix time in seconds.
+    pub timestamp: u64,
+    pub track: &'a T,
+}
+
+impl<'a, T: Serialize> StreamMessage<'a, T> {
+    pub fn new(event: StreamEvent, timestamp: u64, track: &'a T) -> Self {
+        StreamMessage {
+            v: API_VERSION,
+            event,
+            timestamp,
+            track,
+        }
+    }
+}
+
+/// The response to a control pipe or socket request.
+#[derive(Debug, Serialize)]
+pub struct ControlResponse {
+    pub v: u64,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<LastTrackInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ControlResponse {
+    pub fn ok() -> Self {
+        ControlResponse {
+            v: API_VERSION,
+            ok: true,
+            track: None,
+            error: None,
+        }
+    }
+
+    pub fn track(track: LastTrackInfo) -> Self {
+        ControlResponse {
+            track: Some(track),
+            ..ControlResponse::ok()
+        }
+    }
+
+    pub fn error(error: impl Into<String>) -> Self {
+        ControlResponse {
+            ok: false,
+            error: Some(error.into()),
+            ..ControlResponse::ok()
+        }
+    }
+}
+
+/// One JSON line, as the pipe and socket send it.
+impl fmt::Display for ControlResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&serde_json::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+/// Pushed to control socket clients on every playback event.
+#[derive(Debug, Serialize)]
+pub struct ControlEvent<'a> {
+    pub v: u64,
+    pub event: &'static str,
+    pub kind: TrackEventKind,
+    pub track: &'a LastTrackInfo,
+}
+
+impl<'a> ControlEvent<'a> {
+    pub fn track_update(kind: TrackEventKind, track: &'a LastTrackInfo) -> Self {
+        ControlEvent {
+            v: API_VERSION,
+            event: "trackUpdate",
+            kind,
+            track,
+        }
+    }
+}
+
+/// The retained payload of the MQTT `<base>/state` topic.
+#[derive(Debug, Serialize)]
+pub struct MqttState {
+    pub v: u64,
+    pub state: PlaybackState,
+}
+
+impl MqttState {
+    pub fn new(state: PlaybackState) -> Self {
+        MqttState { v: API_VERSION, state }
+    }
+}
+
+/// The retained payload of the MQTT `<base>/track` topic, which Home
+/// Assistant shows as attributes: the track's fields plus `v`.
+#[derive(Debug, Serialize)]
+pub struct MqttTrack<'a> {
+    pub v: u64,
+    #[serde(flatten)]
+    pub track: &'a LastTrackInfo,
+}
+
+impl<'a> MqttTrack<'a> {
+    pub fn new(track: &'a LastTrackInfo) -> Self {
+        MqttTrack { v: API_VERSION, track }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, to_value};
+
+    use super::*;
+
+    fn track() -> LastTrackInfo {
+        LastTrackInfo {
+            title: Some("Song".to_string()),
+            artist: Some("Artist".to_string()),
+            album: Some("Album".to_string()),
+            album_art: Some("https://lh3.googleusercontent.com/art=w60-h60".to_string()),
+            video_id: Some("abcdefghijk".to_string()),
+            duration_sec: Some(200),
+            position_sec: Some(42),
+            is_playing: true,
+            is_video: false,
+            explicit: true,
+            liked: Some(true),
+            queue_index: Some(3),
+            queue_total: Some(12),
+            volume: Some(80),
+        }
+    }
+
+    /// The field names clients read; renaming one breaks them.
+    fn track_json() -> serde_json::Value {
+        json!({
+            "title": "Song",
+            "artist": "Artist",
+            "album": "Album",
+            "album_art": "https://lh3.googleusercontent.com/art=w60-h60",
+            "video_id": "abcdefghijk",
+            "duration_sec": 200,
+            "position_sec": 42,
+            "is_playing": true,
+            "is_video": false,
+            "explicit": true,
+            "liked": true,
+            "queue_index": 3,
+            "queue_total": 12,
+            "volume": 80,
+        })
+    }
+
+    #[test]
+    fn api_version_is_one() {
+        assert_eq!(API_VERSION, 1);
+        let version = to_value(VersionInfo::new(vec!["art"])).unwrap();
+        assert_eq!(
+            version,
+            json!({
+                "app": "ytune",
+                "app_version": env!("CARGO_PKG_VERSION"),
+                "api_version": 1,
+                "features": ["art"],
+            })
+        );
+    }
+
+    #[test]
+    fn now_playing_flattens_the_track_and_adds_state() {
+        let mut expected = track_json();
+        expected["state"] = json!("playing");
+        let now_playing = to_value(NowPlaying::from(track())).unwrap();
+        assert_eq!(now_playing, expected);
+
+        // Clients that read it as a track get the same track back.
+        let read_back: LastTrackInfo = serde_json::from_value(now_playing).unwrap();
+        assert_eq!(read_back, track());
+    }
+
+    #[test]
+    fn now_playing_state_follows_the_track() {
+        let paused = LastTrackInfo {
+            is_playing: false,
+            ..track()
+        };
+        assert_eq!(NowPlaying::from(paused).state, PlaybackState::Paused);
+        let stopped = NowPlaying::from(LastTrackInfo::default());
+        assert_eq!(stopped.state, PlaybackState::Stopped);
+        assert_eq!(to_value(&stopped).unwrap()["state"], "stopped");
+        assert_eq!(to_value(&stopped).unwrap()["title"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn stream_messages_carry_the_version_and_event() {
+        let track = track();
+        let message = to_value(StreamMessage::new(StreamEvent::from(TrackEventKind::Resumed), 1_700_000_000, &track));
+        assert_eq!(
+            message.unwrap(),
+            json!({ "v": 1, "event": "resumed", "timestamp": 1_700_000_000u64, "track": track_json() })
+        );
+        let snapshot = NowPlaying::from(track);
+        let message = to_value(StreamMessage::new(StreamEvent::Snapshot, 0, &snapshot)).unwrap();
+        assert_eq!(message["event"], "snapshot");
+        assert_eq!(message["track"]["state"], "playing");
+    }
+
+    #[test]
+    fn control_responses_leave_out_what_they_lack() {
+        assert_eq!(to_value(ControlResponse::ok()).unwrap(), json!({ "v": 1, "ok": true }));
+        assert_eq!(
+            to_value(ControlResponse::error("nope")).unwrap(),
+            json!({ "v": 1, "ok": false, "error": "nope" })
+        );
+        assert_eq!(
+            to_value(ControlResponse::track(track())).unwrap(),
+            json!({ "v": 1, "ok": true, "track": track_json() })
+        );
+        assert_eq!(ControlResponse::ok().to_string(), r#"{"v":1,"ok":true}"#);
+    }
+
+    #[test]
+    fn control_events_are_track_updates() {
+        let track = track();
+        assert_eq!(
+            to_value(ControlEvent::track_update(TrackEventKind::Started, &track)).unwrap(),
+            json!({ "v": 1, "event": "trackUpdate", "kind": "started", "track": track_json() })
+        );
+    }
+
+    #[test]
+    fn mqtt_payloads_carry_the_version() {
+        assert_eq!(
+            to_value(MqttState::new(PlaybackState::Paused)).unwrap(),
+            json!({ "v": 1, "state": "paused" })
+        );
+        let mut expected = track_json();
+        expected["v"] = json!(1);
+        assert_eq!(to_value(MqttTrack::new(&track())).unwrap(), expected);
+    }
+}
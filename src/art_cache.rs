@@ -0,0 +1,114 @@
+use std::{
+    io::Read,
+    sync::{mpsc::Receiver, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{events::TrackEvent, youtube_url};
+
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Album art is a few hundred KB at most; anything bigger is not an image we want.
+const MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+const ART_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone)]
+pub struct CachedArt {
+    pub source_url: String,
+    pub content_type: String,
+    pub bytes: Arc<Vec<u8>>,
+}
+
+/// Album art of the current track, and the previous one so a consumer
+/// reloading during a track change still gets an image.
+#[derive(Default)]
+pub struct ArtCache {
+    slots: Mutex<(Option<CachedArt>, Option<CachedArt>)>,
+}
+
+impl ArtCache {
+    pub fn current(&self) -> Option<CachedArt> {
+        self.slots.lock().unwrap().0.clone()
+    }
+
+    /// The art `run_fetcher` keeps for `url` at `size`, waiting up to
+    /// `timeout` for it to finish downloading.
+    pub fn wait_for(&self, url: &str, size: u32, timeout: Duration) -> Option<CachedArt> {
+        let url = youtube_url::resize_youtube_art_url(url, size);
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(art) = self.current().filter(|art| art.source_url == url) {
+                return Some(art);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(ART_POLL_INTERVAL);
+        }
+    }
+
+    /// Makes the cached image for `url` current, if either slot has it.
+    fn promote(&self, url: &str) -> bool {
+        let mut slots = self.slots.lock().unwrap();
+        if slots.1.as_ref().is_some_and(|art| art.source_url == url) {
+            let (current, previous) = &mut *slots;
+            std::mem::swap(current, previous);
+            return true;
+        }
+        slots.0.as_ref().is_some_and(|art| art.source_url == url)
+    }
+
+    fn insert(&self, art: CachedArt) {
+        let mut slots = self.slots.lock().unwrap();
+        slots.1 = slots.0.replace(art);
+    }
+}
+
+/// Fetches the art, at `size` pixels where the CDN allows, whenever the
+/// track's art URL changes. Returns when the event bus is closed.
+pub fn run_fetcher(cache: Arc<ArtCache>, size: u32, events: Receiver<TrackEvent>) {
+    let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+    let mut failed_url: Option<String> = None;
+
+    for event in events {
+        let Some(url) = event.track.album_art.as_deref() else {
+            continue;
+        };
+        let resized = youtube_url::resize_youtube_art_url(url, size);
+        let url = resized.as_str();
+        // Going back to the previous track doesn't need a download.
+        if cache.promote(url) || failed_url.as_deref() == Some(url) {
+            continue;
+        }
+
+        match fetch(&agent, url) {
+            Ok(art) => {
+                cache.insert(art);
+                failed_url = None;
+            }
+            Err(e) => {
+                log::warn!("Failed to fetch album art from {}: {}", url, e);
+                failed_url = Some(url.to_string());
+            }
+        }
+    }
+}
+
+/// Downloads one image, for consumers that keep their own copy.
+pub fn fetch(agent: &ureq::Agent, url: &str) -> Result<CachedArt, String> {
+    let response = agent.get(url).call().map_err(|e| e.to_string())?;
+    let content_type = response.content_type().to_string();
+
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_IMAGE_BYTES)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+
+    Ok(CachedArt {
+        source_url: url.to_string(),
+        content_type,
+        bytes: Arc::new(bytes),
+    })
+}
@@ -0,0 +1,235 @@
+use std::io;
+
+/// Whether this platform has an autostart mechanism ytune knows about.
+pub const SUPPORTED: bool = cfg!(any(target_os = "windows", target_os = "linux"));
+
+/// Name of the Run value on Windows and of the `.desktop` file on Linux.
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+const ENTRY_NAME: &str = "ytune";
+
+/// The command the system runs at login: this executable, hidden in the
+/// tray, and in portable mode when this copy runs in it.
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn launch_args() -> io::Result<(std::path::PathBuf, Vec<&'static str>)> {
+    let (exe, mut args) = exe_args()?;
+    args.insert(0, "--minimized");
+    Ok((exe, args))
+}
+
+/// This executable, in portable mode when this copy runs in it.
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn exe_args() -> io::Result<(std::path::PathBuf, Vec<&'static str>)> {
+    let exe = std::env::current_exe()?;
+    let args = if crate::config::is_portable() { vec!["--portable"] } else { Vec::new() };
+    Ok((exe, args))
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::io;
+
+    use windows::{
+        core::{w, HSTRING, PCWSTR},
+        Win32::System::Registry::{
+            RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ,
+            RRF_RT_REG_SZ,
+        },
+    };
+
+    use super::{exe_args, launch_args, ENTRY_NAME};
+
+    const RUN_KEY: PCWSTR = w!("Software\\Microsoft\\Windows\\CurrentVersion\\Run");
+
+    pub fn is_enabled() -> bool {
+        let name = HSTRING::from(ENTRY_NAME);
+        unsafe {
+            RegGetValueW(HKEY_CURRENT_USER, RUN_KEY, &name, RRF_RT_REG_SZ, None, None, None)
+                .is_ok()
+        }
+    }
+
+    pub fn enable() -> io::Result<()> {
+        let (exe, args) = launch_args()?;
+        let command = format!("\"{}\" {}", exe.display(), args.join(" "));
+        let data: Vec<u16> = command.encode_utf16().chain(std::iter::once(0)).collect();
+        let name = HSTRING::from(ENTRY_NAME);
+        unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                RUN_KEY,
+                &name,
+                REG_SZ.0,
+                Some(data.as_ptr() as *const std::ffi::c_void),
+                (data.len() * std::mem::size_of::<u16>()) as u32,
+            )
+        }
+        .ok()
+        .map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    pub fn disable() -> io::Result<()> {
+        if !is_enabled() {
+            return Ok(());
+        }
+        let name = HSTRING::from(ENTRY_NAME);
+        unsafe { RegDeleteKeyValueW(HKEY_CURRENT_USER, RUN_KEY, &name) }
+            .ok()
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    /// `HKCU\Software\Classes\<scheme>`, which makes `<scheme>:` links
+    /// open this executable with `--open-uri <link>`.
+    pub fn register_uri_scheme(scheme: &str) -> io::Result<()> {
+        let (exe, mut args) = exe_args()?;
+        args.extend(["--open-uri", "\"%1\""]);
+        let command = format!("\"{}\" {}", exe.display(), args.join(" "));
+        let key = format!("Software\\Classes\\{}", scheme);
+        set_string(&key, None, &format!("URL:{} session", ENTRY_NAME))?;
+        set_string(&key, Some("URL Protocol"), "")?;
+        set_string(&format!("{}\\shell\\open\\command", key), None, &command)
+    }
+
+    /// Sets a string value, or the key's default value when `name` is None,
+    /// creating the key as needed.
+    fn set_string(key: &str, name: Option<&str>, value: &str) -> io::Result<()> {
+        let data: Vec<u16> = value.encode_utf16().chain(std::iter::once(0)).collect();
+        let key = HSTRING::from(key);
+        let name = name.map(HSTRING::from);
+        let name = name.as_ref().map_or(PCWSTR::null(), |name| PCWSTR(name.as_ptr()));
+        unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                &key,
+                name,
+                REG_SZ.0,
+                Some(data.as_ptr() as *const std::ffi::c_void),
+                (data.len() * std::mem::size_of::<u16>()) as u32,
+            )
+        }
+        .ok()
+        .map_err(|e| io::Error::other(e.to_string()))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::{fs, io, path::PathBuf, process::Command};
+
+    use super::{exe_args, launch_args, ENTRY_NAME};
+
+    /// `~/.config/autostart/ytune.desktop`, per the XDG autostart spec. This
+    /// is the desktop's directory, so it ignores portable mode.
+    fn entry_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("autostart").join(format!("{}.desktop", ENTRY_NAME)))
+    }
+
+    pub fn is_enabled() -> bool {
+        entry_path().is_some_and(|path| path.is_file())
+    }
+
+    pub fn enable() -> io::Result<()> {
+        let path = entry_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory available"))?;
+        let (exe, args) = launch_args()?;
+        let entry = format!(
+            "[Desktop Entry]\nType=Application\nName=ytune\nExec={} {}\nX-GNOME-Autostart-enabled=true\n",
+            quote_exec_arg(&exe.to_string_lossy()),
+            args.join(" ")
+        );
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, entry)
+    }
+
+    pub fn disable() -> io::Result<()> {
+        match entry_path().map(fs::remove_file) {
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes `~/.local/share/applications/ytune-<scheme>.desktop`, which
+    /// opens `<scheme>:` links with `--open-uri <link>`, and makes it the
+    /// handler through `xdg-mime`.
+    pub fn register_uri_scheme(scheme: &str) -> io::Result<()> {
+        let dir = dirs::data_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No data directory available"))?
+            .join("applications");
+        let file_name = format!("{}-{}.desktop", ENTRY_NAME, scheme);
+        let (exe, mut args) = exe_args()?;
+        args.extend(["--open-uri", "%u"]);
+        let entry = format!(
+            "[Desktop Entry]\nType=Application\nName=ytune\nExec={} {}\nNoDisplay=true\nMimeType=x-scheme-handler/{};\n",
+            quote_exec_arg(&exe.to_string_lossy()),
+            args.join(" "),
+            scheme
+        );
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(&file_name), entry)?;
+
+        let status = Command::new("xdg-mime")
+            .args(["default", &file_name, &format!("x-scheme-handler/{}", scheme)])
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("xdg-mime exited with {}", status)));
+        }
+        Ok(())
+    }
+
+    /// Quotes an `Exec` argument as the desktop entry spec requires.
+    fn quote_exec_arg(arg: &str) -> String {
+        let mut quoted = String::from("\"");
+        for c in arg.chars() {
+            if matches!(c, '"' | '`' | '$' | '\\') {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use std::io;
+
+    pub fn is_enabled() -> bool {
+        false
+    }
+
+    pub fn enable() -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Autostart is not supported on this platform"))
+    }
+
+    pub fn disable() -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn register_uri_scheme(_scheme: &str) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Link handlers are not supported on this platform"))
+    }
+}
+
+/// Whether ytune is currently registered to start on login.
+pub fn is_enabled() -> bool {
+    platform::is_enabled()
+}
+
+pub fn set_enabled(enabled: bool) -> io::Result<()> {
+    if enabled {
+        platform::enable()?;
+    } else {
+        platform::disable()?;
+    }
+    log::info!("Start with system {}", if enabled { "enabled" } else { "disabled" });
+    Ok(())
+}
+
+/// Makes the system open `<scheme>:` links with this executable.
+pub fn register_uri_scheme(scheme: &str) -> io::Result<()> {
+    platform::register_uri_scheme(scheme)?;
+    log::info!("Registered ytune for {}: links", scheme);
+    Ok(())
+}
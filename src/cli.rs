@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+
+use crate::share_session::Session;
+
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+pub enum Command {
+    /// Launch the player window.
+    Run,
+    /// Print the most recent plays and exit.
+    History { limit: usize },
+    /// Print the most recent plays whose title, artist or album match.
+    SearchHistory { query: String },
+    /// Write the play history to a CSV or JSON file, optionally limited to
+    /// plays started within `[since, until)` (Unix seconds).
+    ExportHistory {
+        path: PathBuf,
+        since: Option<u64>,
+        until: Option<u64>,
+    },
+    /// Add the plays from a Google Takeout history export, a file or a
+    /// directory of them, to the local history.
+    ImportTakeout { path: PathBuf },
+    /// Authorize ytune with Last.fm and store the session key.
+    LastFmLogin,
+    /// Summarize the play history, as text or JSON.
+    Stats { json: bool },
+    /// Print the track the running ytune is playing as JSON.
+    PrintTrack,
+    /// Send a control command, e.g. `next`, to the running ytune and print
+    /// its response.
+    Send { cmd: String },
+    /// Print an i3bar block for every track change of the running ytune.
+    I3StatusBlock,
+    /// Print a `ytune://session/` link to the running ytune's track and
+    /// position, for another ytune user to open.
+    ShareSession,
+    /// Start ytune on login and register it for `ytune://` links.
+    InstallAutostart,
+}
+
+pub struct Args {
+    pub command: Command,
+    /// Store config, data, and the webview profile next to the executable.
+    pub portable: bool,
+    /// Start with the window hidden in the tray.
+    pub minimized: bool,
+    /// A session link to play, from `--open-uri`.
+    pub open_session: Option<Session>,
+}
+
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+    let mut args = args.into_iter().skip(1).peekable();
+    let mut command = Command::Run;
+    let mut portable = false;
+    let mut minimized = false;
+    let mut open_session = None;
+    let mut json = false;
+    let mut since = None;
+    let mut until = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--history" => {
+                let limit = match args.next_if(|next| !next.starts_with("--")) {
+                    Some(value) => value
+                        .parse()
+                        .map_err(|_| format!("--history expects a number, got '{}'", value))?,
+                    None => DEFAULT_HISTORY_LIMIT,
+                };
+                command = Command::History { limit };
+            }
+            "--search-history" => {
+                let query = args
+                    .next()
+                    .filter(|query| !query.trim().is_empty())
+                    .ok_or("--search-history expects something to search for, e.g. --search-history \"daft punk\"")?;
+                command = Command::SearchHistory { query };
+            }
+            "--export-history" => {
+                let path = args.next().ok_or("--export-history expects a file path")?;
+                command = Command::ExportHistory {
+                    path: PathBuf::from(path),
+                    since: None,
+                    until: None,
+                };
+            }
+            "--import-takeout" => {
+                let path = args.next().ok_or("--import-takeout expects a file or directory")?;
+                command = Command::ImportTakeout {
+                    path: PathBuf::from(path),
+                };
+            }
+            "--send" => {
+                let cmd = args
+                    .next()
+                    .filter(|cmd| !cmd.trim().is_empty())
+                    .ok_or("--send expects a command, e.g. --send next")?;
+                command = Command::Send { cmd };
+            }
+            "--print-track" => command = Command::PrintTrack,
+            "--i3status-block" => command = Command::I3StatusBlock,
+            "--share-session" => command = Command::ShareSession,
+            "--install-autostart" => command = Command::InstallAutostart,
+            "--open-uri" => {
+                let uri = args.next().ok_or("--open-uri expects a ytune://session/ link")?;
+                open_session = Some(Session::parse(&uri)?);
+            }
+            "--lastfm-login" => command = Command::LastFmLogin,
+            "--portable" => portable = true,
+            "--minimized" => minimized = true,
+            "--stats" => command = Command::Stats { json: false },
+            "--json" => json = true,
+            "--since" => {
+                let value = args.next().ok_or("--since expects a date")?;
+                since = Some(parse_date(&value, false)?);
+            }
+            "--until" => {
+                let value = args.next().ok_or("--until expects a date")?;
+                until = Some(parse_date(&value, true)?);
+            }
+            other => return Err(format!("Unknown argument '{}'", other)),
+        }
+    }
+
+    if open_session.is_some() && !matches!(command, Command::Run) {
+        return Err("--open-uri can't be combined with other commands".to_string());
+    }
+
+    match &mut command {
+        Command::Stats { json: stats_json } => *stats_json = json,
+        _ if json => return Err("--json can only be used with --stats".to_string()),
+        Command::ExportHistory {
+            since: export_since,
+            until: export_until,
+            ..
+        } => {
+            *export_since = since;
+            *export_until = until;
+        }
+        _ if since.is_some() || until.is_some() => {
+            return Err("--since and --until can only be used with --export-history".to_string());
+        }
+        _ => {}
+    }
+
+    Ok(Args {
+        command,
+        portable,
+        minimized,
+        open_session,
+    })
+}
+
+/// Accepts an RFC 3339 timestamp or a plain `YYYY-MM-DD` local date. A plain
+/// date used as an upper bound includes that whole day.
+fn parse_date(value: &str, end_of_day: bool) -> Result<u64, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return u64::try_from(time.timestamp()).map_err(|_| format!("Date out of range: {}", value));
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", value))?;
+    let date = if end_of_day { date.succ_opt().unwrap_or(date) } else { date };
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .and_then(|time| u64::try_from(time.timestamp()).ok())
+        .ok_or_else(|| format!("Date out of range: {}", value))
+}
+
+/// GUI-subsystem builds have no console of their own, so CLI verbs borrow the
+/// one of the shell that started them.
+#[cfg(target_os = "windows")]
+pub fn attach_parent_console() {
+    use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn attach_parent_console() {}
@@ -0,0 +1,741 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    thread,
+    time::Duration,
+};
+
+use crate::events::TrackEventKind;
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+/// A file with this name next to the executable turns on portable mode.
+const PORTABLE_MARKER_FILE_NAME: &str = "portable.txt";
+/// How often `watch` looks at the config file.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Names a directory to keep everything in instead of the platform ones.
+const CONFIG_DIR_VAR: &str = "YTUNE_CONFIG_DIR";
+
+/// Directory of the executable when running in portable mode.
+static PORTABLE_DIR: OnceLock<PathBuf> = OnceLock::new();
+/// The `YTUNE_CONFIG_DIR` directory, when it is set and writable.
+static OVERRIDE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// `music` plays YouTube Music; `video` plays regular YouTube instead.
+    pub mode: Mode,
+    /// Discord Rich Presence; only in Windows builds with the `discord` feature.
+    pub discord_enabled: bool,
+    /// First presence line. Supports `{title}`, `{artist}`, `{album}`,
+    /// `{position}` and `{duration}`; the line is left out when none of them
+    /// has a value.
+    #[serde(alias = "discord_details_format")]
+    pub details_template: String,
+    /// Second presence line, with the same placeholders.
+    #[serde(alias = "discord_state_format")]
+    pub state_template: String,
+    /// Puts "Title - Artist" on the first line and the album on the second,
+    /// for Discord's smaller layouts, in place of the two templates above.
+    pub compact_presence: bool,
+    /// Text shown when hovering the album art, with the same placeholders.
+    pub discord_large_text_format: String,
+    /// Show the queue position, e.g. "(3 of 12)", next to the state line.
+    pub show_queue: bool,
+    /// Appended to the first presence line for explicit tracks, e.g. " 🅴".
+    pub explicit_suffix: Option<String>,
+    /// Pixel size of the album art asked of the CDN for the presence. Discord
+    /// shows it at up to 256 px. 0 keeps the size the player bar uses.
+    pub discord_album_art_size: u32,
+    /// Pixel size of the album art ytune downloads itself, for the local
+    /// API's `/art.jpg` and the file output's image. 0 keeps the player's size.
+    pub notification_album_art_size: u32,
+    /// How long to look for Discord before assuming it isn't running, spread
+    /// over `discord_connect_attempts` tries.
+    pub discord_connect_timeout_ms: u64,
+    pub discord_connect_attempts: u32,
+    /// Activity shown in Discord: playing, streaming, listening, watching,
+    /// custom or competing. `custom` shows the state line as the status.
+    pub discord_activity_type: DiscordActivityType,
+    /// Twitch or YouTube URL the `streaming` type links to; Discord requires one.
+    pub streaming_url: Option<String>,
+    /// Show "Watching" instead of "Listening to" while a music video plays.
+    pub watching_for_videos: bool,
+    /// Send the elapsed and remaining time with the presence.
+    pub show_timestamps: bool,
+    /// Count down the time remaining; false counts up the time elapsed.
+    /// Tracks without a duration, like live streams, always count up.
+    pub discord_show_time_remaining: bool,
+    /// Record finished plays to the local history database.
+    pub history_enabled: bool,
+    /// Keep at most this many plays; older ones are pruned.
+    pub history_max_entries: Option<u32>,
+    /// Prune plays older than this many days.
+    pub history_max_age_days: Option<u32>,
+    /// Load YouTube Music in the OS light/dark theme and follow changes.
+    pub theme_follows_system: bool,
+    /// CSS selector for the player bar to read, for when ytune picks the wrong
+    /// one. Unset lets ytune choose among the bars on the page.
+    pub player_bar_selector: Option<String>,
+    /// Register ytune to start on login, or unregister it. Unset leaves the
+    /// system alone; the tray's "Start with system" toggle writes this too.
+    pub start_with_system: Option<bool>,
+    /// Hide YouTube Music's "Your browser is outdated" banner instead of
+    /// pointing out how to update the webview.
+    pub suppress_browser_update_banner: bool,
+    /// Start with concert mode on: after 30 seconds without mouse movement the
+    /// window fills with the album art, title and artist. F11 toggles it.
+    pub concert_mode: bool,
+    /// Pause when another app starts playing media; Windows only.
+    pub pause_when_others_play: bool,
+    /// Always start hidden in the tray, like `--minimized`.
+    pub start_minimized: bool,
+    /// Global shortcut that copies the current track, e.g. `CmdOrCtrl+Shift+C`.
+    /// Empty disables it.
+    pub copy_track_hotkey: Option<String>,
+    /// Text copied by the hotkey. Supports the same placeholders as `file_output.template`.
+    pub copy_track_template: String,
+    /// Confirm copying the track or its link with a notification.
+    pub notify_on_copy: bool,
+    /// PNG or ICO used instead of the built-in window and tray icon.
+    pub window_icon_path: Option<PathBuf>,
+    /// Directory of user scripts (`*.js`) run in the page after ytune's own,
+    /// in file name order.
+    pub extra_scripts_dir: Option<PathBuf>,
+    /// Overrides the Last.fm API key ytune was built with.
+    pub lastfm_api_key: Option<String>,
+    /// Overrides the Last.fm API secret ytune was built with.
+    pub lastfm_api_secret: Option<String>,
+    /// Written by the Last.fm authorization flow; scrobbling is off without it.
+    pub lastfm_session_key: Option<String>,
+    /// Unlove tracks on Last.fm when they are unliked. Likes always become loves.
+    pub lastfm_unlove_on_unlike: bool,
+    /// When a play counts as a scrobble, and which ones never do.
+    pub scrobble: ScrobbleConfig,
+    /// Accept JSON commands on `\\.\pipe\ytune-control` on Windows, or on
+    /// a Unix socket elsewhere.
+    pub pipe_server_enabled: bool,
+    /// Where the Unix control socket goes instead of `$XDG_RUNTIME_DIR/ytune.sock`.
+    pub socket_path: Option<PathBuf>,
+    /// `--i3status-block` color for tracks whose album art gives none.
+    pub i3status_color: String,
+    /// gRPC control server on localhost; needs a build with the `grpc` feature.
+    pub grpc_enabled: bool,
+    pub grpc_port: u16,
+    /// Local HTTP server for overlays and other integrations.
+    pub api: ApiConfig,
+    /// Now-playing text file for OBS and similar tools.
+    pub file_output: FileOutputConfig,
+    /// HTTP callbacks fired on playback events.
+    pub webhook: WebhookConfig,
+    /// Local commands run on playback events.
+    pub hooks: HooksConfig,
+    /// Global shortcuts, accelerator to action name, e.g.
+    /// `"Ctrl+Alt+L" = "like"`. Re-read whenever `config.toml` changes.
+    pub hotkeys: BTreeMap<String, String>,
+    /// Home Assistant integration over MQTT.
+    pub mqtt: MqttConfig,
+    /// Listening summary notification.
+    pub summary: SummaryConfig,
+    /// Overlay showing the new track for a few seconds.
+    pub osd: OsdConfig,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Music,
+    Video,
+}
+
+impl Mode {
+    /// Host of the site the player window shows.
+    pub fn host(self) -> &'static str {
+        match self {
+            Mode::Music => crate::YTM_HOST,
+            Mode::Video => crate::YOUTUBE_HOST,
+        }
+    }
+
+    pub fn url(self) -> &'static str {
+        match self {
+            Mode::Music => crate::YTM_URL,
+            Mode::Video => crate::YOUTUBE_HOME_URL,
+        }
+    }
+}
+
+/// Discord's activity types, numbered as in its API.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscordActivityType {
+    Playing = 0,
+    Streaming = 1,
+    Listening = 2,
+    Watching = 3,
+    Custom = 4,
+    Competing = 5,
+}
+
+impl From<DiscordActivityType> for u32 {
+    fn from(activity_type: DiscordActivityType) -> u32 {
+        activity_type as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SummaryPeriod {
+    Day,
+    Week,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SummaryConfig {
+    /// Off by default; needs the play history.
+    pub enabled: bool,
+    /// `day` sums up the last 24 hours, `week` the last seven days and is
+    /// shown on Sundays.
+    pub period: SummaryPeriod,
+    /// Local time, `HH:MM`, to show the summary at; unset shows it only on start.
+    pub at: Option<String>,
+    /// Also show it when ytune starts.
+    pub on_start: bool,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        SummaryConfig {
+            enabled: false,
+            period: SummaryPeriod::Day,
+            at: Some("21:00".to_string()),
+            on_start: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OsdCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OsdConfig {
+    /// Off by default.
+    pub enabled: bool,
+    pub corner: OsdCorner,
+    /// 0 for the primary display, 1 and up for the displays in the order
+    /// the system lists them.
+    pub monitor: usize,
+    /// Distance from the display's edges in logical pixels; the default
+    /// clears most taskbars.
+    pub margin: u32,
+}
+
+impl Default for OsdConfig {
+    fn default() -> Self {
+        OsdConfig {
+            enabled: false,
+            corner: OsdCorner::BottomRight,
+            monitor: 0,
+            margin: 48,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    /// `mqtt://host:1883` or `mqtts://host:8883`; MQTT is off when unset.
+    pub broker: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// State goes to `<base>/state` and `<base>/track`, commands come from `<base>/command`.
+    pub base_topic: String,
+    /// Home Assistant's discovery prefix.
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            broker: None,
+            username: None,
+            password: None,
+            base_topic: "ytune".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+/// Programs to run, each getting the event name as its first argument.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub on_track_change: Option<String>,
+    pub on_pause: Option<String>,
+    pub on_resume: Option<String>,
+    pub on_stop: Option<String>,
+}
+
+impl HooksConfig {
+    pub fn is_empty(&self) -> bool {
+        [&self.on_track_change, &self.on_pause, &self.on_resume, &self.on_stop]
+            .iter()
+            .all(|hook| hook.is_none())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Endpoints that receive a JSON POST per event.
+    pub urls: Vec<String>,
+    /// Signs each body with HMAC-SHA256 in the `X-Ytune-Signature` header.
+    pub secret: Option<String>,
+    /// Which of `started`, `paused`, `resumed`, `stopped` and `position` fire.
+    pub events: Vec<TrackEventKind>,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            urls: Vec::new(),
+            secret: None,
+            events: vec![
+                TrackEventKind::Started,
+                TrackEventKind::Paused,
+                TrackEventKind::Resumed,
+                TrackEventKind::Stopped,
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileOutputConfig {
+    /// File to write; nothing is written when unset.
+    pub path: Option<PathBuf>,
+    /// Supports `{title}`, `{artist}`, `{album}`, `{position}` and `{remaining}`.
+    pub template: String,
+    /// Written while paused or when nothing is playing.
+    pub idle_text: String,
+    /// Also save the album art next to the text file, under the same name
+    /// with the image's extension (`.jpg`, `.png` or `.webp`).
+    pub save_art: bool,
+}
+
+impl Default for FileOutputConfig {
+    fn default() -> Self {
+        FileOutputConfig {
+            path: None,
+            template: "{title} — {artist}".to_string(),
+            idle_text: String::new(),
+            save_art: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScrobbleConfig {
+    /// Share of the track, in percent, that has to be played. Capped at four
+    /// minutes of listening, as Last.fm requires.
+    pub threshold_percent: u8,
+    /// Play at least this many seconds, whatever the percentage says.
+    pub min_seconds: u64,
+    /// Never scrobble tracks of 30 seconds or less.
+    pub skip_short_tracks: bool,
+    /// Titles or artists containing any of these (case-insensitive) are not scrobbled.
+    pub ignore: Vec<String>,
+}
+
+impl Default for ScrobbleConfig {
+    fn default() -> Self {
+        ScrobbleConfig {
+            threshold_percent: 50,
+            min_seconds: 0,
+            skip_short_tracks: true,
+            ignore: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Off by default.
+    pub enabled: bool,
+    /// Address to listen on; localhost unless set to e.g. `"0.0.0.0"` for
+    /// the remote control page on a phone, which makes the token mandatory.
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Download the current album art and serve it at `/art.jpg`.
+    pub serve_art: bool,
+    /// Bearer token commands must carry. Generated and stored the first
+    /// time the API starts without one.
+    pub token: Option<String>,
+    /// Also require the token for now-playing and the album art.
+    pub require_token_for_reads: bool,
+    /// Origins of web pages allowed to call the API from a browser, e.g.
+    /// `"http://localhost:3000"`.
+    pub allowed_origins: Vec<String>,
+    /// Announce the API on the network over mDNS as `_ytune._tcp.local.`
+    /// while it is bound to a non-loopback address.
+    pub advertise: bool,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig {
+            enabled: false,
+            bind: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 9863,
+            serve_art: true,
+            token: None,
+            require_token_for_reads: false,
+            allowed_origins: Vec::new(),
+            advertise: true,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            mode: Mode::Music,
+            discord_enabled: true,
+            details_template: "{title}".to_string(),
+            state_template: "by {artist}".to_string(),
+            compact_presence: false,
+            discord_large_text_format: "{title} — {artist}".to_string(),
+            show_queue: false,
+            explicit_suffix: None,
+            discord_album_art_size: 500,
+            notification_album_art_size: 256,
+            discord_connect_timeout_ms: 5000,
+            discord_connect_attempts: 10,
+            discord_activity_type: DiscordActivityType::Listening,
+            streaming_url: None,
+            watching_for_videos: false,
+            show_timestamps: true,
+            discord_show_time_remaining: true,
+            history_enabled: true,
+            history_max_entries: Some(50_000),
+            history_max_age_days: None,
+            theme_follows_system: false,
+            window_icon_path: None,
+            extra_scripts_dir: None,
+            player_bar_selector: None,
+            start_with_system: None,
+            suppress_browser_update_banner: false,
+            concert_mode: false,
+            pause_when_others_play: false,
+            start_minimized: false,
+            copy_track_hotkey: Some("CmdOrCtrl+Shift+C".to_string()),
+            copy_track_template: "{title} — {artist}".to_string(),
+            notify_on_copy: true,
+            lastfm_api_key: None,
+            lastfm_api_secret: None,
+            lastfm_session_key: None,
+            lastfm_unlove_on_unlike: false,
+            scrobble: ScrobbleConfig::default(),
+            pipe_server_enabled: false,
+            socket_path: None,
+            i3status_color: "#ffffff".to_string(),
+            grpc_enabled: false,
+            grpc_port: 50051,
+            api: ApiConfig::default(),
+            file_output: FileOutputConfig::default(),
+            webhook: WebhookConfig::default(),
+            hooks: HooksConfig::default(),
+            hotkeys: BTreeMap::new(),
+            mqtt: MqttConfig::default(),
+            summary: SummaryConfig::default(),
+            osd: OsdConfig::default(),
+        }
+    }
+}
+
+/// Keeps every file next to the executable instead of in the platform
+/// directories, when `requested` or a `portable.txt` marker is present. Must
+/// run before anything reads a path.
+pub fn init_portable(requested: bool) {
+    let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    else {
+        if requested {
+            log::warn!("Portable mode unavailable: cannot locate the executable");
+        }
+        return;
+    };
+
+    if requested || exe_dir.join(PORTABLE_MARKER_FILE_NAME).is_file() {
+        log::info!("Portable mode: storing data in {}", exe_dir.display());
+        let _ = PORTABLE_DIR.set(exe_dir);
+    }
+}
+
+/// Keeps every file in the directory `YTUNE_CONFIG_DIR` names, laid out
+/// like portable mode, which it takes precedence over. The directory is
+/// created as needed; when it can't be written to, the error is logged and
+/// the usual locations are used. Must run before anything reads a path.
+pub fn init_override_dir() {
+    let Some(dir) = std::env::var_os(CONFIG_DIR_VAR).filter(|dir| !dir.is_empty()) else {
+        return;
+    };
+    let dir = PathBuf::from(dir);
+    match ensure_writable(&dir) {
+        Ok(()) => {
+            log::info!("{} set: storing data in {}", CONFIG_DIR_VAR, dir.display());
+            let _ = OVERRIDE_DIR.set(dir);
+        }
+        Err(e) => log::error!(
+            "Ignoring {}: {} isn't writable ({}); using the default locations",
+            CONFIG_DIR_VAR,
+            dir.display(),
+            e
+        ),
+    }
+}
+
+fn ensure_writable(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(".ytune-write-test");
+    fs::write(&probe, b"")?;
+    fs::remove_file(probe)
+}
+
+pub fn is_portable() -> bool {
+    PORTABLE_DIR.get().is_some()
+}
+
+/// Whether everything lives in one directory of its own, from portable
+/// mode or `YTUNE_CONFIG_DIR`, rather than in the platform directories.
+pub fn has_own_dir() -> bool {
+    own_dir().is_some()
+}
+
+fn own_dir() -> Option<&'static PathBuf> {
+    OVERRIDE_DIR.get().or_else(|| PORTABLE_DIR.get())
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    match own_dir() {
+        Some(dir) => Some(dir.clone()),
+        None => dirs::config_dir().map(|dir| dir.join("ytune")),
+    }
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    match own_dir() {
+        Some(dir) => Some(dir.join("data")),
+        None => dirs::data_dir().map(|dir| dir.join("ytune")),
+    }
+}
+
+/// Webview profile (cookies, local storage) location. `None` keeps the
+/// webview's platform default, so installed copies keep their sign-in.
+pub fn webview_data_dir() -> Option<PathBuf> {
+    own_dir().map(|dir| dir.join("webview"))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(CONFIG_FILE_NAME))
+}
+
+/// Calls `on_change` whenever `config.toml` is saved, going by its
+/// modification time, from a background thread.
+pub fn watch<F>(on_change: F)
+where
+    F: Fn() + Send + 'static,
+{
+    let Some(path) = config_path() else {
+        return;
+    };
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    thread::spawn(move || {
+        let mut current = modified(&path);
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let detected = modified(&path);
+            if detected != current {
+                current = detected;
+                if detected.is_some() {
+                    on_change();
+                }
+            }
+        }
+    });
+}
+
+/// Loads `config.toml` from the platform config directory, falling back to
+/// defaults when the file is missing or cannot be parsed.
+pub fn load() -> Config {
+    let Some(path) = config_path() else {
+        return Config::default();
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => toml::from_str(&contents).map(validate).unwrap_or_else(|e| {
+            log::warn!("Failed to parse {}: {}. Using defaults.", path.display(), e);
+            Config::default()
+        }),
+        Err(_) => Config::default(),
+    }
+}
+
+/// Replaces settings that parse but can't work, with a warning for each.
+fn validate(mut config: Config) -> Config {
+    if config.discord_activity_type == DiscordActivityType::Streaming
+        && !config.streaming_url.as_deref().is_some_and(is_streaming_url)
+    {
+        log::warn!("discord_activity_type \"streaming\" needs a Twitch or YouTube streaming_url; using \"listening\"");
+        config.discord_activity_type = DiscordActivityType::Listening;
+    }
+    config
+}
+
+/// Discord only links streams on Twitch and YouTube.
+fn is_streaming_url(url: &str) -> bool {
+    let Some(rest) = url.strip_prefix("https://") else {
+        return false;
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.strip_prefix("www.").unwrap_or(authority);
+    // A channel or video, not just the site.
+    let has_path = rest[authority.len()..]
+        .strip_prefix('/')
+        .is_some_and(|path| !path.is_empty() && !path.starts_with(['/', '?', '#']));
+    matches!(host, "twitch.tv" | "youtube.com" | "youtu.be") && has_path
+}
+
+/// Sets a single key in `config.toml`, keeping the rest of the file,
+/// comments included, as the user wrote it. A dotted key such as
+/// `"api.token"` goes into that table, which is created when missing.
+pub fn store_value(key: &str, value: impl Into<toml_edit::Value>) -> io::Result<()> {
+    let (path, contents) = updated_config(key, value)?;
+    fs::write(path, contents)
+}
+
+/// The config file's path and its contents with `key` set to `value`.
+fn updated_config(key: &str, value: impl Into<toml_edit::Value>) -> io::Result<(PathBuf, String)> {
+    let path = config_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory available"))?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    let mut document: toml_edit::Document = contents
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let (tables, key) = key.rsplit_once('.').unwrap_or(("", key));
+    let mut table = document.as_table_mut();
+    for name in tables.split('.').filter(|name| !name.is_empty()) {
+        let item = table.entry(name).or_insert_with(toml_edit::table);
+        table = item.as_table_mut().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a table", name))
+        })?;
+    }
+    table[key] = toml_edit::value(value);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok((path, document.to_string()))
+}
+
+/// Like [`store_value`], for values such as session keys that other local
+/// users must not read. On Unix the file is limited to the owner; on Windows
+/// the per-user config directory is already private.
+pub fn store_secret(key: &str, value: &str) -> io::Result<()> {
+    let (path, contents) = updated_config(key, value)?;
+    write_private(&path, &contents)
+}
+
+/// Writes through a temporary file that only the owner can read from the
+/// moment it exists, then moves it over `path`. Setting the permissions
+/// after writing would leave the secret readable in between, and readable
+/// for good if that failed.
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    let temp_path = path.with_extension("toml.tmp");
+    // A leftover file would keep its permissions.
+    match fs::remove_file(&temp_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn secrets_replace_a_readable_file_with_a_private_one() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("ytune-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE_NAME);
+        fs::write(&path, "theme = \"dark\"\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        // Left behind by an earlier write that was cut short.
+        fs::write(path.with_extension("toml.tmp"), "").unwrap();
+        fs::set_permissions(path.with_extension("toml.tmp"), fs::Permissions::from_mode(0o666)).unwrap();
+
+        write_private(&path, "lastfm_session_key = \"secret\"\n").unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "lastfm_session_key = \"secret\"\n");
+        assert!(!path.with_extension("toml.tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn streaming_urls_need_a_channel_or_video() {
+        assert!(is_streaming_url("https://www.twitch.tv/someone"));
+        assert!(is_streaming_url("https://twitch.tv/someone"));
+        assert!(is_streaming_url("https://youtu.be/dQw4w9WgXcQ"));
+        assert!(is_streaming_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
+
+        assert!(!is_streaming_url("https://www.twitch.tv/"));
+        assert!(!is_streaming_url("https://www.twitch.tv"));
+        assert!(!is_streaming_url("https://www.youtube.com/?v=x"));
+        assert!(!is_streaming_url("https://twitch.tv#someone"));
+        assert!(!is_streaming_url("http://twitch.tv/someone"));
+        assert!(!is_streaming_url("https://nottwitch.tv/someone"));
+    }
+}
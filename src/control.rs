@@ -0,0 +1,58 @@
+use serde_json::{json, Value};
+
+/// Playback actions other parts of ytune can trigger in the page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerCommand {
+    Play,
+    Pause,
+    PlayPause,
+    Next,
+    Previous,
+    /// Likes the current track; does nothing when it is already liked.
+    Like,
+    /// Dislikes the current track; does nothing when it is already disliked.
+    Dislike,
+    /// Re-reads the player bar now rather than on the next tick.
+    Refresh,
+    /// Moves the playback position, in seconds.
+    Seek(Adjustment),
+    /// Sets the player volume, from 0 to 100; the page clamps it.
+    Volume(Adjustment),
+}
+
+/// A new value, or a change to the current one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Adjustment {
+    To(i64),
+    By(i64),
+}
+
+impl Adjustment {
+    fn to_json(self) -> Value {
+        match self {
+            Adjustment::To(value) => json!({ "to": value }),
+            Adjustment::By(delta) => json!({ "by": delta }),
+        }
+    }
+}
+
+impl PlayerCommand {
+    /// Call into the bridge functions defined by the page script.
+    pub fn script(self) -> String {
+        let (name, argument) = match self {
+            PlayerCommand::Play => ("play", Value::Null),
+            PlayerCommand::Pause => ("pause", Value::Null),
+            PlayerCommand::PlayPause => ("playPause", Value::Null),
+            PlayerCommand::Next => ("next", Value::Null),
+            PlayerCommand::Previous => ("previous", Value::Null),
+            PlayerCommand::Like => ("like", Value::Null),
+            PlayerCommand::Dislike => ("dislike", Value::Null),
+            PlayerCommand::Seek(adjustment) => ("seek", adjustment.to_json()),
+            PlayerCommand::Volume(adjustment) => ("volume", adjustment.to_json()),
+            PlayerCommand::Refresh => {
+                return "window.__ytuneForceScrape && window.__ytuneForceScrape();".to_string()
+            }
+        };
+        format!("window.__ytuneCommand && window.__ytuneCommand('{}', {});", name, argument)
+    }
+}
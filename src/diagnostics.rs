@@ -0,0 +1,73 @@
+use std::{fmt::Write as _, fs, io, path::PathBuf};
+
+use crate::{config, logging, status::AppStatus, LastTrackInfo};
+
+const REPORT_FILE_NAME: &str = "diagnostics.txt";
+const REPORT_LOG_LINES: usize = 30;
+
+/// Builds a plain-text report users can paste into bug reports.
+pub fn render(status: &AppStatus, track: &LastTrackInfo, discord_status: &str) -> String {
+    let mut report = String::new();
+
+    let _ = writeln!(report, "ytune {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "OS: {} ({})", std::env::consts::OS, std::env::consts::ARCH);
+    let _ = writeln!(report, "Discord: {}", discord_status);
+    let _ = writeln!(
+        report,
+        "Account tier: {}",
+        status
+            .account_tier
+            .map(|tier| format!("{:?}", tier))
+            .unwrap_or_else(|| "unknown".to_string())
+    );
+    let _ = writeln!(
+        report,
+        "Last scrape: {}",
+        status
+            .last_scrape_at
+            .and_then(|at| chrono::DateTime::from_timestamp(at as i64, 0))
+            .map(|at| at.with_timezone(&chrono::Local).to_rfc2822())
+            .unwrap_or_else(|| "never".to_string())
+    );
+    let _ = writeln!(
+        report,
+        "Pending scrobbles: {}",
+        status
+            .scrobbles_pending
+            .map(|count| count.to_string())
+            .unwrap_or_else(|| "Last.fm not connected".to_string())
+    );
+    let _ = writeln!(
+        report,
+        "Last love sync: {}",
+        status.last_love_sync.as_deref().unwrap_or("none")
+    );
+    let _ = writeln!(report, "Local API: {}", status.api.as_deref().unwrap_or("disabled"));
+    let _ = writeln!(
+        report,
+        "Last track: {} — {}{}",
+        track.title.as_deref().unwrap_or("-"),
+        track.artist.as_deref().unwrap_or("-"),
+        if track.is_playing { "" } else { " (paused)" }
+    );
+
+    let _ = writeln!(report, "\nRecent log:");
+    for line in logging::recent_lines(REPORT_LOG_LINES) {
+        let _ = writeln!(report, "{}", line);
+    }
+
+    report
+}
+
+/// Writes the report to the data directory and opens it in the default
+/// text editor.
+pub fn write_and_open(report: &str) -> io::Result<PathBuf> {
+    let dir = config::data_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No data directory available"))?;
+    fs::create_dir_all(&dir)?;
+
+    let path = dir.join(REPORT_FILE_NAME);
+    fs::write(&path, report)?;
+    open::that(&path)?;
+    Ok(path)
+}
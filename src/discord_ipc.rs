@@ -16,32 +16,115 @@ use uuid::Uuid;
 const PIPE_PATH: &str = r"\\.\pipe\discord-ipc-0";
 
 #[cfg(target_os = "windows")]
-pub fn connect() -> std::io::Result<File> {
-    for attempt in 1..=10 {
+const OP_FRAME: u32 = 1;
+#[cfg(target_os = "windows")]
+const OP_CLOSE: u32 = 2;
+#[cfg(target_os = "windows")]
+const OP_PING: u32 = 3;
+#[cfg(target_os = "windows")]
+const OP_PONG: u32 = 4;
+
+/// Where the connection to Discord stands. Every change goes through
+/// `transition`, so the presence worker, reconnects and the initial connect
+/// agree on who may open the pipe.
+#[cfg(target_os = "windows")]
+#[derive(Debug, Default)]
+pub enum ConnectionState {
+    /// Not connected and nobody trying: before the first attempt, or after
+    /// the pipe broke and before the reconnect starts.
+    #[default]
+    Disconnected,
+    /// An attempt is under way; others wait for its outcome.
+    Connecting,
+    /// The handshaken pipe, with the pid it was opened for.
+    Connected(File, u32),
+    /// The last attempt failed; the next presence update tries again.
+    Failed,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug)]
+pub enum ConnectionEvent {
+    Attempt,
+    Established(File, u32),
+    AttemptFailed,
+    Broken,
+}
+
+#[cfg(target_os = "windows")]
+impl ConnectionState {
+    /// Applies `event`, returning false and leaving the state alone when it
+    /// doesn't apply, e.g. an `Attempt` while one is already under way.
+    pub fn transition(&mut self, event: ConnectionEvent) -> bool {
+        let next = match (std::mem::take(self), event) {
+            (ConnectionState::Disconnected | ConnectionState::Failed, ConnectionEvent::Attempt) => {
+                ConnectionState::Connecting
+            }
+            (ConnectionState::Connecting, ConnectionEvent::Established(file, pid)) => {
+                ConnectionState::Connected(file, pid)
+            }
+            (ConnectionState::Connecting, ConnectionEvent::AttemptFailed) => ConnectionState::Failed,
+            (ConnectionState::Connected(..), ConnectionEvent::Broken) => ConnectionState::Disconnected,
+            (state, event) => {
+                log::debug!("Ignoring Discord connection event {:?} while {:?}", event, state);
+                *self = state;
+                return false;
+            }
+        };
+        *self = next;
+        true
+    }
+
+    /// Whether nothing is connected and no attempt is under way.
+    pub fn can_attempt(&self) -> bool {
+        matches!(self, ConnectionState::Disconnected | ConnectionState::Failed)
+    }
+}
+
+/// How long `connect` keeps trying before deciding Discord isn't running.
+#[cfg(target_os = "windows")]
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectRetry {
+    attempts: u32,
+    delay: Duration,
+}
+
+#[cfg(target_os = "windows")]
+impl ConnectRetry {
+    /// Spreads `timeout_ms` evenly over `attempts` tries.
+    pub fn new(timeout_ms: u64, attempts: u32) -> Self {
+        let attempts = attempts.max(1);
+        ConnectRetry {
+            attempts,
+            delay: Duration::from_millis(timeout_ms / u64::from(attempts)),
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn connect(retry: ConnectRetry) -> std::io::Result<File> {
+    let mut attempt = 1;
+    loop {
         match OpenOptions::new().read(true).write(true).open(PIPE_PATH) {
             Ok(file) => {
-                println!("Connected to Discord IPC pipe.");
+                log::info!("Connected to Discord IPC pipe.");
                 return Ok(file);
             }
+            // Discord not running is normal, so giving up is left to the caller to report.
+            Err(e) if attempt >= retry.attempts => {
+                log::debug!("Failed to connect after {} attempts: {:?}", attempt, e);
+                return Err(e);
+            }
             Err(e) => {
-                if attempt == 10 {
-
-                    eprintln!("Failed to connect after 10 attempts: {:?}", e);
-                    return Err(e);
-                }
-                eprintln!(
+                log::debug!(
                     "Attempt {} failed to connect to Discord IPC: {:?}. Retrying...",
                     attempt, e
                 );
-                thread::sleep(Duration::from_millis(500));
+                thread::sleep(retry.delay);
+                attempt += 1;
             }
         }
     }
-
-    Err(std::io::Error::new(
-        std::io::ErrorKind::NotFound,
-        "Failed to connect after retries",
-    ))
 }
 
 #[cfg(target_os = "windows")]
@@ -93,12 +176,35 @@ pub fn send_handshake(file: &mut File, client_id: &str) -> std::io::Result<()> {
     write_message(file, 0, &handshake_str) 
 }
 
+/// Describes the error Discord refused a handshake with, as carried by a
+/// close frame or an ERROR event: `{"code": 4000, "message": "Invalid Client ID"}`.
+#[cfg(target_os = "windows")]
+pub fn handshake_error(error: &Value) -> String {
+    let code = error.get("code").and_then(Value::as_u64);
+    let message = error.get("message").and_then(Value::as_str).unwrap_or("no message");
+    let reason = match code {
+        Some(4000) => "the client ID is invalid",
+        Some(4001) => "the origin is invalid",
+        Some(4002) => "rate limited, try again later",
+        Some(4003) => "the token was revoked",
+        Some(4004) => "the RPC version is unsupported",
+        Some(4005) => "the encoding is invalid",
+        _ => "unknown reason",
+    };
+    match code {
+        Some(code) => format!("Discord refused the handshake, {} ({}: {})", reason, code, message),
+        None => format!("Discord refused the handshake, {}: {}", reason, message),
+    }
+}
+
+/// Sends a SET_ACTIVITY command and returns its nonce, which the matching
+/// response will carry.
 #[cfg(target_os = "windows")]
 pub fn set_activity(
     file: &mut File,
     pid: u32,
     activity_json_str: &str,
-) -> std::io::Result<()> {
+) -> std::io::Result<String> {
 
     let activity_value: Value = serde_json::from_str(activity_json_str).map_err(|e| {
         std::io::Error::new(
@@ -119,5 +225,40 @@ pub fn set_activity(
     });
 
     let payload_string = serde_json::to_string(&command_payload)?;
-    write_message(file, 1, &payload_string) 
+    write_message(file, OP_FRAME, &payload_string)?;
+    Ok(nonce)
+}
+
+/// Reads frames until the response carrying `nonce` arrives. Discord may push
+/// events and pings at any time, so those are answered or skipped on the way
+/// instead of being mistaken for the response.
+#[cfg(target_os = "windows")]
+pub fn read_response(file: &mut File, nonce: &str) -> std::io::Result<Value> {
+    loop {
+        let (opcode, payload) = read_message(file)?;
+        match opcode {
+            OP_FRAME => {
+                let frame: Value = match serde_json::from_str(&payload) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        log::debug!("Skipping unparseable Discord frame: {}", e);
+                        continue;
+                    }
+                };
+                if frame.get("nonce").and_then(|v| v.as_str()) == Some(nonce) {
+                    return Ok(frame);
+                }
+                log::debug!("Skipping Discord event: {}", payload);
+            }
+            OP_PING => write_message(file, OP_PONG, &payload)?,
+            OP_PONG => {}
+            OP_CLOSE => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    format!("Discord closed the connection: {}", payload),
+                ))
+            }
+            other => log::debug!("Ignoring Discord frame with opcode {}", other),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,185 @@
+use std::{
+    io::{self, Write},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Mutex,
+    },
+    thread,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{
+    api::types::{NowPlaying, StreamEvent, StreamMessage},
+    events::{self, TrackEvent},
+};
+
+/// Appended to the client's key before hashing, per RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Messages a client may fall behind by before it is dropped, so one stalled
+/// overlay never holds up the others or the event bus.
+const CLIENT_BACKLOG: usize = 64;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+/// Close code telling clients the server is going away.
+const CLOSE_GOING_AWAY: u16 = 1001;
+
+/// Fans track events out to the WebSocket clients of `/api/events`.
+#[derive(Default)]
+pub struct EventStream {
+    clients: Mutex<Vec<SyncSender<String>>>,
+}
+
+impl EventStream {
+    /// Forwards events until the event bus is closed, then closes every
+    /// client's connection.
+    pub fn run(&self, events: Receiver<TrackEvent>) {
+        for event in events {
+            let message = StreamMessage::new(event.kind.into(), event.timestamp, &event.track);
+            let Ok(message) = serde_json::to_string(&message) else {
+                continue;
+            };
+            self.clients
+                .lock()
+                .unwrap()
+                .retain(|client| client.try_send(message.clone()).is_ok());
+        }
+        // Dropping the senders ends the writers, which say goodbye first.
+        self.clients.lock().unwrap().clear();
+    }
+
+    /// Streams to an upgraded connection from its own thread, starting with
+    /// `snapshot`, the current track in the `/api/now-playing` format.
+    pub fn add_client<S: Write + Send + 'static>(&self, mut stream: S, snapshot: NowPlaying) {
+        let (sender, receiver) = mpsc::sync_channel(CLIENT_BACKLOG);
+        let snapshot = StreamMessage::new(StreamEvent::Snapshot, events::unix_now(), &snapshot);
+        if let Ok(snapshot) = serde_json::to_string(&snapshot) {
+            let _ = sender.try_send(snapshot);
+        }
+        self.clients.lock().unwrap().push(sender);
+
+        thread::spawn(move || {
+            for message in receiver {
+                if let Err(e) = write_frame(&mut stream, OPCODE_TEXT, message.as_bytes()) {
+                    log::debug!("Event stream client disconnected: {}", e);
+                    return;
+                }
+            }
+            let _ = write_frame(&mut stream, OPCODE_CLOSE, &CLOSE_GOING_AWAY.to_be_bytes());
+        });
+    }
+}
+
+/// The `Sec-WebSocket-Accept` answer to a client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    STANDARD.encode(sha1(format!("{}{}", key.trim(), WEBSOCKET_GUID).as_bytes()))
+}
+
+/// Writes one unfragmented, unmasked frame, as servers send them.
+fn write_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+/// SHA-1 is only needed for the handshake, where it is mandated; it is not
+/// used for anything that relies on it being secure.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let next = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = next;
+        }
+        for (value, added) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(digest: [u8; 20]) -> String {
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn accept_key_matches_rfc_6455() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(accept_key(" dGhlIHNhbXBsZSBub25jZQ==\r"), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn sha1_matches_known_digests() {
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // Two blocks once padded.
+        assert_eq!(
+            hex(sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn frames_use_the_right_length_encoding() {
+        let mut frame = Vec::new();
+        write_frame(&mut frame, OPCODE_TEXT, b"hi").unwrap();
+        assert_eq!(frame, [0x81, 2, b'h', b'i']);
+
+        let mut frame = Vec::new();
+        write_frame(&mut frame, OPCODE_TEXT, &[0; 300]).unwrap();
+        assert_eq!(frame[..4], [0x81, 126, 0x01, 0x2C]);
+        assert_eq!(frame.len(), 4 + 300);
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::LastTrackInfo;
+
+/// How close to the start a jump back has to land to count as a restart.
+const REPLAY_START_WINDOW_SEC: u64 = 5;
+/// Fraction of the duration after which a restart begins a new play.
+const THRESHOLD_FRACTION: f64 = 0.5;
+/// Fraction of the duration after which a play is considered finished.
+const COMPLETED_FRACTION: f64 = 0.9;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackEventKind {
+    Started,
+    Paused,
+    Resumed,
+    Position,
+    Stopped,
+}
+
+#[derive(Clone, Debug)]
+pub struct TrackEvent {
+    pub kind: TrackEventKind,
+    pub track: LastTrackInfo,
+    /// Unix time in seconds at which the event was produced.
+    pub timestamp: u64,
+}
+
+impl TrackEvent {
+    fn new(kind: TrackEventKind, track: &LastTrackInfo) -> Self {
+        TrackEvent {
+            kind,
+            track: track.clone(),
+            timestamp: unix_now(),
+        }
+    }
+
+    /// Share of the track that had been played, between 0.0 and 1.0, when
+    /// both the position and duration are known.
+    pub fn completion(&self) -> Option<f64> {
+        match (self.track.position_sec, self.track.duration_sec) {
+            (Some(position), Some(duration)) if duration > 0 => {
+                Some((position as f64 / duration as f64).min(1.0))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Fan-out of track events to any number of consumer threads.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<TrackEvent>>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> Receiver<TrackEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: TrackEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // Consumers that went away are dropped on the next publish.
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /// Disconnects every consumer so their receive loops end.
+    pub fn close(&self) {
+        self.subscribers.lock().unwrap().clear();
+    }
+}
+
+/// How far the current play has got. Repeat-one looks like one endless
+/// track, so a jump back to the start only begins a new play once the
+/// threshold was passed, and then only once per cycle: restarting early or
+/// scrubbing around within the track keeps the same play.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum PlayPhase {
+    #[default]
+    Started,
+    ThresholdReached,
+    Completed,
+}
+
+/// Turns the periodic scrape results into discrete playback events.
+#[derive(Default)]
+pub struct PlaybackTracker {
+    /// Last scrape of the track being played, including its position.
+    current: Option<LastTrackInfo>,
+    phase: PlayPhase,
+}
+
+impl PlaybackTracker {
+    pub fn update(&mut self, track: &LastTrackInfo) -> Vec<TrackEvent> {
+        let mut events = Vec::new();
+
+        let same_track = self
+            .current
+            .as_ref()
+            .is_some_and(|current| is_same_track(current, track));
+
+        if same_track && self.is_restart(track) {
+            events.extend(self.stop());
+        }
+
+        match self.current.as_ref() {
+            Some(current) if same_track => {
+                if track.is_playing != current.is_playing {
+                    let kind = if track.is_playing {
+                        TrackEventKind::Resumed
+                    } else {
+                        TrackEventKind::Paused
+                    };
+                    events.push(TrackEvent::new(kind, track));
+                } else if track.is_playing || track.liked != current.liked {
+                    // Also sent while paused when the like status changes.
+                    events.push(TrackEvent::new(TrackEventKind::Position, track));
+                }
+            }
+            _ => {
+                events.extend(self.stop());
+                self.phase = PlayPhase::Started;
+                events.push(TrackEvent::new(TrackEventKind::Started, track));
+                if !track.is_playing {
+                    events.push(TrackEvent::new(TrackEventKind::Paused, track));
+                }
+            }
+        }
+
+        self.advance(track);
+        self.current = Some(track.clone());
+        events
+    }
+
+    /// Ends the current play, if any, e.g. when the app is closing. The event
+    /// carries the last position seen.
+    pub fn stop(&mut self) -> Option<TrackEvent> {
+        let track = self.current.take()?;
+        Some(TrackEvent::new(TrackEventKind::Stopped, &track))
+    }
+
+    /// Moves the play forward once its position passes the threshold or the
+    /// end. Scrubbing back never moves it backwards.
+    fn advance(&mut self, track: &LastTrackInfo) {
+        let progress = match (track.position_sec, track.duration_sec) {
+            (Some(position), Some(duration)) if duration > 0 => position as f64 / duration as f64,
+            _ => return,
+        };
+        if progress >= COMPLETED_FRACTION {
+            self.phase = PlayPhase::Completed;
+        } else if progress >= THRESHOLD_FRACTION && self.phase == PlayPhase::Started {
+            self.phase = PlayPhase::ThresholdReached;
+        }
+    }
+
+    /// A jump back to the very start, after the threshold, means repeat-one
+    /// or the user started the track over.
+    fn is_restart(&self, track: &LastTrackInfo) -> bool {
+        let last_position = self.current.as_ref().and_then(|current| current.position_sec);
+        match (last_position, track.position_sec) {
+            (Some(last), Some(position)) => {
+                self.phase != PlayPhase::Started
+                    && position <= REPLAY_START_WINDOW_SEC
+                    && position < last
+            }
+            _ => false,
+        }
+    }
+}
+
+fn is_same_track(a: &LastTrackInfo, b: &LastTrackInfo) -> bool {
+    match (&a.video_id, &b.video_id) {
+        (Some(a_id), Some(b_id)) => a_id == b_id,
+        _ => a.title == b.title && a.artist == b.artist,
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrape(position_sec: u64, is_playing: bool) -> LastTrackInfo {
+        LastTrackInfo {
+            title: Some("Song".to_string()),
+            artist: Some("Artist".to_string()),
+            video_id: Some("abcdefghijk".to_string()),
+            duration_sec: Some(200),
+            position_sec: Some(position_sec),
+            is_playing,
+            ..LastTrackInfo::default()
+        }
+    }
+
+    fn kinds(events: Vec<TrackEvent>) -> Vec<TrackEventKind> {
+        events.into_iter().map(|event| event.kind).collect()
+    }
+
+    #[test]
+    fn repeat_one_restart_after_the_threshold_starts_a_new_play() {
+        let mut tracker = PlaybackTracker::default();
+        assert_eq!(kinds(tracker.update(&scrape(0, true))), [TrackEventKind::Started]);
+        assert_eq!(kinds(tracker.update(&scrape(150, true))), [TrackEventKind::Position]);
+        assert_eq!(
+            kinds(tracker.update(&scrape(1, true))),
+            [TrackEventKind::Stopped, TrackEventKind::Started]
+        );
+        // The new play hasn't passed its threshold, so going back again keeps it.
+        assert_eq!(kinds(tracker.update(&scrape(0, true))), [TrackEventKind::Position]);
+    }
+
+    #[test]
+    fn restarting_before_the_threshold_keeps_the_play() {
+        let mut tracker = PlaybackTracker::default();
+        tracker.update(&scrape(0, true));
+        tracker.update(&scrape(60, true));
+        assert_eq!(kinds(tracker.update(&scrape(2, true))), [TrackEventKind::Position]);
+    }
+
+    #[test]
+    fn scrubbing_back_past_the_threshold_keeps_the_play() {
+        let mut tracker = PlaybackTracker::default();
+        tracker.update(&scrape(0, true));
+        tracker.update(&scrape(120, true));
+        // Back, but not to the start.
+        assert_eq!(kinds(tracker.update(&scrape(40, true))), [TrackEventKind::Position]);
+        assert_eq!(tracker.phase, PlayPhase::ThresholdReached);
+        // Going back to the start after that is still a restart.
+        assert_eq!(
+            kinds(tracker.update(&scrape(0, true))),
+            [TrackEventKind::Stopped, TrackEventKind::Started]
+        );
+    }
+
+    #[test]
+    fn pause_and_resume_keep_the_play() {
+        let mut tracker = PlaybackTracker::default();
+        tracker.update(&scrape(0, true));
+        assert_eq!(kinds(tracker.update(&scrape(30, false))), [TrackEventKind::Paused]);
+        // Nothing changes while paused.
+        assert!(tracker.update(&scrape(30, false)).is_empty());
+        assert_eq!(kinds(tracker.update(&scrape(30, true))), [TrackEventKind::Resumed]);
+        assert_eq!(kinds(tracker.update(&scrape(35, true))), [TrackEventKind::Position]);
+    }
+
+    #[test]
+    fn another_track_stops_the_current_play() {
+        let mut tracker = PlaybackTracker::default();
+        tracker.update(&scrape(100, true));
+        let next = LastTrackInfo {
+            video_id: Some("lmnopqrstuv".to_string()),
+            ..scrape(0, false)
+        };
+        assert_eq!(
+            kinds(tracker.update(&next)),
+            [TrackEventKind::Stopped, TrackEventKind::Started, TrackEventKind::Paused]
+        );
+        assert_eq!(tracker.stop().map(|event| event.kind), Some(TrackEventKind::Stopped));
+        assert!(tracker.stop().is_none());
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+
+use crate::{config, history::PlayRecord};
+
+const CSV_HEADER: &str =
+    "played_at_local,played_at_utc,title,artist,album,video_id,duration_sec,completion";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    /// Picks the format from the file extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// `ytune-history-<date>.csv` in the documents folder, or the data
+/// directory when there is none.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = dirs::document_dir().or_else(config::data_dir)?;
+    Some(dir.join(format!("ytune-history-{}.csv", Local::now().format("%Y-%m-%d"))))
+}
+
+pub fn export_history(records: &[PlayRecord], path: &Path, format: ExportFormat) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Csv => write_csv(records, &mut writer)?,
+        ExportFormat::Json => serde_json::to_writer_pretty(&mut writer, records)?,
+    }
+    writer.flush()
+}
+
+fn write_csv<W: Write>(records: &[PlayRecord], writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for record in records {
+        let played_at = DateTime::<Utc>::from_timestamp(record.played_at as i64, 0).unwrap_or_default();
+        let fields = [
+            played_at
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Secs, false),
+            played_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            csv_field(record.title.as_deref().unwrap_or("")),
+            csv_field(record.artist.as_deref().unwrap_or("")),
+            csv_field(record.album.as_deref().unwrap_or("")),
+            csv_field(record.video_id.as_deref().unwrap_or("")),
+            record.duration_sec.map(|d| d.to_string()).unwrap_or_default(),
+            record
+                .completion
+                .map(|c| format!("{:.3}", c))
+                .unwrap_or_default(),
+        ];
+        writeln!(writer, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// Quotes a field per RFC 4180 when it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads back what `write_csv` wrote, per RFC 4180.
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => row.push(std::mem::take(&mut field)),
+                '\n' if !quoted => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                c => field.push(c),
+            }
+        }
+        assert!(!quoted, "unterminated quoted field");
+        assert!(field.is_empty() && row.is_empty(), "missing final line break");
+        rows
+    }
+
+    fn record(title: &str, artist: &str, album: Option<&str>) -> PlayRecord {
+        PlayRecord {
+            version: 1,
+            video_id: Some("dQw4w9WgXcQ".to_string()),
+            title: Some(title.to_string()),
+            artist: Some(artist.to_string()),
+            album: album.map(str::to_string),
+            duration_sec: Some(213),
+            played_at: 1_700_000_000,
+            completion: Some(0.5),
+        }
+    }
+
+    #[test]
+    fn nasty_titles_round_trip_through_csv() {
+        let records = [
+            record("Hello, World", "Artist", Some("Album")),
+            record("Say \"Hi\"", "\"Quoted\" Artist", None),
+            record("Line one\nLine two", "Artist, The", Some("A\r\nB")),
+            record("\"\"", ",", Some("\"")),
+            record(" padded ", "Ünïcödé ♪", Some("")),
+            record("-Intro-", "", None),
+        ];
+        let mut output = Vec::new();
+        write_csv(&records, &mut output).unwrap();
+
+        let rows = parse_csv(&String::from_utf8(output).unwrap());
+        assert_eq!(rows[0].join(","), CSV_HEADER);
+        assert_eq!(rows.len(), records.len() + 1);
+        for (row, record) in rows[1..].iter().zip(&records) {
+            assert_eq!(row.len(), CSV_HEADER.split(',').count());
+            assert_eq!(row[2], record.title.as_deref().unwrap());
+            assert_eq!(row[3], record.artist.as_deref().unwrap());
+            assert_eq!(row[4], record.album.as_deref().unwrap_or(""));
+            assert_eq!(row[5], "dQw4w9WgXcQ");
+            assert_eq!(row[6], "213");
+            assert_eq!(row[7], "0.500");
+        }
+    }
+
+    #[test]
+    fn plain_fields_are_left_unquoted() {
+        assert_eq!(csv_field("Plain title"), "Plain title");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(ExportFormat::from_path(Path::new("plays.CSV")), Some(ExportFormat::Csv));
+        assert_eq!(ExportFormat::from_path(Path::new("plays.json")), Some(ExportFormat::Json));
+        assert_eq!(ExportFormat::from_path(Path::new("plays.txt")), None);
+        assert_eq!(ExportFormat::from_path(Path::new("plays")), None);
+    }
+}
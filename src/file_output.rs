@@ -0,0 +1,154 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::Receiver,
+};
+
+use crate::{
+    api::types::NowPlaying,
+    art_cache,
+    config::{self, FileOutputConfig},
+    events::{TrackEvent, TrackEventKind},
+    template, youtube_url, LastTrackInfo,
+};
+
+const CURRENT_TRACK_FILE: &str = "current_track.json";
+
+/// Keeps a text file, and optionally an image next to it, in sync with the
+/// current track for OBS text and image sources. Returns when the event bus
+/// is closed. The image is fetched at `art_size` pixels where the CDN allows.
+pub fn run_writer(config: FileOutputConfig, path: PathBuf, art_size: u32, events: Receiver<TrackEvent>) {
+    let mut written_art_path: Option<PathBuf> = None;
+    let agent = ureq::AgentBuilder::new()
+        .timeout(art_cache::FETCH_TIMEOUT)
+        .build();
+    let mut written_text: Option<String> = None;
+    let mut written_art_url: Option<String> = None;
+
+    for event in events {
+        let text = match event.kind {
+            TrackEventKind::Started | TrackEventKind::Resumed | TrackEventKind::Position
+                if event.track.is_playing =>
+            {
+                template::render(&config.template, &event.track)
+            }
+            TrackEventKind::Started | TrackEventKind::Resumed | TrackEventKind::Position => continue,
+            TrackEventKind::Paused | TrackEventKind::Stopped => config.idle_text.clone(),
+        };
+
+        if written_text.as_deref() != Some(text.as_str()) {
+            match write_atomically(&path, text.as_bytes()) {
+                Ok(()) => written_text = Some(text),
+                // Another program may hold the file; the next event tries again.
+                Err(e) => log::warn!("Failed to write {}: {}", path.display(), e),
+            }
+        }
+
+        if let Some(url) = event.track.album_art.as_deref().filter(|_| config.save_art) {
+            let resized = youtube_url::resize_youtube_art_url(url, art_size);
+            let url = resized.as_str();
+            if written_art_url.as_deref() != Some(url) {
+                // Remembered even on failure so a broken URL isn't retried every tick.
+                written_art_url = Some(url.to_string());
+                let result = art_cache::fetch(&agent, url).and_then(|art| {
+                    let art_path = path.with_extension(art_extension(&art.content_type));
+                    write_atomically(&art_path, &art.bytes)
+                        .map(|()| art_path)
+                        .map_err(|e| e.to_string())
+                });
+                match result {
+                    Ok(art_path) => {
+                        // Art of another format would otherwise be left behind.
+                        if let Some(old_path) = written_art_path.filter(|old_path| *old_path != art_path) {
+                            let _ = fs::remove_file(old_path);
+                        }
+                        written_art_path = Some(art_path);
+                    }
+                    Err(e) => log::warn!("Failed to save album art next to {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+}
+
+/// The file extension for art served as `content_type`, `jpg` when it is
+/// unknown.
+fn art_extension(content_type: &str) -> &'static str {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    match mime.to_ascii_lowercase().as_str() {
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        "image/avif" => "avif",
+        _ => "jpg",
+    }
+}
+
+/// Where the running ytune keeps the current track for `--print-track`:
+/// `$XDG_RUNTIME_DIR/ytune/current_track.json`, or the data directory where
+/// there is no runtime directory (Windows, macOS) or ytune runs portable or
+/// from `YTUNE_CONFIG_DIR`.
+pub fn current_track_path() -> Option<PathBuf> {
+    let runtime_dir = if config::has_own_dir() {
+        None
+    } else {
+        dirs::runtime_dir().map(|dir| dir.join("ytune"))
+    };
+    runtime_dir
+        .or_else(config::data_dir)
+        .map(|dir| dir.join(CURRENT_TRACK_FILE))
+}
+
+/// Keeps `path` holding the current track in the `/api/now-playing` format.
+/// Returns when the event bus is closed, removing the file so a ytune that
+/// has exited isn't taken for one still playing.
+pub fn run_track_writer(path: PathBuf, events: Receiver<TrackEvent>) {
+    let mut written: Option<String> = None;
+
+    for event in events {
+        let track = match event.kind {
+            TrackEventKind::Stopped => LastTrackInfo::default(),
+            _ => event.track,
+        };
+        let Ok(json) = serde_json::to_string(&NowPlaying::from(track)) else {
+            continue;
+        };
+        if written.as_deref() != Some(json.as_str()) {
+            match write_atomically(&path, json.as_bytes()) {
+                Ok(()) => written = Some(json),
+                Err(e) => log::warn!("Failed to write {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    if written.is_some() {
+        if let Err(e) = fs::remove_file(&path) {
+            log::debug!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// OBS polls the file, so it must never see a half-written one.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn art_extension_follows_the_content_type() {
+        assert_eq!(art_extension("image/jpeg"), "jpg");
+        assert_eq!(art_extension("image/png"), "png");
+        assert_eq!(art_extension("Image/WebP; charset=binary"), "webp");
+        assert_eq!(art_extension("application/octet-stream"), "jpg");
+        assert_eq!(art_extension(""), "jpg");
+    }
+}
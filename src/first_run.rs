@@ -0,0 +1,95 @@
+use crate::{autostart, config, lastfm};
+
+/// What the first-run questions settled that ytune acts on right away,
+/// rather than through the saved config.
+pub struct Answers {
+    /// Last.fm authorization is to start once the tray is up.
+    pub connect_lastfm: bool,
+}
+
+/// Asks the essentials on the first launch, when there is no `config.toml`
+/// yet, and saves the answers to it. Creating the file beforehand skips
+/// the questions. None when they weren't asked.
+pub fn run(config: &config::Config) -> Option<Answers> {
+    let path = config::config_path()?;
+    if path.exists() {
+        return None;
+    }
+
+    #[cfg(all(target_os = "windows", feature = "discord"))]
+    let discord = ask("Show what you're playing on your Discord profile (Rich Presence)?")?;
+    let lastfm = match lastfm::Credentials::from_config(config) {
+        Some(_) => ask("Scrobble your plays to Last.fm? Your browser opens to connect your account.")?,
+        None => false,
+    };
+    let start_with_system = if autostart::SUPPORTED {
+        Some(ask("Start ytune when you log in?")?)
+    } else {
+        None
+    };
+    let start_minimized = ask("Start ytune minimized to the tray?")?;
+
+    #[cfg(all(target_os = "windows", feature = "discord"))]
+    save("discord_enabled", discord);
+    if let Some(start_with_system) = start_with_system {
+        save("start_with_system", start_with_system);
+    }
+    save("start_minimized", start_minimized);
+    log::info!("Saved the first-run settings to {}", path.display());
+
+    Some(Answers { connect_lastfm: lastfm })
+}
+
+fn save(key: &str, value: bool) {
+    if let Err(e) = config::store_value(key, value) {
+        log::warn!("Failed to save {}: {}", key, e);
+    }
+}
+
+/// A Yes/No message box. None when none could be shown.
+#[cfg(target_os = "windows")]
+fn ask(question: &str) -> Option<bool> {
+    use windows::{
+        core::{w, HSTRING},
+        Win32::UI::WindowsAndMessaging::{MessageBoxW, IDYES, MB_ICONQUESTION, MB_YESNO},
+    };
+
+    let answer = unsafe { MessageBoxW(None, &HSTRING::from(question), w!("ytune setup"), MB_YESNO | MB_ICONQUESTION) };
+    Some(answer == IDYES)
+}
+
+/// A Yes/No dialog from zenity or kdialog on Linux, or AppleScript on
+/// macOS. None when none of them is available, and the questions are
+/// skipped.
+#[cfg(not(target_os = "windows"))]
+fn ask(question: &str) -> Option<bool> {
+    use std::process::Command;
+
+    if cfg!(target_os = "macos") {
+        let script = format!(
+            "display dialog \"{}\" with title \"ytune setup\" buttons {{\"No\", \"Yes\"}} default button \"Yes\"",
+            question.replace('"', "\\\"")
+        );
+        let output = Command::new("osascript").args(["-e", &script]).output().ok()?;
+        return Some(String::from_utf8_lossy(&output.stdout).contains("button returned:Yes"));
+    }
+
+    let dialogs: [(&str, [&str; 4]); 2] = [
+        ("zenity", ["--question", "--title=ytune setup", "--text", question]),
+        ("kdialog", ["--title", "ytune setup", "--yesno", question]),
+    ];
+    let status = dialogs.iter().find_map(|(program, args)| {
+        Command::new(program)
+            .args(args)
+            .status()
+            .map_err(|e| log::debug!("No {} for the first-run questions: {}", program, e))
+            .ok()
+    });
+    match status {
+        Some(status) => Some(status.success()),
+        None => {
+            log::info!("Skipping the first-run questions, neither zenity nor kdialog is installed");
+            None
+        }
+    }
+}
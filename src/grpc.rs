@@ -0,0 +1,141 @@
+#![cfg(feature = "grpc")]
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    pin::Pin,
+    sync::{mpsc::Receiver, Arc, Mutex},
+    thread,
+};
+
+use tokio::sync::broadcast;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+use tonic::{transport::Server, Request, Response, Status};
+use wry::application::event_loop::EventLoopProxy;
+
+use crate::{control::PlayerCommand, events::TrackEvent, LastTrackInfo, UserEvent};
+
+mod proto {
+    tonic::include_proto!("ytune");
+}
+
+use proto::{
+    ytune_service_server::{YtuneService, YtuneServiceServer},
+    CommandResult, Empty, TrackInfo,
+};
+
+/// Updates a streaming client may fall behind by before it misses some.
+const UPDATE_BUFFER: usize = 16;
+
+struct Service {
+    last_track: Arc<Mutex<LastTrackInfo>>,
+    proxy: Mutex<EventLoopProxy<UserEvent>>,
+    updates: broadcast::Sender<TrackInfo>,
+}
+
+impl Service {
+    fn send(&self, command: PlayerCommand) -> Response<CommandResult> {
+        let result = match self.proxy.lock().unwrap().send_event(UserEvent::PlayerCommand(command)) {
+            Ok(()) => CommandResult {
+                ok: true,
+                error: String::new(),
+            },
+            Err(_) => CommandResult {
+                ok: false,
+                error: "ytune is shutting down".to_string(),
+            },
+        };
+        Response::new(result)
+    }
+}
+
+#[tonic::async_trait]
+impl YtuneService for Service {
+    type StreamTrackUpdatesStream = Pin<Box<dyn Stream<Item = Result<TrackInfo, Status>> + Send>>;
+
+    async fn get_current_track(&self, _: Request<Empty>) -> Result<Response<TrackInfo>, Status> {
+        Ok(Response::new(track_info(&self.last_track.lock().unwrap())))
+    }
+
+    async fn play_pause(&self, _: Request<Empty>) -> Result<Response<CommandResult>, Status> {
+        Ok(self.send(PlayerCommand::PlayPause))
+    }
+
+    async fn next(&self, _: Request<Empty>) -> Result<Response<CommandResult>, Status> {
+        Ok(self.send(PlayerCommand::Next))
+    }
+
+    async fn previous(&self, _: Request<Empty>) -> Result<Response<CommandResult>, Status> {
+        Ok(self.send(PlayerCommand::Previous))
+    }
+
+    async fn stream_track_updates(
+        &self,
+        _: Request<Empty>,
+    ) -> Result<Response<Self::StreamTrackUpdatesStream>, Status> {
+        let updates = BroadcastStream::new(self.updates.subscribe()).filter_map(|update| match update {
+            Ok(track) => Some(Ok(track)),
+            // A slow client just skips what it couldn't keep up with.
+            Err(BroadcastStreamRecvError::Lagged(_)) => None,
+        });
+        Ok(Response::new(Box::pin(updates)))
+    }
+}
+
+/// Serves the gRPC API on localhost from its own Tokio runtime. Returns the
+/// thread feeding track events to streaming clients, which ends when the
+/// event bus is closed.
+pub fn start(
+    port: u16,
+    last_track: Arc<Mutex<LastTrackInfo>>,
+    proxy: EventLoopProxy<UserEvent>,
+    events: Receiver<TrackEvent>,
+) -> thread::JoinHandle<()> {
+    let (updates, _) = broadcast::channel(UPDATE_BUFFER);
+    let service = Service {
+        last_track,
+        proxy: Mutex::new(proxy),
+        updates: updates.clone(),
+    };
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+
+    thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                log::warn!("gRPC server disabled: {}", e);
+                return;
+            }
+        };
+        log::info!("gRPC server listening on {}", address);
+        let server = Server::builder()
+            .add_service(YtuneServiceServer::new(service))
+            .serve(address);
+        if let Err(e) = runtime.block_on(server) {
+            log::warn!("gRPC server stopped: {}", e);
+        }
+    });
+
+    thread::spawn(move || {
+        for event in events {
+            // Fails only when nobody is streaming, which is fine.
+            let _ = updates.send(track_info(&event.track));
+        }
+    })
+}
+
+fn track_info(track: &LastTrackInfo) -> TrackInfo {
+    TrackInfo {
+        title: track.title.clone(),
+        artist: track.artist.clone(),
+        album: track.album.clone(),
+        album_art: track.album_art.clone(),
+        video_id: track.video_id.clone(),
+        duration_sec: track.duration_sec,
+        position_sec: track.position_sec,
+        is_playing: track.is_playing,
+        is_video: track.is_video,
+    }
+}
@@ -0,0 +1,508 @@
+use rusqlite::{params, params_from_iter, Connection, Row};
+use serde::Serialize;
+use std::{path::Path, sync::mpsc::Receiver};
+
+use crate::events::{TrackEvent, TrackEventKind};
+
+/// Version written into every play record; bump when the meaning of a
+/// column changes so older rows can be told apart.
+pub const RECORD_VERSION: u32 = 1;
+const DATABASE_FILE_NAME: &str = "history.sqlite3";
+/// Most matches a history search returns.
+pub const SEARCH_LIMIT: usize = 50;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PlayRecord {
+    pub version: u32,
+    pub video_id: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration_sec: Option<u64>,
+    /// Unix time in seconds at which the play started.
+    pub played_at: u64,
+    /// Share of the track that was played, between 0.0 and 1.0.
+    pub completion: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Retention {
+    pub max_entries: Option<u32>,
+    pub max_age_days: Option<u32>,
+}
+
+pub struct History {
+    conn: Connection,
+    retention: Retention,
+}
+
+impl History {
+    pub fn open(data_dir: &Path, retention: Retention) -> rusqlite::Result<Self> {
+        if let Err(e) = std::fs::create_dir_all(data_dir) {
+            log::warn!("Failed to create {}: {}", data_dir.display(), e);
+        }
+        let conn = Connection::open(data_dir.join(DATABASE_FILE_NAME))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS plays (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                version INTEGER NOT NULL,
+                video_id TEXT,
+                title TEXT,
+                artist TEXT,
+                album TEXT,
+                duration_sec INTEGER,
+                played_at INTEGER NOT NULL,
+                completion REAL
+            );
+            CREATE INDEX IF NOT EXISTS plays_played_at ON plays (played_at);",
+        )?;
+
+        Ok(History { conn, retention })
+    }
+
+    pub fn insert(&self, record: &PlayRecord) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO plays (version, video_id, title, artist, album, duration_sec, played_at, completion)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                record.version,
+                record.video_id,
+                record.title,
+                record.artist,
+                record.album,
+                record.duration_sec,
+                record.played_at,
+                record.completion,
+            ],
+        )?;
+        self.prune()
+    }
+
+    /// Inserts plays from another source in one transaction, skipping ones
+    /// that have a play of the same video within `window_sec` seconds.
+    /// Returns how many went in and were kept, how many were duplicates, and
+    /// how many the retention limits dropped straight away.
+    pub fn import(&self, records: &[PlayRecord], window_sec: u64) -> rusqlite::Result<(usize, usize, usize)> {
+        let transaction = self.conn.unchecked_transaction()?;
+        let mut inserted = Vec::new();
+        let mut duplicates = 0;
+        {
+            let mut exists = transaction.prepare(
+                "SELECT EXISTS (SELECT 1 FROM plays WHERE video_id = ?1 AND played_at BETWEEN ?2 AND ?3)",
+            )?;
+            let mut insert = transaction.prepare(
+                "INSERT INTO plays (version, video_id, title, artist, album, duration_sec, played_at, completion)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for record in records {
+                let duplicate: bool = exists.query_row(
+                    params![
+                        record.video_id,
+                        record.played_at.saturating_sub(window_sec),
+                        record.played_at.saturating_add(window_sec),
+                    ],
+                    |row| row.get(0),
+                )?;
+                if duplicate {
+                    duplicates += 1;
+                    continue;
+                }
+                insert.execute(params![
+                    record.version,
+                    record.video_id,
+                    record.title,
+                    record.artist,
+                    record.album,
+                    record.duration_sec,
+                    record.played_at,
+                    record.completion,
+                ])?;
+                inserted.push(transaction.last_insert_rowid());
+            }
+        }
+        // Pruned before committing so only plays that stay are counted.
+        self.prune()?;
+        let mut kept = transaction.prepare("SELECT EXISTS (SELECT 1 FROM plays WHERE id = ?1)")?;
+        let mut imported = 0;
+        for id in &inserted {
+            if kept.query_row(params![id], |row| row.get::<_, bool>(0))? {
+                imported += 1;
+            }
+        }
+        drop(kept);
+        transaction.commit()?;
+        Ok((imported, duplicates, inserted.len() - imported))
+    }
+
+    /// Most recent plays first.
+    pub fn recent(&self, limit: usize) -> rusqlite::Result<Vec<PlayRecord>> {
+        let mut statement = self.conn.prepare(
+            "SELECT version, video_id, title, artist, album, duration_sec, played_at, completion
+             FROM plays ORDER BY played_at DESC, id DESC LIMIT ?1",
+        )?;
+        let rows = statement.query_map(params![limit as i64], read_record)?;
+        rows.collect()
+    }
+
+    /// Plays started within `[since, until)`, oldest first. Either bound may be
+    /// left open.
+    pub fn range(&self, since: Option<u64>, until: Option<u64>) -> rusqlite::Result<Vec<PlayRecord>> {
+        let mut statement = self.conn.prepare(
+            "SELECT version, video_id, title, artist, album, duration_sec, played_at, completion
+             FROM plays WHERE played_at >= ?1 AND played_at < ?2 ORDER BY played_at, id",
+        )?;
+        let since = since.unwrap_or(0) as i64;
+        let until = until.map(|until| until as i64).unwrap_or(i64::MAX);
+        let rows = statement.query_map(params![since, until], read_record)?;
+        rows.collect()
+    }
+
+    /// Most recent plays whose title, artist or album contain every word of
+    /// `query`, ignoring ASCII case; "punk daft" finds Daft Punk. SQLite stops
+    /// at `limit` rows, so large histories are never read in full. An empty
+    /// query matches nothing.
+    pub fn search(&self, query: &str, limit: usize) -> rusqlite::Result<Vec<PlayRecord>> {
+        let patterns: Vec<String> = query.split_whitespace().map(like_pattern).collect();
+        if patterns.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conditions = (1..=patterns.len())
+            .map(|n| {
+                format!(
+                    "(title LIKE ?{n} ESCAPE '\\' OR artist LIKE ?{n} ESCAPE '\\' OR album LIKE ?{n} ESCAPE '\\')"
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT version, video_id, title, artist, album, duration_sec, played_at, completion
+             FROM plays WHERE {} ORDER BY played_at DESC, id DESC LIMIT {}",
+            conditions, limit
+        ))?;
+        let rows = statement.query_map(params_from_iter(patterns), read_record)?;
+        rows.collect()
+    }
+
+    /// For aggregate queries that don't fit the record-oriented methods.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Deletes plays beyond the retention limits. Opening the history
+    /// doesn't, so reading it never loses plays.
+    pub fn prune(&self) -> rusqlite::Result<()> {
+        if let Some(days) = self.retention.max_age_days {
+            let cutoff = crate::events::unix_now().saturating_sub(u64::from(days) * 86_400);
+            self.conn
+                .execute("DELETE FROM plays WHERE played_at < ?1", params![cutoff])?;
+        }
+        if let Some(max_entries) = self.retention.max_entries {
+            self.conn.execute(
+                "DELETE FROM plays WHERE id NOT IN (
+                    SELECT id FROM plays ORDER BY played_at DESC, id DESC LIMIT ?1
+                )",
+                params![max_entries],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `%word%` with the LIKE wildcards in `word` escaped, so they match literally.
+fn like_pattern(word: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in word.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+fn read_record(row: &Row) -> rusqlite::Result<PlayRecord> {
+    Ok(PlayRecord {
+        version: row.get(0)?,
+        video_id: row.get(1)?,
+        title: row.get(2)?,
+        artist: row.get(3)?,
+        album: row.get(4)?,
+        duration_sec: row.get(5)?,
+        played_at: row.get(6)?,
+        completion: row.get(7)?,
+    })
+}
+
+/// Consumes track events and records a play whenever one ends. `on_change`
+/// runs at start and after every recorded play. Returns when the event bus
+/// is closed.
+pub fn run_recorder(history: History, events: Receiver<TrackEvent>, on_change: impl Fn(&History)) {
+    let mut started_at = None;
+    on_change(&history);
+
+    for event in events {
+        match event.kind {
+            TrackEventKind::Started => started_at = Some(event.timestamp),
+            TrackEventKind::Stopped => {
+                let Some(played_at) = started_at.take() else {
+                    continue;
+                };
+                // Nothing was actually heard, e.g. skipped while paused.
+                if event.track.position_sec.unwrap_or(0) == 0 {
+                    continue;
+                }
+
+                let record = PlayRecord {
+                    version: RECORD_VERSION,
+                    video_id: event.track.video_id.clone(),
+                    title: event.track.title.clone(),
+                    artist: event.track.artist.clone(),
+                    album: event.track.album.clone(),
+                    duration_sec: event.track.duration_sec,
+                    played_at,
+                    completion: event.completion(),
+                };
+                match history.insert(&record) {
+                    Ok(()) => on_change(&history),
+                    Err(e) => log::error!("Failed to record play history: {}", e),
+                }
+            }
+            TrackEventKind::Paused | TrackEventKind::Resumed | TrackEventKind::Position => {}
+        }
+    }
+}
+
+/// Prints the last `limit` plays for the `--history` command.
+pub fn print_recent(history: &History, limit: usize) -> rusqlite::Result<()> {
+    let records = history.recent(limit)?;
+    if records.is_empty() {
+        println!("No plays recorded yet.");
+        return Ok(());
+    }
+
+    for record in &records {
+        print_record(record);
+    }
+    Ok(())
+}
+
+/// Prints the plays matching `query` for the `--search-history` command.
+pub fn print_search(history: &History, query: &str) -> rusqlite::Result<()> {
+    let records = history.search(query, SEARCH_LIMIT)?;
+    if records.is_empty() {
+        println!("No plays match '{}'.", query);
+        return Ok(());
+    }
+
+    for record in &records {
+        print_record(record);
+    }
+    if records.len() == SEARCH_LIMIT {
+        println!("Showing the {} most recent matches; add words to narrow the search.", SEARCH_LIMIT);
+    }
+    Ok(())
+}
+
+fn print_record(record: &PlayRecord) {
+    let played_at = chrono::DateTime::from_timestamp(record.played_at as i64, 0)
+        .map(|time| {
+            time.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default();
+    let title = record.title.as_deref().unwrap_or("Unknown title");
+    let artist = record.artist.as_deref().unwrap_or("Unknown artist");
+    let album = record
+        .album
+        .as_deref()
+        .map(|album| format!(" ({})", album))
+        .unwrap_or_default();
+    let completion = record
+        .completion
+        .map(|completion| format!(" [{:.0}%]", completion * 100.0))
+        .unwrap_or_default();
+
+    println!("{}  {} — {}{}{}", played_at, title, artist, album, completion);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, sync::mpsc};
+
+    use super::*;
+    use crate::{events::unix_now, LastTrackInfo};
+
+    const KEEP_ALL: Retention = Retention {
+        max_entries: None,
+        max_age_days: None,
+    };
+
+    /// A history in a fresh folder of its own, removed again by the caller.
+    fn open_history(name: &str, retention: Retention) -> (History, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("ytune-history-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        (History::open(&dir, retention).unwrap(), dir)
+    }
+
+    fn play(video_id: &str, played_at: u64) -> PlayRecord {
+        PlayRecord {
+            version: RECORD_VERSION,
+            video_id: Some(video_id.to_string()),
+            title: Some(format!("Title {}", video_id)),
+            artist: Some("Artist".to_string()),
+            album: None,
+            duration_sec: Some(200),
+            played_at,
+            completion: Some(1.0),
+        }
+    }
+
+    #[test]
+    fn import_counts_only_plays_the_retention_limits_keep() {
+        let retention = Retention {
+            max_entries: Some(3),
+            max_age_days: Some(30),
+        };
+        let (history, dir) = open_history("import", retention);
+        let now = unix_now();
+        history.insert(&play("kept", now - 100)).unwrap();
+
+        let records = [
+            play("new", now - 50),
+            play("kept", now - 90),
+            play("too-old", now - 40 * 86_400),
+            play("older", now - 2_000),
+            play("oldest", now - 3_000),
+        ];
+        let (imported, duplicates, pruned) = history.import(&records, 60).unwrap();
+
+        assert_eq!((imported, duplicates, pruned), (2, 1, 2));
+        let ids: Vec<_> = history.recent(10).unwrap().into_iter().filter_map(|play| play.video_id).collect();
+        assert_eq!(ids, ["new", "kept", "older"]);
+        drop(history);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn titled(title: &str, artist: &str, played_at: u64) -> PlayRecord {
+        PlayRecord {
+            title: Some(title.to_string()),
+            artist: Some(artist.to_string()),
+            ..play(title, played_at)
+        }
+    }
+
+    fn titles(records: Vec<PlayRecord>) -> Vec<String> {
+        records.into_iter().filter_map(|record| record.title).collect()
+    }
+
+    #[test]
+    fn prune_drops_plays_beyond_the_age_and_count_limits() {
+        let (history, dir) = open_history("prune", KEEP_ALL);
+        let now = unix_now();
+        for (video_id, days_ago) in [("a", 40), ("b", 20), ("c", 3), ("d", 2), ("e", 1)] {
+            history.insert(&play(video_id, now - days_ago * 86_400)).unwrap();
+        }
+        drop(history);
+
+        let by_age = Retention {
+            max_entries: None,
+            max_age_days: Some(30),
+        };
+        let history = History::open(&dir, by_age).unwrap();
+        // Opening alone never loses plays.
+        assert_eq!(history.recent(10).unwrap().len(), 5);
+        history.prune().unwrap();
+        assert_eq!(history.recent(10).unwrap().len(), 4);
+        drop(history);
+
+        let by_count = Retention {
+            max_entries: Some(2),
+            max_age_days: None,
+        };
+        let history = History::open(&dir, by_count).unwrap();
+        history.prune().unwrap();
+        let ids: Vec<_> = history.recent(10).unwrap().into_iter().filter_map(|play| play.video_id).collect();
+        assert_eq!(ids, ["e", "d"]);
+        drop(history);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn search_needs_every_word_and_takes_wildcards_literally() {
+        let (history, dir) = open_history("search", KEEP_ALL);
+        for (played_at, (title, artist)) in [
+            ("One More Time", "Daft Punk"),
+            ("Punk Rock Song", "Somebody"),
+            ("100% Pure", "Band"),
+            ("100 Pure Love", "Band"),
+            ("snake_case", "Coder"),
+            ("snakeXcase", "Coder"),
+            ("back\\slash", "Coder"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            history.insert(&titled(title, artist, 1000 + played_at as u64)).unwrap();
+        }
+
+        assert_eq!(titles(history.search("punk daft", 10).unwrap()), ["One More Time"]);
+        assert_eq!(titles(history.search("PUNK", 10).unwrap()), ["Punk Rock Song", "One More Time"]);
+        assert_eq!(titles(history.search("100%", 10).unwrap()), ["100% Pure"]);
+        assert_eq!(titles(history.search("e_c", 10).unwrap()), ["snake_case"]);
+        assert_eq!(titles(history.search("k\\s", 10).unwrap()), ["back\\slash"]);
+        assert_eq!(titles(history.search("%", 10).unwrap()), ["100% Pure"]);
+        assert!(history.search("   ", 10).unwrap().is_empty());
+        assert!(history.search("punk nothing", 10).unwrap().is_empty());
+
+        // Most recent first, stopping at the limit.
+        assert_eq!(titles(history.search("coder", 2).unwrap()), ["back\\slash", "snakeXcase"]);
+        drop(history);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recorder_records_each_play_once() {
+        let (history, dir) = open_history("recorder", KEEP_ALL);
+        let event = |kind, position_sec, timestamp| TrackEvent {
+            kind,
+            track: LastTrackInfo {
+                video_id: Some(format!("video-{}", timestamp / 100)),
+                title: Some("Song".to_string()),
+                artist: Some("Artist".to_string()),
+                duration_sec: Some(200),
+                position_sec: Some(position_sec),
+                ..LastTrackInfo::default()
+            },
+            timestamp,
+        };
+        let (sender, events) = mpsc::channel();
+        for event in [
+            event(TrackEventKind::Started, 0, 1000),
+            event(TrackEventKind::Stopped, 200, 1200),
+            // Repeat-one can end a play twice without a start in between.
+            event(TrackEventKind::Stopped, 200, 1201),
+            // Skipped before anything was heard.
+            event(TrackEventKind::Started, 0, 1300),
+            event(TrackEventKind::Stopped, 0, 1300),
+            event(TrackEventKind::Started, 0, 1400),
+            event(TrackEventKind::Stopped, 50, 1450),
+        ] {
+            sender.send(event).unwrap();
+        }
+        drop(sender);
+
+        let changes = std::cell::Cell::new(0);
+        run_recorder(history, events, |_| changes.set(changes.get() + 1));
+        // Once at start and once per recorded play.
+        assert_eq!(changes.get(), 3);
+
+        let history = History::open(&dir, KEEP_ALL).unwrap();
+        let plays: Vec<_> = history.recent(10).unwrap().into_iter().map(|play| play.played_at).collect();
+        assert_eq!(plays, [1400, 1000]);
+        drop(history);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::mpsc::Receiver,
+    thread,
+};
+
+use crate::{
+    config::HooksConfig,
+    events::{TrackEvent, TrackEventKind},
+};
+
+/// Keeps the hooks from flashing a console window on Windows.
+#[cfg(target_os = "windows")]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+/// Runs the configured user commands on playback events. The event name is
+/// the first argument and the track is passed in `YTUNE_*` environment
+/// variables. Returns when the event bus is closed.
+pub fn run_hooks(config: HooksConfig, events: Receiver<TrackEvent>) {
+    let mut running: HashMap<TrackEventKind, Child> = HashMap::new();
+
+    for event in events {
+        let (name, command) = match event.kind {
+            TrackEventKind::Started => ("track_change", &config.on_track_change),
+            TrackEventKind::Paused => ("pause", &config.on_pause),
+            TrackEventKind::Resumed => ("resume", &config.on_resume),
+            TrackEventKind::Stopped => ("stop", &config.on_stop),
+            TrackEventKind::Position => continue,
+        };
+        let Some(command) = command.as_deref() else {
+            continue;
+        };
+
+        // A hook that is still busy from the last event of its kind is skipped
+        // rather than piling up more processes.
+        if let Some(child) = running.get_mut(&event.kind) {
+            if matches!(child.try_wait(), Ok(None)) {
+                log::debug!("Skipping {} hook, the previous run hasn't finished", name);
+                continue;
+            }
+        }
+
+        match spawn(command, name, &event) {
+            Ok(child) => {
+                running.insert(event.kind, child);
+            }
+            Err(e) => log::warn!("Failed to run {} hook '{}': {}", name, command, e),
+        }
+    }
+}
+
+fn spawn(program: &str, name: &str, event: &TrackEvent) -> std::io::Result<Child> {
+    let track = &event.track;
+    let mut command = Command::new(program);
+    command
+        .arg(name)
+        .env("YTUNE_TITLE", track.title.as_deref().unwrap_or_default())
+        .env("YTUNE_ARTIST", track.artist.as_deref().unwrap_or_default())
+        .env("YTUNE_ALBUM", track.album.as_deref().unwrap_or_default())
+        .env(
+            "YTUNE_DURATION",
+            track.duration_sec.map(|d| d.to_string()).unwrap_or_default(),
+        )
+        .env("YTUNE_VIDEO_ID", track.video_id.as_deref().unwrap_or_default())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let mut child = command.spawn()?;
+    if let Some(stderr) = child.stderr.take() {
+        let name = name.to_string();
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                log::debug!("[{} hook] {}", name, line);
+            }
+        });
+    }
+    Ok(child)
+}
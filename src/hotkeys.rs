@@ -0,0 +1,209 @@
+use std::str::FromStr;
+
+use wry::application::{
+    accelerator::{Accelerator, AcceleratorId},
+    event_loop::EventLoopWindowTarget,
+    global_shortcut::ShortcutManager,
+};
+
+use crate::{config::Config, control::PlayerCommand};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HotkeyAction {
+    Player(PlayerCommand),
+    ShowHide,
+    CopyTrack,
+    CopyLink,
+    ShareCard,
+}
+
+impl HotkeyAction {
+    /// The action names `[hotkeys]` accepts.
+    fn from_name(name: &str) -> Option<Self> {
+        let action = match name {
+            "play_pause" => HotkeyAction::Player(PlayerCommand::PlayPause),
+            "next" => HotkeyAction::Player(PlayerCommand::Next),
+            "previous" => HotkeyAction::Player(PlayerCommand::Previous),
+            "like" => HotkeyAction::Player(PlayerCommand::Like),
+            "dislike" => HotkeyAction::Player(PlayerCommand::Dislike),
+            "show_hide" => HotkeyAction::ShowHide,
+            "copy_track" => HotkeyAction::CopyTrack,
+            "copy_link" => HotkeyAction::CopyLink,
+            "share_card" => HotkeyAction::ShareCard,
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+/// A shortcut from the config, parsed.
+#[derive(Clone, Debug)]
+struct Binding {
+    /// As written in the config, for messages.
+    text: String,
+    accelerator: Accelerator,
+    id: AcceleratorId,
+    action: HotkeyAction,
+}
+
+/// System-wide shortcuts, which fire even while the window is hidden in the tray.
+pub struct Hotkeys {
+    // Unregisters everything when dropped, so it is kept alive here.
+    manager: ShortcutManager,
+    bindings: Vec<Binding>,
+}
+
+impl Hotkeys {
+    /// Registers the shortcuts set in the config. Ones that don't parse or
+    /// are taken by another program are skipped with a warning.
+    pub fn register<T: 'static>(event_loop: &EventLoopWindowTarget<T>, config: &Config) -> Self {
+        let mut hotkeys = Hotkeys {
+            manager: ShortcutManager::new(event_loop),
+            bindings: Vec::new(),
+        };
+        let (bindings, errors) = parse_bindings(config);
+        for error in errors {
+            log::warn!("{}", error);
+        }
+        for binding in bindings {
+            match hotkeys.manager.register(binding.accelerator.clone()) {
+                Ok(_) => hotkeys.bindings.push(binding),
+                Err(e) => log::warn!("Failed to register hotkey '{}': {}", binding.text, e),
+            }
+        }
+        hotkeys
+    }
+
+    /// Swaps the registered shortcuts for the ones in `config`, e.g. after
+    /// `config.toml` was edited. The old set goes right before the new one
+    /// is registered, so a combination can move between actions. It is all
+    /// or nothing: when a shortcut doesn't parse or can't be registered,
+    /// the old set stays in place.
+    pub fn reload(&mut self, config: &Config) {
+        let (bindings, errors) = parse_bindings(config);
+        if !errors.is_empty() {
+            for error in errors {
+                log::warn!("{}", error);
+            }
+            log::warn!("Keeping the current hotkeys until the config is fixed");
+            return;
+        }
+
+        self.unregister_all();
+        match self.register_all(&bindings) {
+            Ok(()) => {
+                log::info!("Registered {} hotkey(s) from the reloaded config", bindings.len());
+                self.bindings = bindings;
+            }
+            Err(e) => {
+                log::warn!("{}; keeping the current hotkeys", e);
+                self.unregister_all();
+                let old_bindings = std::mem::take(&mut self.bindings);
+                if let Err(e) = self.register_all(&old_bindings) {
+                    log::warn!("Failed to restore the hotkeys: {}", e);
+                }
+                self.bindings = old_bindings;
+            }
+        }
+    }
+
+    fn unregister_all(&mut self) {
+        if let Err(e) = self.manager.unregister_all() {
+            log::warn!("Failed to unregister hotkeys: {}", e);
+        }
+    }
+
+    fn register_all(&mut self, bindings: &[Binding]) -> Result<(), String> {
+        for binding in bindings {
+            self.manager
+                .register(binding.accelerator.clone())
+                .map_err(|e| format!("Failed to register hotkey '{}': {}", binding.text, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn action_for(&self, id: AcceleratorId) -> Option<HotkeyAction> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.id == id)
+            .map(|binding| binding.action)
+    }
+}
+
+/// The shortcuts set in `config`, and what was wrong with the ones that
+/// couldn't be used.
+fn parse_bindings(config: &Config) -> (Vec<Binding>, Vec<String>) {
+    let mut names = vec![(config.copy_track_hotkey.as_deref(), "copy_track")];
+    names.extend(
+        config
+            .hotkeys
+            .iter()
+            .map(|(binding, action)| (Some(binding.as_str()), action.as_str())),
+    );
+
+    let mut bindings = Vec::new();
+    let mut errors = Vec::new();
+    for (binding, action_name) in names {
+        let Some(binding) = binding.filter(|binding| !binding.trim().is_empty()) else {
+            continue;
+        };
+        let Some(action) = HotkeyAction::from_name(action_name) else {
+            errors.push(format!("Unknown action '{}' for hotkey '{}'", action_name, binding));
+            continue;
+        };
+        match Accelerator::from_str(binding) {
+            Ok(accelerator) => bindings.push(Binding {
+                text: binding.to_string(),
+                id: accelerator.clone().id(),
+                accelerator,
+                action,
+            }),
+            Err(e) => errors.push(format!("Invalid hotkey '{}': {}", binding, e)),
+        }
+    }
+    (bindings, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(hotkeys: &[(&str, &str)]) -> Config {
+        Config {
+            copy_track_hotkey: None,
+            hotkeys: hotkeys
+                .iter()
+                .map(|(binding, action)| (binding.to_string(), action.to_string()))
+                .collect(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn valid_bindings_parse_without_errors() {
+        let (bindings, errors) = parse_bindings(&config(&[("Ctrl+Alt+N", "next"), ("Ctrl+Alt+P", "play_pause")]));
+        assert!(errors.is_empty(), "{:?}", errors);
+        let actions: Vec<_> = bindings.iter().map(|binding| binding.action).collect();
+        assert_eq!(
+            actions,
+            [
+                HotkeyAction::Player(PlayerCommand::Next),
+                HotkeyAction::Player(PlayerCommand::PlayPause)
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_bindings_are_reported() {
+        let (bindings, errors) = parse_bindings(&config(&[
+            ("Ctrl+Alt+N", "next"),
+            ("Ctrl+Alt+X", "explode"),
+            ("Ctrl+Nope+Q", "previous"),
+            ("  ", "next"),
+        ]));
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors.iter().any(|error| error.starts_with("Unknown action 'explode'")));
+        assert!(errors.iter().any(|error| error.starts_with("Invalid hotkey 'Ctrl+Nope+Q'")));
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    thread,
+    time::Duration,
+};
+
+use image::{load_from_memory_with_format, ImageFormat};
+use serde_json::{json, Value};
+
+use crate::{api::types::API_VERSION, art_cache, config::Config, socket_server, youtube_url, LastTrackInfo};
+
+/// How long to wait before trying the socket again while ytune isn't running.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const PAUSED_COLOR: &str = "#888888";
+/// Big enough to pick a color from, small enough to fetch for every track.
+const COLOR_SAMPLE_SIZE: u32 = 32;
+
+/// Prints a one-block i3bar line, e.g. `[{"full_text": "🎵 Title - Artist",
+/// "color": "#c04a3d"}]`, whenever the running ytune's track changes. Reads
+/// the control socket, so ytune needs `pipe_server_enabled = true`; while it
+/// isn't reachable the block is empty. Runs until writing to stdout fails.
+pub fn run(config: &Config) -> io::Result<()> {
+    let path = config
+        .socket_path
+        .clone()
+        .unwrap_or_else(socket_server::default_path);
+    let mut colors = ArtColors::default();
+    let mut printed = None;
+
+    loop {
+        match UnixStream::connect(&path).and_then(follow) {
+            Ok(tracks) => {
+                for track in tracks {
+                    match track {
+                        Ok(track) => {
+                            let line = block(&track, &mut colors, &config.i3status_color).to_string();
+                            print_changed(&line, &mut printed)?;
+                        }
+                        Err(e) => {
+                            log::debug!("Lost the control socket: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => log::debug!("Control socket at {} unavailable: {}", path.display(), e),
+        }
+        print_changed(&json!([{ "full_text": "" }]).to_string(), &mut printed)?;
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+/// Asks for the current track, then yields it and every update after it
+/// until the socket closes.
+fn follow(stream: UnixStream) -> io::Result<impl Iterator<Item = io::Result<LastTrackInfo>>> {
+    let mut writer = stream.try_clone()?;
+    writeln!(writer, "{}", json!({ "v": API_VERSION, "cmd": "now_playing" }))?;
+    writer.flush()?;
+
+    Ok(BufReader::new(stream).lines().filter_map(|line| {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        // Other responses and lines that aren't JSON carry no track.
+        let mut message: Value = serde_json::from_str(&line).ok()?;
+        serde_json::from_value(message.get_mut("track")?.take()).ok().map(Ok)
+    }))
+}
+
+/// Position updates arrive every few seconds but rarely change the block.
+fn print_changed(line: &str, printed: &mut Option<String>) -> io::Result<()> {
+    if printed.as_deref() == Some(line) {
+        return Ok(());
+    }
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", line)?;
+    stdout.flush()?;
+    *printed = Some(line.to_string());
+    Ok(())
+}
+
+fn block(track: &LastTrackInfo, colors: &mut ArtColors, default_color: &str) -> Value {
+    let Some(title) = track.title.as_deref() else {
+        return json!([{ "full_text": "" }]);
+    };
+    let text = match track.artist.as_deref() {
+        Some(artist) => format!("{} - {}", title, artist),
+        None => title.to_string(),
+    };
+
+    if !track.is_playing {
+        return json!([{ "full_text": format!("⏸ {}", text), "color": PAUSED_COLOR }]);
+    }
+    let color = track
+        .album_art
+        .as_deref()
+        .and_then(|url| colors.get(url))
+        .unwrap_or_else(|| default_color.to_string());
+    json!([{ "full_text": format!("🎵 {}", text), "color": color }])
+}
+
+/// The dominant color of each album art seen, so it's fetched once per track.
+#[derive(Default)]
+struct ArtColors {
+    agent: Option<ureq::Agent>,
+    colors: HashMap<String, Option<String>>,
+}
+
+impl ArtColors {
+    fn get(&mut self, url: &str) -> Option<String> {
+        if let Some(color) = self.colors.get(url) {
+            return color.clone();
+        }
+        let agent = self
+            .agent
+            .get_or_insert_with(|| ureq::AgentBuilder::new().timeout(art_cache::FETCH_TIMEOUT).build());
+        let color = png_art_url(url).and_then(|png_url| match dominant_color(agent, &png_url) {
+            Ok(color) => Some(color),
+            Err(e) => {
+                log::debug!("No color from album art {}: {}", png_url, e);
+                None
+            }
+        });
+        self.colors.insert(url.to_string(), color.clone());
+        color
+    }
+}
+
+/// A small PNG of the art, as only the `googleusercontent.com` style URLs
+/// can be asked for; ytune decodes nothing but PNG. None for the rest.
+fn png_art_url(url: &str) -> Option<String> {
+    let resized = youtube_url::resize_youtube_art_url(url, COLOR_SAMPLE_SIZE);
+    if resized == url {
+        return None;
+    }
+    let (base, options) = resized.rsplit_once('=')?;
+    let mut options: Vec<&str> = options
+        .split('-')
+        .filter(|option| !matches!(*option, "rj" | "rw" | "rp"))
+        .collect();
+    options.push("rp");
+    Some(format!("{}={}", base, options.join("-")))
+}
+
+/// The most common color, ignoring near-black and near-white pixels that
+/// borders and backgrounds bring, averaged over the pixels that share it.
+fn dominant_color(agent: &ureq::Agent, url: &str) -> Result<String, String> {
+    let art = art_cache::fetch(agent, url)?;
+    let image = load_from_memory_with_format(&art.bytes, ImageFormat::Png)
+        .map_err(|e| e.to_string())?
+        .to_rgb8();
+
+    let mut buckets: HashMap<[u8; 3], (u32, [u32; 3])> = HashMap::new();
+    for pixel in image.pixels() {
+        let [r, g, b] = pixel.0;
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        if max < 40 || min > 215 {
+            continue;
+        }
+        let (count, sum) = buckets.entry([r >> 5, g >> 5, b >> 5]).or_default();
+        *count += 1;
+        for (total, value) in sum.iter_mut().zip([r, g, b]) {
+            *total += u32::from(value);
+        }
+    }
+
+    let (count, sum) = buckets
+        .into_values()
+        .max_by_key(|(count, _)| *count)
+        .ok_or("the art has no colored pixels")?;
+    Ok(format!("#{:02x}{:02x}{:02x}", sum[0] / count, sum[1] / count, sum[2] / count))
+}
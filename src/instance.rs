@@ -0,0 +1,93 @@
+use std::fmt;
+
+/// Another copy of ytune is already running. Its window has been brought
+/// to the front.
+#[derive(Debug)]
+pub struct AlreadyRunningError;
+
+impl fmt::Display for AlreadyRunningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ytune is already running")
+    }
+}
+
+/// Held for as long as this copy runs; dropping it lets another one start.
+/// The OS releases it too when the process exits, crashes included.
+pub struct InstanceLock {
+    _lock: platform::Lock,
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::{
+        core::{w, PCWSTR},
+        Win32::{
+            Foundation::{CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE, TRUE},
+            System::Threading::{CreateMutexW, ReleaseMutex},
+            UI::WindowsAndMessaging::{
+                FindWindowW, IsIconic, SetForegroundWindow, ShowWindow, SW_RESTORE, SW_SHOW,
+            },
+        },
+    };
+
+    use super::AlreadyRunningError;
+
+    /// Per session, so other users on the machine can run their own copy.
+    const MUTEX_NAME: PCWSTR = w!("Local\\ytune-single-instance");
+    const WINDOW_TITLE: PCWSTR = w!("ytune");
+
+    /// `None` when the mutex couldn't be created; ytune then runs unguarded.
+    pub struct Lock(Option<HANDLE>);
+
+    impl Drop for Lock {
+        fn drop(&mut self) {
+            if let Some(handle) = self.0 {
+                unsafe {
+                    let _ = ReleaseMutex(handle);
+                    let _ = CloseHandle(handle);
+                }
+            }
+        }
+    }
+
+    pub fn acquire() -> Result<Lock, AlreadyRunningError> {
+        let handle = match unsafe { CreateMutexW(None, TRUE, MUTEX_NAME) } {
+            Ok(handle) => handle,
+            Err(e) => {
+                log::warn!("Failed to create the single-instance mutex: {}", e);
+                return Ok(Lock(None));
+            }
+        };
+        if unsafe { GetLastError() } != ERROR_ALREADY_EXISTS {
+            return Ok(Lock(Some(handle)));
+        }
+
+        unsafe {
+            let _ = CloseHandle(handle);
+            // Also finds the window while it is hidden in the tray.
+            let window = FindWindowW(PCWSTR::null(), WINDOW_TITLE);
+            if window.0 != 0 {
+                ShowWindow(window, if IsIconic(window).as_bool() { SW_RESTORE } else { SW_SHOW });
+                SetForegroundWindow(window);
+            }
+        }
+        Err(AlreadyRunningError)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+    use super::AlreadyRunningError;
+
+    pub struct Lock;
+
+    pub fn acquire() -> Result<Lock, AlreadyRunningError> {
+        Ok(Lock)
+    }
+}
+
+/// Claims the single-instance lock, or hands over to the copy that already
+/// holds it. Only enforced on Windows.
+pub fn acquire_instance_lock() -> Result<InstanceLock, AlreadyRunningError> {
+    platform::acquire().map(|lock| InstanceLock { _lock: lock })
+}
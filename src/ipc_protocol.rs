@@ -0,0 +1,222 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{
+    api::types::{ControlResponse, API_VERSION},
+    config::Config,
+    control::PlayerCommand,
+    share_session::Session,
+    LastTrackInfo, UserEvent,
+};
+
+/// How long `request` waits for the running ytune to answer.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Answers one JSON request from a local control client, e.g.
+/// `{"v": 1, "cmd": "now_playing"}` or `{"cmd": "next"}`. The original
+/// camelCase names (`getTrack`, `playPause`) keep working. Requests may
+/// leave `"v"` out; other fields they carry are ignored. Commands go to the
+/// event loop through `send_event`, which is false once it has stopped.
+pub fn handle_request(
+    request: &str,
+    last_track: &Mutex<LastTrackInfo>,
+    send_event: impl Fn(UserEvent) -> bool,
+) -> ControlResponse {
+    let request: Value = match serde_json::from_str(request) {
+        Ok(request) => request,
+        Err(e) => return ControlResponse::error(format!("invalid JSON: {}", e)),
+    };
+    if let Some(version) = request.get("v") {
+        if version.as_u64() != Some(API_VERSION) {
+            return ControlResponse::error(format!(
+                "unsupported protocol version {}, this ytune speaks {}",
+                version, API_VERSION
+            ));
+        }
+    }
+
+    match request.get("cmd").and_then(Value::as_str) {
+        Some("now_playing" | "getTrack") => ControlResponse::track(last_track.lock().unwrap().clone()),
+        Some("play") => send(&send_event, UserEvent::PlayerCommand(PlayerCommand::Play)),
+        Some("pause") => send(&send_event, UserEvent::PlayerCommand(PlayerCommand::Pause)),
+        Some("play_pause" | "playPause") => send(&send_event, UserEvent::PlayerCommand(PlayerCommand::PlayPause)),
+        Some("next") => send(&send_event, UserEvent::PlayerCommand(PlayerCommand::Next)),
+        Some("previous") => send(&send_event, UserEvent::PlayerCommand(PlayerCommand::Previous)),
+        Some("refresh") => send(&send_event, UserEvent::PlayerCommand(PlayerCommand::Refresh)),
+        Some("show") => send(&send_event, UserEvent::ShowWindow),
+        Some("open_session") => match request.get("uri").and_then(Value::as_str).map(Session::parse) {
+            Some(Ok(session)) => send(&send_event, UserEvent::OpenSession(session)),
+            Some(Err(e)) => ControlResponse::error(e),
+            None => ControlResponse::error("missing 'uri'"),
+        },
+        Some(other) => ControlResponse::error(format!("unknown command '{}'", other)),
+        None => ControlResponse::error("missing 'cmd'"),
+    }
+}
+
+fn send(send_event: impl Fn(UserEvent) -> bool, event: UserEvent) -> ControlResponse {
+    if send_event(event) {
+        ControlResponse::ok()
+    } else {
+        ControlResponse::error("ytune is shutting down")
+    }
+}
+
+/// Sends `request` to the ytune already running with the control server on
+/// and returns its response. Fails when none is listening, or when it
+/// doesn't answer within `RESPONSE_TIMEOUT`.
+pub fn request(config: &Config, request: &Value) -> io::Result<Value> {
+    let (reader, writer) = connect(config)?;
+    let request = request.clone();
+    // A pipe opened as a file can't be given a read timeout, so a stuck
+    // instance is waited for on another thread instead.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(exchange(reader, writer, &request));
+    });
+    receiver
+        .recv_timeout(RESPONSE_TIMEOUT)
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "ytune didn't answer")))
+}
+
+fn exchange(reader: impl BufRead, mut writer: impl Write, request: &Value) -> io::Result<Value> {
+    writeln!(writer, "{}", request)?;
+    writer.flush()?;
+
+    for line in reader.lines() {
+        let line = line?;
+        let response: Value =
+            serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // Socket clients are also sent playback events, which may come first.
+        if response.get("event").is_none() {
+            return Ok(response);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "ytune closed the connection without answering"))
+}
+
+#[cfg(target_os = "windows")]
+fn connect(_config: &Config) -> io::Result<(BufReader<std::fs::File>, std::fs::File)> {
+    let pipe = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(crate::pipe_server::PIPE_NAME)?;
+    let writer = pipe.try_clone()?;
+    Ok((BufReader::new(pipe), writer))
+}
+
+#[cfg(unix)]
+fn connect(
+    config: &Config,
+) -> io::Result<(BufReader<std::os::unix::net::UnixStream>, std::os::unix::net::UnixStream)> {
+    let path = config
+        .socket_path
+        .clone()
+        .unwrap_or_else(crate::socket_server::default_path);
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    let writer = stream.try_clone()?;
+    Ok((BufReader::new(stream), writer))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// The response to `request` as sent on the wire, and what reached the
+    /// event loop.
+    fn answer(request: &str) -> (Value, Vec<UserEvent>) {
+        let last_track = Mutex::new(LastTrackInfo {
+            title: Some("Song".to_string()),
+            ..LastTrackInfo::default()
+        });
+        let (sender, receiver) = mpsc::channel();
+        let response = handle_request(request, &last_track, |event| sender.send(event).is_ok());
+        let response = serde_json::from_str(&response.to_string()).unwrap();
+        (response, receiver.try_iter().collect())
+    }
+
+    fn error(response: &Value) -> &str {
+        assert_eq!(response["ok"], false);
+        response["error"].as_str().unwrap()
+    }
+
+    #[test]
+    fn commands_reach_the_event_loop() {
+        let (response, events) = answer(r#"{"v": 1, "cmd": "next"}"#);
+        assert_eq!(response, serde_json::json!({ "v": API_VERSION, "ok": true }));
+        assert!(matches!(events[..], [UserEvent::PlayerCommand(PlayerCommand::Next)]));
+
+        // The original names, without a version.
+        let (_, events) = answer(r#"{"cmd": "playPause"}"#);
+        assert!(matches!(events[..], [UserEvent::PlayerCommand(PlayerCommand::PlayPause)]));
+    }
+
+    #[test]
+    fn now_playing_answers_with_the_track() {
+        for request in [r#"{"v": 1, "cmd": "now_playing"}"#, r#"{"cmd": "getTrack"}"#] {
+            let (response, events) = answer(request);
+            assert_eq!(response["ok"], true);
+            assert_eq!(response["track"]["title"], "Song");
+            assert!(events.is_empty());
+        }
+    }
+
+    #[test]
+    fn bad_requests_get_an_error_and_do_nothing() {
+        for (request, expected) in [
+            (r#"{"cmd": "rewind"}"#, "unknown command 'rewind'"),
+            (r#"{"v": 1}"#, "missing 'cmd'"),
+            (r#"{"cmd": 5}"#, "missing 'cmd'"),
+            (r#"{"v": 2, "cmd": "next"}"#, "unsupported protocol version 2, this ytune speaks 1"),
+            (r#"{"v": "1", "cmd": "next"}"#, "unsupported protocol version \"1\", this ytune speaks 1"),
+            (r#"{"cmd": "open_session"}"#, "missing 'uri'"),
+        ] {
+            let (response, events) = answer(request);
+            assert_eq!(error(&response), expected, "{}", request);
+            assert_eq!(response["v"], API_VERSION);
+            assert!(events.is_empty(), "{}", request);
+        }
+
+        let (response, _) = answer("next");
+        assert!(error(&response).starts_with("invalid JSON"));
+        let (response, events) = answer(r#"{"cmd": "open_session", "uri": "https://example.com"}"#);
+        assert!(error(&response).starts_with("Not a ytune session link"));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn commands_fail_once_the_event_loop_is_gone() {
+        let response = handle_request(r#"{"cmd": "next"}"#, &Mutex::default(), |_| false);
+        assert_eq!(response.error.as_deref(), Some("ytune is shutting down"));
+    }
+
+    #[test]
+    fn exchange_skips_events_sent_before_the_response() {
+        let lines = concat!(
+            r#"{"v":1,"event":"trackUpdate","kind":"position","track":{}}"#,
+            "\n",
+            r#"{"v":1,"ok":true}"#,
+            "\n",
+        );
+        let mut sent = Vec::new();
+        let response = exchange(Cursor::new(lines), &mut sent, &serde_json::json!({ "cmd": "next" })).unwrap();
+        assert_eq!(response, serde_json::json!({ "v": 1, "ok": true }));
+        assert_eq!(sent, b"{\"cmd\":\"next\"}\n");
+    }
+
+    #[test]
+    fn exchange_fails_without_a_response() {
+        let error = exchange(Cursor::new(""), Vec::new(), &serde_json::json!({ "cmd": "next" })).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let error = exchange(Cursor::new("not json\n"), Vec::new(), &serde_json::json!({})).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,571 @@
+// wry runs this script on every document load, including Google sign-in
+// pages. The helpers below are always defined, but the listeners and the
+// scrape timer only start on YouTube Music. Returning there after sign-in
+// loads a fresh document, which runs this script again and starts them.
+const YTUNE_ON_YTM = location.hostname === 'music.youtube.com';
+
+function getElementByXpath(path) {
+    try {
+        return document.evaluate(path, document, null, XPathResult.FIRST_ORDERED_NODE_TYPE, null).singleNodeValue;
+    } catch (e) { return null; }
+}
+
+function parseTimeToSeconds(timeStr) {
+    if (!timeStr || typeof timeStr !== 'string') return null;
+
+    const parts = timeStr.split(':').map(Number);
+    if (parts.some(isNaN) || parts.length > 3 || parts.length < 1) return null;
+
+    let seconds = 0;
+    if (parts.length === 3) {
+        seconds = parts[0] * 3600 + parts[1] * 60 + parts[2];
+    } else if (parts.length === 2) {
+        seconds = parts[0] * 60 + parts[1];
+    } else {
+        seconds = parts[0];
+    }
+
+    return seconds > 0 ? seconds : null;
+}
+
+// Reads the player bar's time info. It is usually "elapsed / total", with
+// "Live" as the total for streams, but some layouts show only the elapsed
+// time; that must not be taken for the duration, or the presence would end
+// early. Either value is null when unknown.
+function parseTimeInfo(text) {
+    if (!text || typeof text !== 'string') return { position: null, duration: null };
+
+    const parts = text.split('/').map(part => part.trim());
+    const elapsedText = parts[0];
+    const elapsed = elapsedText === '0:00' ? 0 : parseTimeToSeconds(elapsedText);
+    if (parts.length < 2) return { position: elapsed, duration: null };
+
+    const totalText = parts[parts.length - 1];
+    let duration = totalText.toLowerCase() === 'live' ? null : parseTimeToSeconds(totalText);
+    // A total below the elapsed time means the text was misread.
+    if (duration !== null && elapsed !== null && duration < elapsed) duration = null;
+    return { position: elapsed, duration };
+}
+
+// Premium accounts never see ads, so once the tier is known we can skip
+// the ad checks entirely. Stays null until the account menu has rendered.
+let accountTier = null;
+
+function detectAccountTier() {
+    if (accountTier) return accountTier;
+
+    const settingsButton = document.querySelector('ytmusic-settings-button');
+    if (!settingsButton) return null;
+
+    const upgradeEntry = document.querySelector('a[href*="music_premium"], ytmusic-guide-entry-renderer a[href*="/premium"]');
+    accountTier = upgradeEntry ? 'free' : 'premium';
+    return accountTier;
+}
+
+function isAdPlaying() {
+    const player = document.querySelector('#movie_player');
+    if (player && (player.classList.contains('ad-showing') || player.classList.contains('ad-interrupting'))) {
+        return true;
+    }
+    return !!document.querySelector('ytmusic-player-bar .advertisement, ytmusic-player-bar [class*="ad-badge"]');
+}
+
+// Set from config.toml when the built-in choice below picks the wrong element.
+let playerBarSelectorOverride = null;
+
+window.__ytuneSetPlayerBarSelector = function (selector) {
+    playerBarSelectorOverride = selector || null;
+};
+
+function postLog(level, message) {
+    window.ipc.postMessage(JSON.stringify({ cmd: 'log', level, message }));
+}
+
+function playerBarTitle(playerBar) {
+    return playerBar.querySelector('.title.style-scope.ytmusic-player-bar')?.innerText.trim() || "";
+}
+
+function isRendered(element) {
+    return element.getClientRects().length > 0;
+}
+
+let playerBarCandidates = 1;
+
+// With the miniplayer open there can be more than one player bar, and the
+// first one in the document may be stale. Prefer a visible bar showing a
+// title, then one inside the main app showing a title, then any with a title.
+function findPlayerBar() {
+    if (playerBarSelectorOverride) {
+        try {
+            const overridden = document.querySelector(playerBarSelectorOverride);
+            if (overridden) return overridden;
+        } catch (e) {
+            postLog('warn', `Invalid player_bar_selector ${playerBarSelectorOverride}: ${e.message}`);
+            playerBarSelectorOverride = null;
+        }
+    }
+
+    const candidates = Array.from(document.querySelectorAll('ytmusic-player-bar'));
+    if (candidates.length !== playerBarCandidates) {
+        // Logged on changes only, since this runs on every scrape.
+        if (candidates.length > 1) {
+            postLog('info', `Found ${candidates.length} player bars, choosing the active one`);
+        }
+        playerBarCandidates = candidates.length;
+    }
+    if (candidates.length <= 1) return candidates[0] || null;
+
+    const hasTitle = candidate => playerBarTitle(candidate) !== "";
+    return candidates.find(candidate => isRendered(candidate) && hasTitle(candidate))
+        || candidates.find(candidate => candidate.closest('ytmusic-app') && hasTitle(candidate))
+        || candidates.find(hasTitle)
+        || candidates[0];
+}
+
+// Position of the playing track in the queue, from the queue items rendered
+// behind the player page. Null until the queue has been rendered at least once.
+function getQueuePosition() {
+    const items = Array.from(document.querySelectorAll('ytmusic-player-queue ytmusic-player-queue-item'));
+    if (items.length === 0) return null;
+
+    const current = items.findIndex(item =>
+        item.hasAttribute('selected') || item.getAttribute('play-button-state') === 'playing');
+    if (current < 0) return null;
+
+    return { index: current + 1, total: items.length };
+}
+
+// From 0 to 100. The player API follows YouTube Music's own slider; the
+// video element is the fallback.
+function getVolume() {
+    const player = document.querySelector('#movie_player');
+    if (player && typeof player.getVolume === 'function') return Math.round(player.getVolume());
+    const video = document.querySelector('video');
+    return video ? Math.round(video.volume * 100) : null;
+}
+
+function clamp(value, min, max) {
+    return Math.min(Math.max(value, min), max);
+}
+
+// A single empty read is usually just a page transition.
+const EMPTY_READS_BEFORE_CLEAR = 2;
+let emptyReads = 0;
+// A read identical to the last one isn't posted again, except this often so
+// ytune can still tell the page is alive.
+const UNCHANGED_POST_INTERVAL_MS = 60000;
+let lastPostedUpdate = null;
+let lastPostedAt = 0;
+
+function postTrackUpdate(update, force) {
+    concertTrack = update.cleared ? null : update;
+    renderConcertOverlay();
+
+    const message = JSON.stringify(update);
+    const now = Date.now();
+    if (!force && message === lastPostedUpdate && now - lastPostedAt < UNCHANGED_POST_INTERVAL_MS) return;
+
+    lastPostedUpdate = message;
+    lastPostedAt = now;
+    window.ipc.postMessage(message);
+}
+
+// How long scrapes take is summed up and reported this often, for tuning.
+const SCRAPE_METRICS_INTERVAL_MS = 60000;
+let scrapeTimings = { count: 0, totalMs: 0, maxMs: 0, since: Date.now() };
+
+function timeScrape(scrape) {
+    const startedAt = performance.now();
+    try {
+        scrape();
+    } finally {
+        const elapsed = performance.now() - startedAt;
+        scrapeTimings.count++;
+        scrapeTimings.totalMs += elapsed;
+        scrapeTimings.maxMs = Math.max(scrapeTimings.maxMs, elapsed);
+        if (Date.now() - scrapeTimings.since >= SCRAPE_METRICS_INTERVAL_MS) {
+            window.ipc.postMessage(JSON.stringify({
+                cmd: 'scrapeMetrics',
+                scrapes: scrapeTimings.count,
+                scrape_ms: scrapeTimings.totalMs / scrapeTimings.count,
+                max_scrape_ms: scrapeTimings.maxMs
+            }));
+            scrapeTimings = { count: 0, totalMs: 0, maxMs: 0, since: Date.now() };
+        }
+    }
+}
+
+// `force` posts even when nothing changed since the last read.
+function getTrackInfo(force = false) {
+    timeScrape(() => readTrackInfo(force));
+}
+
+function readTrackInfo(force) {
+    // The player bar keeps the last track while the error screen is up.
+    if (playerErrorReported) return;
+    const playerBar = findPlayerBar();
+    if (!playerBar) return;
+
+    const tier = detectAccountTier();
+    if (tier !== 'premium' && isAdPlaying()) return;
+
+    const artistContainer = playerBar.querySelector('.byline.style-scope.ytmusic-player-bar');
+    const albumArtEl = playerBar.querySelector('img');
+    const durationEl = playerBar.querySelector('#progress-bar .time-info.style-scope.ytmusic-player-bar');
+
+    const titleText = playerBarTitle(playerBar);
+    const albumEl = artistContainer?.querySelector('a[href*="browse/MPREb"]');
+    const albumText = albumEl?.innerText.trim() || "";
+
+    let artistText = "";
+    if (artistContainer) {
+        const artistNodes = Array.from(artistContainer.querySelectorAll('yt-formatted-string a, yt-formatted-string'))
+            .filter(node => node !== albumEl);
+        artistText = artistNodes.map(node => node.innerText.trim()).filter(text => text && !/^\d{4}$/.test(text)).join(', ');
+        // Uploads without an artist channel have a plain-text byline.
+        artistText = artistText ? artistText.trim() : artistContainer.innerText.trim();
+    }
+
+    // The "E" badge next to the title; its label is localized, so any
+    // inline badge there counts.
+    const isExplicit = !!playerBar.querySelector(
+        '.middle-controls ytmusic-inline-badge-renderer, .middle-controls .explicit-badge');
+
+    const albumArtUrl = albumArtEl?.getAttribute("src") || "";
+    // LIKE, DISLIKE or INDIFFERENT; ads and some uploads have no like button.
+    const likeStatus = playerBar.querySelector('ytmusic-like-button-renderer')?.getAttribute('like-status');
+
+    const player = document.querySelector('#movie_player');
+    const videoData = player && typeof player.getVideoData === 'function' ? player.getVideoData() : null;
+    const videoId = videoData?.video_id || new URLSearchParams(location.search).get('v') || "";
+
+    const timeInfo = parseTimeInfo(durationEl?.innerText);
+    const video = document.querySelector('video');
+    const positionSeconds = video && isFinite(video.currentTime) ? Math.floor(video.currentTime) : timeInfo.position;
+    const isPlaying = !!video && !video.paused && !video.ended;
+    // In song mode the player hides the video element and shows the album art instead.
+    const isVideo = !!video && video.style.display !== 'none';
+
+    const durationSeconds = timeInfo.duration;
+
+    const cleanedTitle = titleText ? titleText.split(' • ')[0].trim() : "";
+    const cleanedArtist = artistText ? artistText.split(' • ')[0].trim() : "";
+
+    if (!cleanedTitle && !cleanedArtist) {
+        emptyReads++;
+        // Reported once, when the player bar has stayed empty long enough.
+        if (emptyReads === EMPTY_READS_BEFORE_CLEAR) {
+            postTrackUpdate({ cmd: 'trackUpdate', cleared: true }, force);
+        }
+        return;
+    }
+    emptyReads = 0;
+
+    const queuePosition = getQueuePosition();
+
+    postTrackUpdate({
+        cmd: 'trackUpdate',
+        title: cleanedTitle || null,
+        artist: cleanedArtist || null,
+        album: albumText || null,
+        album_art: albumArtUrl || null,
+        video_id: videoId || null,
+        duration: durationSeconds,
+        position: positionSeconds,
+        is_playing: isPlaying,
+        is_video: isVideo,
+        explicit: isExplicit,
+        liked: likeStatus ? likeStatus === 'LIKE' : null,
+        queue_index: queuePosition?.index ?? null,
+        queue_total: queuePosition?.total ?? null,
+        volume: getVolume(),
+        account_tier: tier
+    }, force);
+}
+
+// YouTube Music's "Your browser is outdated" banner. It has no stable
+// element of its own, so promo bars are also matched by their text.
+const BROWSER_BANNER_SELECTORS = 'ytmusic-browser-upgrade-renderer, #browser-upgrade-banner';
+const PROMO_BAR_SELECTORS = 'ytmusic-mealbar-promo-renderer, tp-yt-paper-toast, ytmusic-notification-action-renderer';
+const BROWSER_BANNER_PATTERN = /browser (is )?(outdated|out of date|no longer supported|not supported)|update your browser/i;
+// Set from config.toml to hide the banner instead of just reporting it.
+let suppressBrowserBanner = false;
+let browserBannerReported = false;
+
+window.__ytuneSuppressBrowserBanner = function () {
+    suppressBrowserBanner = true;
+};
+
+function findBrowserBanner() {
+    const banner = document.querySelector(BROWSER_BANNER_SELECTORS);
+    if (banner) return banner;
+    return Array.from(document.querySelectorAll(PROMO_BAR_SELECTORS))
+        .find(bar => BROWSER_BANNER_PATTERN.test(bar.innerText || '')) || null;
+}
+
+function checkBrowserBanner() {
+    const banner = findBrowserBanner();
+    if (!banner) return;
+
+    if (!browserBannerReported) {
+        browserBannerReported = true;
+        window.ipc.postMessage(JSON.stringify({ cmd: 'browserUpdateBanner', suppressed: suppressBrowserBanner }));
+    }
+    if (suppressBrowserBanner) {
+        // Dismissing keeps YTM's own state consistent; hiding covers banners without a button.
+        banner.querySelector('#dismiss-button button, .dismiss-button, button[aria-label="Close"]')?.click();
+        banner.style.setProperty('display', 'none', 'important');
+    }
+}
+
+// YouTube Music's error screens: the player's "An error occurred" overlay
+// and the page shown when the connection is gone.
+const PLAYER_ERROR_SELECTORS = '#movie_player .ytp-error, yt-player-error-message-renderer, ytmusic-player-error-message-renderer';
+const OFFLINE_PAGE_SELECTORS = 'ytmusic-offline-page, ytmusic-browse-response[is-offline], #offline-message';
+// Consecutive checks an error must last before it is reported, so a
+// screen flashing by during navigation doesn't clear the presence.
+const ERROR_CHECKS_BEFORE_REPORT = 2;
+let playerErrorChecks = 0;
+let playerErrorReported = false;
+
+function visibleElement(selectors) {
+    return Array.from(document.querySelectorAll(selectors)).find(el => el.offsetParent !== null) || null;
+}
+
+// 'offline', 'playback', or null when playback looks fine.
+function detectPlayerError() {
+    if (!navigator.onLine || visibleElement(OFFLINE_PAGE_SELECTORS)) return 'offline';
+    if (visibleElement(PLAYER_ERROR_SELECTORS)) return 'playback';
+    return null;
+}
+
+function checkPlayerError() {
+    const reason = detectPlayerError();
+    if (reason) {
+        playerErrorChecks++;
+        if (playerErrorChecks === ERROR_CHECKS_BEFORE_REPORT) {
+            playerErrorReported = true;
+            window.ipc.postMessage(JSON.stringify({ cmd: 'playerError', error: true, reason }));
+        }
+        return;
+    }
+    playerErrorChecks = 0;
+    if (playerErrorReported) {
+        playerErrorReported = false;
+        window.ipc.postMessage(JSON.stringify({ cmd: 'playerError', error: false }));
+        // Brings the presence back without waiting for the next tick.
+        getTrackInfo(true);
+    }
+}
+
+const FOCUS_MODE_STYLE_ID = 'ytune-focus-mode';
+const FOCUS_MODE_CSS = `
+    ytmusic-nav-bar, .ytmusic-nav-bar, #nav-bar-background, #guide-wrapper,
+    ytmusic-guide-renderer, ytmusic-mini-guide-renderer,
+    ytmusic-browse-page, ytmusic-search-page { display: none !important; }
+    body { background: #0f0f0f; }
+`;
+
+window.__ytuneSetFocusMode = function (enabled) {
+    // The CSS would restyle the sign-in pages too.
+    if (!YTUNE_ON_YTM) return;
+
+    const existing = document.getElementById(FOCUS_MODE_STYLE_ID);
+    if (enabled && !existing) {
+        const style = document.createElement('style');
+        style.id = FOCUS_MODE_STYLE_ID;
+        style.textContent = FOCUS_MODE_CSS;
+        (document.head || document.documentElement).appendChild(style);
+    } else if (!enabled && existing) {
+        existing.remove();
+    }
+};
+
+// Concert mode: a full-screen card with the album art, title and artist that
+// fades in once the mouse has been idle for a while and out again when it
+// moves, like a TV screensaver. F11 toggles it; so does setting
+// window.ytune_concertMode.
+const CONCERT_MODE_ID = 'ytune-concert-mode';
+const CONCERT_MODE_IDLE_MS = 30000;
+const CONCERT_MODE_CSS = `
+    #${CONCERT_MODE_ID} { position: fixed; inset: 0; z-index: 2147483647; background: #000; overflow: hidden;
+        opacity: 0; pointer-events: none; transition: opacity 1s ease; font-family: Roboto, "Segoe UI", sans-serif; }
+    #${CONCERT_MODE_ID}.visible { opacity: 1; pointer-events: auto; cursor: none; }
+    #${CONCERT_MODE_ID} .backdrop { position: absolute; inset: -10%; background-size: cover; background-position: center;
+        filter: blur(60px) brightness(0.35); }
+    #${CONCERT_MODE_ID} .content { position: relative; height: 100%; display: flex; flex-direction: column;
+        align-items: center; justify-content: center; gap: 3vh; color: #fff; text-align: center; padding: 0 5vw; }
+    #${CONCERT_MODE_ID} img { width: min(60vh, 80vw); height: min(60vh, 80vw); object-fit: cover; border-radius: 1vh;
+        box-shadow: 0 2vh 8vh rgba(0, 0, 0, 0.6); }
+    #${CONCERT_MODE_ID} img:not([src]) { visibility: hidden; }
+    #${CONCERT_MODE_ID} .title { font-size: 6vh; font-weight: 500; line-height: 1.1; }
+    #${CONCERT_MODE_ID} .artist { font-size: 3.5vh; color: #ccc; }
+`;
+const YTUNE_ON_PLAYER = YTUNE_ON_YTM || location.hostname === 'www.youtube.com';
+let concertModeEnabled = false;
+let concertModeIdleTimer = null;
+let concertTrack = null;
+// Full-size art for the track, from ytune; the player bar's thumbnail
+// stands in until it arrives.
+let concertArt = { source: null, url: null };
+
+window.__ytuneSetConcertArt = function (source, url) {
+    concertArt = { source, url };
+    renderConcertOverlay();
+};
+
+function concertOverlay() {
+    let overlay = document.getElementById(CONCERT_MODE_ID);
+    if (overlay) return overlay;
+
+    const style = document.createElement('style');
+    style.textContent = CONCERT_MODE_CSS;
+    (document.head || document.documentElement).appendChild(style);
+
+    overlay = document.createElement('div');
+    overlay.id = CONCERT_MODE_ID;
+    overlay.innerHTML = '<div class="backdrop"></div><div class="content"><img alt=""><div class="title"></div><div class="artist"></div></div>';
+    document.documentElement.appendChild(overlay);
+    renderConcertOverlay(overlay);
+    return overlay;
+}
+
+function renderConcertOverlay(overlay = document.getElementById(CONCERT_MODE_ID)) {
+    if (!overlay) return;
+    const source = concertTrack?.album_art || null;
+    const art = source && source === concertArt.source ? concertArt.url : source;
+    const img = overlay.querySelector('img');
+    if (art) {
+        if (img.getAttribute('src') !== art) img.src = art;
+    } else {
+        img.removeAttribute('src');
+    }
+    overlay.querySelector('.backdrop').style.backgroundImage = art ? `url("${art}")` : 'none';
+    overlay.querySelector('.title').textContent = concertTrack?.title || 'Nothing playing';
+    overlay.querySelector('.artist').textContent = concertTrack?.artist || '';
+}
+
+function setConcertOverlayVisible(visible) {
+    if (visible) {
+        concertOverlay().classList.add('visible');
+    } else {
+        document.getElementById(CONCERT_MODE_ID)?.classList.remove('visible');
+    }
+}
+
+function restartConcertModeIdleTimer() {
+    clearTimeout(concertModeIdleTimer);
+    if (concertModeEnabled) {
+        concertModeIdleTimer = setTimeout(() => setConcertOverlayVisible(true), CONCERT_MODE_IDLE_MS);
+    }
+}
+
+Object.defineProperty(window, 'ytune_concertMode', {
+    get: () => concertModeEnabled,
+    set: (enabled) => {
+        if (!YTUNE_ON_PLAYER) return;
+        concertModeEnabled = !!enabled;
+        setConcertOverlayVisible(false);
+        restartConcertModeIdleTimer();
+    },
+});
+
+if (YTUNE_ON_PLAYER) {
+    for (const type of ['mousemove', 'mousedown', 'wheel', 'touchstart']) {
+        document.addEventListener(type, () => {
+            if (!concertModeEnabled) return;
+            setConcertOverlayVisible(false);
+            restartConcertModeIdleTimer();
+        }, { capture: true, passive: true });
+    }
+    document.addEventListener('keydown', (event) => {
+        if (event.key !== 'F11') return;
+        event.preventDefault();
+        window.ytune_concertMode = !concertModeEnabled;
+        // Turning it on with the key shows it right away.
+        if (concertModeEnabled) setConcertOverlayVisible(true);
+    }, true);
+}
+
+// Player controls driven from the Rust side (tray, hotkeys, control APIs).
+function clickPlayerButton(selector) {
+    findPlayerBar()?.querySelector(selector)?.click();
+}
+
+function isVideoPaused() {
+    const video = document.querySelector('video');
+    return !video || video.paused;
+}
+
+const PLAYER_COMMANDS = {
+    play: () => { if (isVideoPaused()) clickPlayerButton('#play-pause-button'); },
+    pause: () => { if (!isVideoPaused()) clickPlayerButton('#play-pause-button'); },
+    playPause: () => clickPlayerButton('#play-pause-button'),
+    next: () => clickPlayerButton('.next-button'),
+    previous: () => clickPlayerButton('.previous-button'),
+    like: () => {
+        const renderer = findPlayerBar()?.querySelector('ytmusic-like-button-renderer');
+        if (!renderer || renderer.getAttribute('like-status') === 'LIKE') return;
+        (renderer.querySelector('#button-shape-like button') || renderer.querySelector('.like'))?.click();
+        setTimeout(window.__ytuneForceScrape, 500);
+    },
+    dislike: () => {
+        const renderer = findPlayerBar()?.querySelector('ytmusic-like-button-renderer');
+        if (!renderer || renderer.getAttribute('like-status') === 'DISLIKE') return;
+        (renderer.querySelector('#button-shape-dislike button') || renderer.querySelector('.dislike'))?.click();
+        setTimeout(window.__ytuneForceScrape, 500);
+    },
+    seek: ({ to, by }) => {
+        const video = document.querySelector('video');
+        if (!video || !isFinite(video.duration)) return;
+        video.currentTime = clamp(to ?? video.currentTime + by, 0, video.duration);
+        window.__ytuneForceScrape();
+    },
+    volume: ({ to, by }) => {
+        const current = getVolume();
+        if (current === null) return;
+        const level = clamp(Math.round(to ?? current + by), 0, 100);
+        const player = document.querySelector('#movie_player');
+        if (player && typeof player.setVolume === 'function') {
+            player.setVolume(level);
+        } else {
+            document.querySelector('video').volume = level / 100;
+        }
+        window.__ytuneForceScrape();
+    },
+};
+
+// Answers the watchdog's pings; a page that stops answering is reloaded.
+window.__ytunePing = function () {
+    window.ipc.postMessage(JSON.stringify({ cmd: 'pong' }));
+};
+
+// Scrapes right away instead of waiting for the next tick.
+window.__ytuneForceScrape = function () {
+    if (YTUNE_ON_YTM) getTrackInfo(true);
+};
+
+window.__ytuneCommand = function (name, argument) {
+    const command = PLAYER_COMMANDS[name];
+    if (command) command(argument);
+};
+
+if (YTUNE_ON_YTM) {
+    document.addEventListener('keydown', (event) => {
+        if (event.ctrlKey && !event.shiftKey && !event.altKey && event.key.toLowerCase() === 'f') {
+            event.preventDefault();
+            window.ipc.postMessage(JSON.stringify({ cmd: 'shortcut', action: 'toggleFocusMode' }));
+        }
+    }, true);
+
+    setInterval(() => getTrackInfo(), 5000);
+    setInterval(checkBrowserBanner, 2000);
+    setInterval(checkPlayerError, 2000);
+    window.addEventListener('online', checkPlayerError);
+
+    if (document.readyState === 'loading') {
+        document.addEventListener('DOMContentLoaded', () => setTimeout(() => getTrackInfo(), 1500));
+    } else {
+        setTimeout(() => getTrackInfo(), 1500);
+    }
+}
@@ -0,0 +1,148 @@
+(function () {
+    if (location.hostname !== 'music.youtube.com') return;
+
+    const PANEL_ID = 'ytune-queue-panel';
+    const STYLE_ID = 'ytune-queue-panel-style';
+    const MAX_ITEMS = 10;
+
+    const PANEL_CSS = `
+        #${PANEL_ID} {
+            position: fixed; top: 72px; right: 24px; z-index: 10000;
+            width: 360px; max-height: 70vh; overflow-y: auto;
+            background: rgba(24, 24, 24, 0.96); color: #fff;
+            border-radius: 8px; box-shadow: 0 8px 24px rgba(0, 0, 0, 0.6);
+            font-family: Roboto, Arial, sans-serif; font-size: 14px; padding: 8px 0;
+        }
+        #${PANEL_ID} h2 { font-size: 16px; font-weight: 500; margin: 4px 16px 8px; }
+        #${PANEL_ID} .ytune-queue-item { display: flex; align-items: center; gap: 8px; padding: 6px 16px; }
+        #${PANEL_ID} .ytune-queue-item.current { background: rgba(255, 255, 255, 0.1); }
+        #${PANEL_ID} .ytune-queue-text { flex: 1; min-width: 0; }
+        #${PANEL_ID} .ytune-queue-text div { white-space: nowrap; overflow: hidden; text-overflow: ellipsis; }
+        #${PANEL_ID} .ytune-queue-artist { color: #aaa; font-size: 12px; }
+        #${PANEL_ID} button {
+            background: #333; color: #fff; border: none; border-radius: 4px;
+            padding: 4px 8px; font-size: 12px; cursor: pointer;
+        }
+        #${PANEL_ID} button:hover { background: #555; }
+        #${PANEL_ID} .ytune-queue-empty { color: #aaa; padding: 8px 16px; }
+    `;
+
+    function queueItems() {
+        return Array.from(document.querySelectorAll('ytmusic-player-queue ytmusic-player-queue-item'));
+    }
+
+    // The queue has no public API, so actions go through each item's own
+    // context menu, the same way a user would. Entries are matched by label.
+    function runMenuAction(item, labels) {
+        const menuButton = item.querySelector('ytmusic-menu-renderer button, ytmusic-menu-renderer #button');
+        if (!menuButton) return;
+        menuButton.click();
+
+        setTimeout(() => {
+            const entries = document.querySelectorAll(
+                'ytmusic-menu-popup-renderer ytmusic-menu-service-item-renderer, ' +
+                'ytmusic-menu-popup-renderer ytmusic-menu-navigation-item-renderer'
+            );
+            const entry = Array.from(entries).find(candidate => {
+                const text = candidate.innerText.trim().toLowerCase();
+                return labels.some(label => text === label);
+            });
+            if (entry) {
+                entry.click();
+            } else {
+                document.body.dispatchEvent(new KeyboardEvent('keydown', { key: 'Escape', bubbles: true }));
+            }
+            setTimeout(renderPanel, 300);
+        }, 150);
+    }
+
+    function closePanel() {
+        document.getElementById(PANEL_ID)?.remove();
+    }
+
+    function renderPanel() {
+        let panel = document.getElementById(PANEL_ID);
+        if (!panel) return;
+        panel.textContent = '';
+
+        const heading = document.createElement('h2');
+        heading.textContent = 'Up next';
+        panel.appendChild(heading);
+
+        const items = queueItems().slice(0, MAX_ITEMS);
+        if (items.length === 0) {
+            const empty = document.createElement('div');
+            empty.className = 'ytune-queue-empty';
+            empty.textContent = 'The queue is empty.';
+            panel.appendChild(empty);
+            return;
+        }
+
+        for (const item of items) {
+            const row = document.createElement('div');
+            row.className = 'ytune-queue-item';
+            if (item.hasAttribute('selected') || item.getAttribute('play-button-state') === 'playing') {
+                row.classList.add('current');
+            }
+
+            const text = document.createElement('div');
+            text.className = 'ytune-queue-text';
+            const title = document.createElement('div');
+            title.textContent = item.querySelector('.song-title')?.innerText.trim() || '';
+            const artist = document.createElement('div');
+            artist.className = 'ytune-queue-artist';
+            artist.textContent = item.querySelector('.byline')?.innerText.trim() || '';
+            text.append(title, artist);
+
+            const playNext = document.createElement('button');
+            playNext.textContent = 'Play next';
+            playNext.addEventListener('click', () => runMenuAction(item, ['play next']));
+
+            const remove = document.createElement('button');
+            remove.textContent = 'Remove';
+            remove.addEventListener('click', () => runMenuAction(item, ['remove from queue']));
+
+            row.append(text, playNext, remove);
+            panel.appendChild(row);
+        }
+    }
+
+    function openPanel() {
+        if (!document.getElementById(STYLE_ID)) {
+            const style = document.createElement('style');
+            style.id = STYLE_ID;
+            style.textContent = PANEL_CSS;
+            document.head.appendChild(style);
+        }
+
+        const panel = document.createElement('div');
+        panel.id = PANEL_ID;
+        document.body.appendChild(panel);
+        renderPanel();
+    }
+
+    function togglePanel() {
+        if (document.getElementById(PANEL_ID)) {
+            closePanel();
+        } else {
+            openPanel();
+        }
+    }
+
+    document.addEventListener('keydown', (event) => {
+        if (event.ctrlKey && !event.shiftKey && !event.altKey && event.key.toLowerCase() === 'q') {
+            event.preventDefault();
+            togglePanel();
+        } else if (event.key === 'Escape') {
+            closePanel();
+        }
+    }, true);
+
+    document.addEventListener('mousedown', (event) => {
+        const panel = document.getElementById(PANEL_ID);
+        // Clicks inside YTM's own popup belong to a running menu action.
+        if (panel && !panel.contains(event.target) && !event.target.closest('ytmusic-menu-popup-renderer')) {
+            closePanel();
+        }
+    }, true);
+})();
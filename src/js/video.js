@@ -0,0 +1,100 @@
+// Video mode: reads regular YouTube's watch page instead of the YouTube Music
+// player bar and posts the same trackUpdate messages, with the channel as the
+// artist and the video thumbnail as the art. Runs after init.js, whose
+// helpers and post coalescing it reuses.
+(function () {
+    if (location.hostname !== 'www.youtube.com') return;
+
+    function channelName() {
+        const link = document.querySelector(
+            'ytd-watch-metadata ytd-channel-name a, #owner ytd-channel-name a, ytd-video-owner-renderer #channel-name a');
+        return link?.innerText.trim() || '';
+    }
+
+    function videoTitle() {
+        const heading = document.querySelector('ytd-watch-metadata h1 yt-formatted-string, h1.title yt-formatted-string');
+        // The heading lags behind on navigation between videos; the
+        // document title is "<title> - YouTube" and follows right away.
+        return heading?.innerText.trim() || document.title.replace(/^\(\d+\)\s*/, '').replace(/ - YouTube$/, '').trim();
+    }
+
+    function getVideoInfo(force = false) {
+        timeScrape(() => readVideoInfo(force));
+    }
+
+    function readVideoInfo(force) {
+        const videoId = location.pathname === '/watch' ? new URLSearchParams(location.search).get('v') : null;
+        const player = document.querySelector('#movie_player');
+        if (player && (player.classList.contains('ad-showing') || player.classList.contains('ad-interrupting'))) return;
+
+        const title = videoId ? videoTitle() : '';
+        const channel = videoId ? channelName() : '';
+        if (!title && !channel) {
+            emptyReads++;
+            // Browsing the home page or search results is not watching anything.
+            if (emptyReads === EMPTY_READS_BEFORE_CLEAR) {
+                postTrackUpdate({ cmd: 'trackUpdate', cleared: true }, force);
+            }
+            return;
+        }
+        emptyReads = 0;
+
+        const video = player?.querySelector('video') || document.querySelector('video');
+        // Live streams report an infinite duration.
+        const duration = video && isFinite(video.duration) ? Math.floor(video.duration) : null;
+
+        postTrackUpdate({
+            cmd: 'trackUpdate',
+            title: title || null,
+            artist: channel || null,
+            album: null,
+            album_art: `https://i.ytimg.com/vi/${videoId}/hqdefault.jpg`,
+            video_id: videoId,
+            duration: duration,
+            position: video && isFinite(video.currentTime) ? Math.floor(video.currentTime) : null,
+            is_playing: !!video && !video.paused && !video.ended,
+            is_video: true,
+            explicit: false,
+            liked: null,
+            queue_index: null,
+            queue_total: null,
+            volume: getVolume()
+        }, force);
+    }
+
+    function clickVideoButton(selector) {
+        document.querySelector(`#movie_player ${selector}`)?.click();
+    }
+
+    Object.assign(PLAYER_COMMANDS, {
+        play: () => { if (isVideoPaused()) clickVideoButton('.ytp-play-button'); },
+        pause: () => { if (!isVideoPaused()) clickVideoButton('.ytp-play-button'); },
+        playPause: () => clickVideoButton('.ytp-play-button'),
+        next: () => clickVideoButton('.ytp-next-button'),
+        // Only shown in playlists; elsewhere going back is the closest match.
+        previous: () => {
+            const button = document.querySelector('#movie_player .ytp-prev-button');
+            if (button && button.offsetParent !== null) button.click();
+            else history.back();
+        },
+        like: () => {
+            const button = document.querySelector('ytd-watch-metadata like-button-view-model button');
+            if (button && button.getAttribute('aria-pressed') !== 'true') button.click();
+        },
+        dislike: () => {
+            const button = document.querySelector('ytd-watch-metadata dislike-button-view-model button');
+            if (button && button.getAttribute('aria-pressed') !== 'true') button.click();
+        },
+    });
+
+    window.__ytuneForceScrape = function () {
+        getVideoInfo(true);
+    };
+
+    setInterval(() => getVideoInfo(), 5000);
+    if (document.readyState === 'loading') {
+        document.addEventListener('DOMContentLoaded', () => setTimeout(() => getVideoInfo(), 1500));
+    } else {
+        setTimeout(() => getVideoInfo(), 1500);
+    }
+})();
@@ -0,0 +1,599 @@
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    config::Config,
+    events::{TrackEvent, TrackEventKind},
+    love_sync::LoveSync,
+    metrics::{Counter, Metrics},
+    scrobble_queue::ScrobbleQueue,
+    scrobble_rules::{ListenTimer, ScrobbleRules},
+    status::AppStatus,
+    LastTrackInfo,
+};
+
+const API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+const AUTH_URL: &str = "https://www.last.fm/api/auth/";
+
+/// Credentials baked in at build time; set `YTUNE_LASTFM_API_KEY` and
+/// `YTUNE_LASTFM_API_SECRET` when compiling release builds.
+const API_KEY: Option<&str> = option_env!("YTUNE_LASTFM_API_KEY");
+const API_SECRET: Option<&str> = option_env!("YTUNE_LASTFM_API_SECRET");
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const AUTH_POLL_INTERVAL: Duration = Duration::from_secs(3);
+const AUTH_POLL_ATTEMPTS: u32 = 100;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30 * 60);
+/// How often a scrobbler whose session expired looks for a new one.
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Most scrobbles `track.scrobble` accepts in one call.
+const MAX_BATCH_SIZE: usize = 50;
+
+const ERROR_INVALID_SESSION: u32 = 9;
+const ERROR_SERVICE_OFFLINE: u32 = 11;
+const ERROR_TOKEN_NOT_AUTHORIZED: u32 = 14;
+const ERROR_TEMPORARILY_UNAVAILABLE: u32 = 16;
+const ERROR_RATE_LIMITED: u32 = 29;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request never got an answer from Last.fm.
+    Transport(String),
+    /// Last.fm answered with an error object.
+    Api { code: u32, message: String },
+}
+
+impl Error {
+    fn is_retryable(&self) -> bool {
+        match self {
+            Error::Transport(_) => true,
+            Error::Api { code, .. } => matches!(
+                *code,
+                ERROR_SERVICE_OFFLINE | ERROR_TEMPORARILY_UNAVAILABLE | ERROR_RATE_LIMITED
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(message) => write!(f, "request failed: {}", message),
+            Error::Api { code, message } => write!(f, "error {}: {}", code, message),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    pub api_key: String,
+    pub api_secret: String,
+}
+
+impl Credentials {
+    /// Config values override the build-time constants. Returns `None` when
+    /// no API key is available at all.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let api_key = config.lastfm_api_key.as_deref().or(API_KEY)?;
+        let api_secret = config.lastfm_api_secret.as_deref().or(API_SECRET)?;
+        Some(Credentials {
+            api_key: api_key.to_string(),
+            api_secret: api_secret.to_string(),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub key: String,
+    pub user_name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Scrobble {
+    pub artist: String,
+    pub track: String,
+    pub album: Option<String>,
+    pub duration_sec: Option<u64>,
+    /// Unix time in seconds at which the play started.
+    pub timestamp: u64,
+}
+
+impl Scrobble {
+    fn from_track(track: &LastTrackInfo, timestamp: u64) -> Option<Self> {
+        Some(Scrobble {
+            artist: track.artist.clone()?,
+            track: track.title.clone()?,
+            album: track.album.clone(),
+            duration_sec: track.duration_sec,
+            timestamp,
+        })
+    }
+}
+
+/// A `track.love` or `track.unlove` call.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Love {
+    pub artist: String,
+    pub track: String,
+    pub loved: bool,
+}
+
+pub struct Client {
+    credentials: Credentials,
+    session_key: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl Client {
+    pub fn new(credentials: Credentials, session_key: Option<String>) -> Self {
+        Client {
+            credentials,
+            session_key,
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+        }
+    }
+
+    /// Uses `session_key` from now on, after the user reconnected.
+    pub fn set_session_key(&mut self, session_key: String) {
+        self.session_key = Some(session_key);
+    }
+
+    pub fn get_token(&self) -> Result<String, Error> {
+        let response = self.call("auth.getToken", Vec::new())?;
+        string_field(&response, &["token"])
+    }
+
+    pub fn get_session(&self, token: &str) -> Result<Session, Error> {
+        let response = self.call("auth.getSession", vec![("token", token.to_string())])?;
+        Ok(Session {
+            key: string_field(&response, &["session", "key"])?,
+            user_name: string_field(&response, &["session", "name"]).unwrap_or_default(),
+        })
+    }
+
+    pub fn auth_url(&self, token: &str) -> String {
+        format!("{}?api_key={}&token={}", AUTH_URL, self.credentials.api_key, token)
+    }
+
+    pub fn update_now_playing(&self, scrobble: &Scrobble) -> Result<(), Error> {
+        let mut params = vec![
+            ("artist", scrobble.artist.clone()),
+            ("track", scrobble.track.clone()),
+        ];
+        if let Some(album) = &scrobble.album {
+            params.push(("album", album.clone()));
+        }
+        if let Some(duration) = scrobble.duration_sec {
+            params.push(("duration", duration.to_string()));
+        }
+        self.call("track.updateNowPlaying", params).map(|_| ())
+    }
+
+    /// Submits up to [`MAX_BATCH_SIZE`] scrobbles in one call, using the
+    /// indexed `artist[i]`, `track[i]`, ... parameters.
+    pub fn scrobble(&self, scrobbles: &[Scrobble]) -> Result<(), Error> {
+        let mut params = Vec::new();
+        for (i, scrobble) in scrobbles.iter().enumerate() {
+            params.push((format!("artist[{}]", i), scrobble.artist.clone()));
+            params.push((format!("track[{}]", i), scrobble.track.clone()));
+            params.push((format!("timestamp[{}]", i), scrobble.timestamp.to_string()));
+            if let Some(album) = &scrobble.album {
+                params.push((format!("album[{}]", i), album.clone()));
+            }
+            if let Some(duration) = scrobble.duration_sec {
+                params.push((format!("duration[{}]", i), duration.to_string()));
+            }
+        }
+        let params = params.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+        self.call("track.scrobble", params).map(|_| ())
+    }
+
+    /// Loves or unloves a track, depending on `love.loved`.
+    pub fn set_loved(&self, love: &Love) -> Result<(), Error> {
+        let method = if love.loved { "track.love" } else { "track.unlove" };
+        let params = vec![("artist", love.artist.clone()), ("track", love.track.clone())];
+        self.call(method, params).map(|_| ())
+    }
+
+    /// Signs and POSTs a method call. Every write and auth method needs a
+    /// signature, so all calls are signed.
+    fn call(&self, method: &str, mut params: Vec<(&str, String)>) -> Result<Value, Error> {
+        params.push(("method", method.to_string()));
+        params.push(("api_key", self.credentials.api_key.clone()));
+        if let Some(session_key) = &self.session_key {
+            params.push(("sk", session_key.clone()));
+        }
+        let signature = sign(&params, &self.credentials.api_secret);
+        params.push(("api_sig", signature));
+        params.push(("format", "json".to_string()));
+
+        let form: Vec<(&str, &str)> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let body = match self.agent.post(API_URL).send_form(&form) {
+            Ok(response) => response.into_string(),
+            // Last.fm reports API errors with a 4xx status and a JSON body.
+            Err(ureq::Error::Status(_, response)) => response.into_string(),
+            Err(e) => return Err(Error::Transport(e.to_string())),
+        }
+        .map_err(|e| Error::Transport(e.to_string()))?;
+
+        let value: Value =
+            serde_json::from_str(&body).map_err(|e| Error::Transport(e.to_string()))?;
+        if let Some(code) = value.get("error").and_then(|v| v.as_u64()) {
+            return Err(Error::Api {
+                code: code as u32,
+                message: value
+                    .get("message")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+            });
+        }
+        Ok(value)
+    }
+}
+
+/// `api_sig` is the MD5 of every parameter sorted by name and concatenated
+/// as name+value, followed by the shared secret.
+fn sign(params: &[(&str, String)], secret: &str) -> String {
+    let mut sorted: Vec<&(&str, String)> = params.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut payload = String::new();
+    for (key, value) in sorted {
+        payload.push_str(key);
+        payload.push_str(value);
+    }
+    payload.push_str(secret);
+    format!("{:x}", md5::compute(payload.as_bytes()))
+}
+
+fn string_field(value: &Value, path: &[&str]) -> Result<String, Error> {
+    path.iter()
+        .try_fold(value, |value, key| value.get(key))
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| Error::Transport(format!("missing '{}' in response", path.join("."))))
+}
+
+/// Runs the desktop auth flow: opens the authorization page in the browser
+/// and polls until the user has approved ytune or the attempt times out.
+pub fn authorize(credentials: Credentials) -> Result<Session, Error> {
+    let client = Client::new(credentials, None);
+    let token = client.get_token()?;
+
+    let auth_url = client.auth_url(&token);
+    if let Err(e) = open::that(&auth_url) {
+        log::warn!("Failed to open the browser ({}). Visit {} to authorize ytune.", e, auth_url);
+    }
+
+    for _ in 0..AUTH_POLL_ATTEMPTS {
+        thread::sleep(AUTH_POLL_INTERVAL);
+        match client.get_session(&token) {
+            Ok(session) => return Ok(session),
+            Err(Error::Api { code: ERROR_TOKEN_NOT_AUTHORIZED, .. }) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(Error::Transport("timed out waiting for authorization".to_string()))
+}
+
+/// Runs the auth flow in a terminal: the user confirms with Enter once they
+/// have approved ytune in the browser, then the token is exchanged.
+pub fn login_interactive(credentials: Credentials) -> Result<Session, Error> {
+    let client = Client::new(credentials, None);
+    let token = client.get_token()?;
+
+    let auth_url = client.auth_url(&token);
+    println!("Authorize ytune on Last.fm: {}", auth_url);
+    if let Err(e) = open::that(&auth_url) {
+        println!("Could not open the browser ({}); open the link above manually.", e);
+    }
+
+    print!("Press Enter once you have allowed access… ");
+    let _ = io::stdout().flush();
+    let mut line = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| Error::Transport(e.to_string()))?;
+
+    client.get_session(&token)
+}
+
+/// Consumes track events, keeping Last.fm's now-playing status current,
+/// scrobbling plays that `rules` accept and mirroring likes as loves.
+/// Scrobbles and loves go through the persistent queue, so ones that fail
+/// are retried with exponential backoff, including after a restart. When
+/// Last.fm stops accepting the session key, everything stays queued,
+/// `on_session_expired` is called, and sending resumes once a new key comes
+/// in on `session_keys`. Returns when the event bus is closed.
+#[allow(clippy::too_many_arguments)]
+pub fn run_scrobbler(
+    mut client: Client,
+    rules: ScrobbleRules,
+    mut love_sync: LoveSync,
+    status: Arc<Mutex<AppStatus>>,
+    metrics: Arc<Metrics>,
+    events: Receiver<TrackEvent>,
+    session_keys: Receiver<String>,
+    on_session_expired: impl Fn(),
+) {
+    let mut pending = ScrobbleQueue::load();
+    let mut schedule = FlushSchedule::default();
+    let mut play_started_at = None;
+    let mut scrobbled = false;
+    let mut listen_timer = ListenTimer::default();
+    // Plays queued by an earlier run go out without waiting for the first event.
+    let mut flush_wanted = true;
+
+    loop {
+        while let Ok(session_key) = session_keys.try_recv() {
+            log::info!("Sending queued Last.fm submissions with the new session");
+            client.set_session_key(session_key);
+            schedule = FlushSchedule::default();
+            flush_wanted = true;
+        }
+        let has_queued = !pending.is_empty() || pending.has_loves();
+        if flush_wanted && has_queued && schedule.is_due(Instant::now()) {
+            let outcome = flush(&client, &mut pending, &status, &metrics);
+            schedule.record(outcome, Instant::now());
+            if outcome == FlushOutcome::SessionExpired {
+                log::error!(
+                    "Last.fm session is no longer valid; {} scrobble(s) stay queued until Last.fm is reconnected from the tray.",
+                    pending.len()
+                );
+                on_session_expired();
+            }
+        }
+        flush_wanted = false;
+        status.lock().unwrap().scrobbles_pending = Some(pending.len());
+
+        let timeout = [
+            love_sync.time_until_due(),
+            schedule.time_until_retry(Instant::now()),
+            schedule.session_expired.then_some(SESSION_CHECK_INTERVAL),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let event = match timeout {
+            None => match events.recv() {
+                Ok(event) => Some(event),
+                Err(_) => break,
+            },
+            Some(timeout) => match events.recv_timeout(timeout) {
+                Ok(event) => Some(event),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+        };
+
+        match event {
+            Some(event) => {
+                // Ignored tracks don't show up on Last.fm at all, not even as now playing.
+                let ignored = rules.is_ignored(&event.track) || schedule.session_expired;
+                match event.kind {
+                    TrackEventKind::Started => {
+                        play_started_at = Some(event.timestamp);
+                        scrobbled = false;
+                        if !ignored {
+                            now_playing(&client, &event.track);
+                        }
+                    }
+                    TrackEventKind::Resumed if !ignored => now_playing(&client, &event.track),
+                    TrackEventKind::Resumed => {}
+                    TrackEventKind::Paused | TrackEventKind::Position | TrackEventKind::Stopped => {}
+                }
+
+                let listened_sec = listen_timer.observe(&event);
+                if !scrobbled && rules.should_scrobble(&event.track, listened_sec) {
+                    scrobbled = true;
+                    if let Some(scrobble) = play_started_at
+                        .and_then(|started_at| Scrobble::from_track(&event.track, started_at))
+                    {
+                        pending.push(scrobble);
+                        flush_wanted = true;
+                    }
+                }
+                if event.kind == TrackEventKind::Stopped {
+                    play_started_at = None;
+                }
+
+                if let Some(love) = love_sync.observe(&event.track) {
+                    pending.push_love(love);
+                    flush_wanted = true;
+                }
+            }
+            // A retry or a debounced love is due.
+            None => flush_wanted = true,
+        }
+        if let Some(love) = love_sync.take_due() {
+            pending.push_love(love);
+            flush_wanted = true;
+        }
+    }
+}
+
+/// How far a `flush` got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FlushOutcome {
+    /// Everything queued was sent, or rejected for good.
+    Done,
+    /// A retryable error left some of it queued.
+    Retry,
+    /// Last.fm no longer takes the session key; the rest stays queued.
+    SessionExpired,
+}
+
+/// When the queue may be sent next: right away normally, only once the
+/// backoff is over after a retryable error, and not at all while the
+/// session is expired.
+#[derive(Debug)]
+struct FlushSchedule {
+    retry_delay: Duration,
+    next_retry_at: Option<Instant>,
+    session_expired: bool,
+}
+
+impl Default for FlushSchedule {
+    fn default() -> Self {
+        FlushSchedule {
+            retry_delay: INITIAL_RETRY_DELAY,
+            next_retry_at: None,
+            session_expired: false,
+        }
+    }
+}
+
+impl FlushSchedule {
+    fn is_due(&self, now: Instant) -> bool {
+        !self.session_expired && self.next_retry_at.is_none_or(|at| now >= at)
+    }
+
+    /// Time left until a retry is due, if one is waiting.
+    fn time_until_retry(&self, now: Instant) -> Option<Duration> {
+        self.next_retry_at
+            .filter(|_| !self.session_expired)
+            .map(|at| at.saturating_duration_since(now))
+    }
+
+    fn record(&mut self, outcome: FlushOutcome, now: Instant) {
+        match outcome {
+            FlushOutcome::Done => {
+                self.retry_delay = INITIAL_RETRY_DELAY;
+                self.next_retry_at = None;
+            }
+            FlushOutcome::Retry => {
+                self.next_retry_at = Some(now + self.retry_delay);
+                self.retry_delay = (self.retry_delay * 2).min(MAX_RETRY_DELAY);
+            }
+            FlushOutcome::SessionExpired => {
+                self.next_retry_at = None;
+                self.session_expired = true;
+            }
+        }
+    }
+}
+
+fn now_playing(client: &Client, track: &LastTrackInfo) {
+    let Some(scrobble) = Scrobble::from_track(track, 0) else {
+        return;
+    };
+    if let Err(e) = client.update_now_playing(&scrobble) {
+        log::warn!("Last.fm now playing update failed: {}", e);
+    }
+}
+
+/// Submits queued scrobbles in batches, oldest first, then queued loves.
+fn flush(client: &Client, pending: &mut ScrobbleQueue, status: &Mutex<AppStatus>, metrics: &Metrics) -> FlushOutcome {
+    while !pending.is_empty() {
+        let batch = pending.front(MAX_BATCH_SIZE);
+        let result = client.scrobble(&batch);
+        if result.is_err() {
+            metrics.increment(Counter::ScrobbleFailures);
+        }
+        match result {
+            Ok(()) => {
+                log::info!("Scrobbled {} track(s)", batch.len());
+                pending.remove_front(batch.len());
+            }
+            Err(e) if e.is_retryable() => {
+                log::warn!("Last.fm scrobble failed, {} queued for retry: {}", pending.len(), e);
+                return FlushOutcome::Retry;
+            }
+            Err(Error::Api { code: ERROR_INVALID_SESSION, .. }) => return FlushOutcome::SessionExpired,
+            Err(e) => {
+                log::warn!("Last.fm rejected {} scrobble(s): {}", batch.len(), e);
+                pending.remove_front(batch.len());
+            }
+        }
+    }
+
+    while let Some(love) = pending.front_love() {
+        let action = if love.loved { "Loved" } else { "Unloved" };
+        let result = client.set_loved(&love);
+        let summary = match &result {
+            Ok(()) => format!("{} {} — {}", action, love.track, love.artist),
+            Err(e) => format!("{} {} — {} failed: {}", action, love.track, love.artist, e),
+        };
+        status.lock().unwrap().last_love_sync = Some(summary);
+
+        match result {
+            Ok(()) => {
+                log::info!("{} {} — {} on Last.fm", action, love.track, love.artist);
+                pending.remove_front_love();
+            }
+            Err(e) if e.is_retryable() => {
+                log::warn!("Last.fm love sync failed, queued for retry: {}", e);
+                return FlushOutcome::Retry;
+            }
+            Err(Error::Api { code: ERROR_INVALID_SESSION, .. }) => return FlushOutcome::SessionExpired,
+            Err(e) => {
+                log::warn!("Last.fm rejected love sync for {} — {}: {}", love.track, love.artist, e);
+                pending.remove_front_love();
+            }
+        }
+    }
+    FlushOutcome::Done
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_waits_for_the_backoff_and_doubles_it() {
+        let start = Instant::now();
+        let mut schedule = FlushSchedule::default();
+        assert!(schedule.is_due(start));
+
+        schedule.record(FlushOutcome::Retry, start);
+        assert!(!schedule.is_due(start + INITIAL_RETRY_DELAY / 2));
+        assert!(schedule.is_due(start + INITIAL_RETRY_DELAY));
+        assert_eq!(schedule.time_until_retry(start), Some(INITIAL_RETRY_DELAY));
+
+        let retried_at = start + INITIAL_RETRY_DELAY;
+        schedule.record(FlushOutcome::Retry, retried_at);
+        assert!(!schedule.is_due(retried_at + INITIAL_RETRY_DELAY));
+        assert!(schedule.is_due(retried_at + INITIAL_RETRY_DELAY * 2));
+
+        schedule.record(FlushOutcome::Done, retried_at);
+        assert!(schedule.is_due(retried_at));
+        assert_eq!(schedule.time_until_retry(retried_at), None);
+        assert_eq!(schedule.retry_delay, INITIAL_RETRY_DELAY);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let now = Instant::now();
+        let mut schedule = FlushSchedule::default();
+        for _ in 0..20 {
+            schedule.record(FlushOutcome::Retry, now);
+        }
+        assert_eq!(schedule.retry_delay, MAX_RETRY_DELAY);
+        assert_eq!(schedule.time_until_retry(now), Some(MAX_RETRY_DELAY));
+    }
+
+    #[test]
+    fn expired_session_is_never_due() {
+        let now = Instant::now();
+        let mut schedule = FlushSchedule::default();
+        schedule.record(FlushOutcome::Retry, now);
+        schedule.record(FlushOutcome::SessionExpired, now);
+        assert!(!schedule.is_due(now + MAX_RETRY_DELAY));
+        assert_eq!(schedule.time_until_retry(now), None);
+    }
+}
@@ -0,0 +1,65 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Number of recent log lines kept in memory for the diagnostics report.
+const BUFFERED_LINES: usize = 200;
+
+struct BufferedLogger {
+    lines: Mutex<VecDeque<String>>,
+}
+
+static LOGGER: BufferedLogger = BufferedLogger {
+    lines: Mutex::new(VecDeque::new()),
+};
+
+impl Log for BufferedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format!(
+            "{} {:<5} {}",
+            chrono::Local::now().format("%H:%M:%S"),
+            record.level(),
+            record.args()
+        );
+        eprintln!("{}", line);
+
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == BUFFERED_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger. `YTUNE_LOG=debug` (or `trace`, `warn`, ...) changes
+/// the level, which is `info` by default.
+pub fn init() {
+    let level = std::env::var("YTUNE_LOG")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(LevelFilter::Info);
+
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// The last `count` log lines, oldest first.
+pub fn recent_lines(count: usize) -> Vec<String> {
+    let lines = LOGGER.lines.lock().unwrap();
+    lines
+        .iter()
+        .skip(lines.len().saturating_sub(count))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+use crate::{lastfm::Love, LastTrackInfo};
+
+/// How long a like status has to stay put before it is sent, so toggling
+/// back and forth ends in a single call for the final state.
+const DEBOUNCE: Duration = Duration::from_secs(10);
+
+/// Like status of the current track, as YouTube Music shows it and as
+/// Last.fm last heard about it.
+struct TrackLike {
+    artist: String,
+    title: String,
+    synced: bool,
+    wanted: bool,
+    changed_at: Instant,
+}
+
+impl TrackLike {
+    fn love(&self) -> Love {
+        Love {
+            artist: self.artist.clone(),
+            track: self.title.clone(),
+            loved: self.wanted,
+        }
+    }
+}
+
+/// Turns like-status flips seen in track events into debounced `track.love`
+/// calls, and `track.unlove` calls when `sync_unlove` is on. Only flips
+/// count: a track that was already liked when it started is left alone.
+pub struct LoveSync {
+    sync_unlove: bool,
+    current: Option<TrackLike>,
+}
+
+impl LoveSync {
+    pub fn new(sync_unlove: bool) -> Self {
+        LoveSync {
+            sync_unlove,
+            current: None,
+        }
+    }
+
+    /// Records the like status in `track`. When the track changed while a
+    /// flip of the previous one was still settling, that flip is returned
+    /// right away instead of being dropped.
+    pub fn observe(&mut self, track: &LastTrackInfo) -> Option<Love> {
+        // Last.fm matches loves by artist and title, so a guess is worse than nothing.
+        let (Some(artist), Some(title), Some(liked)) = (&track.artist, &track.title, track.liked) else {
+            return None;
+        };
+
+        if let Some(current) = self.current.as_mut() {
+            if current.artist == *artist && current.title == *title {
+                if current.wanted != liked {
+                    current.wanted = liked;
+                    current.changed_at = Instant::now();
+                }
+                return None;
+            }
+        }
+
+        let previous = self.current.replace(TrackLike {
+            artist: artist.clone(),
+            title: title.clone(),
+            synced: liked,
+            wanted: liked,
+            changed_at: Instant::now(),
+        });
+        previous
+            .filter(|previous| previous.synced != previous.wanted)
+            .and_then(|previous| self.outgoing(previous.love()))
+    }
+
+    /// The change to send once the current track's like status has settled.
+    pub fn take_due(&mut self) -> Option<Love> {
+        let current = self.current.as_mut()?;
+        if current.synced == current.wanted || current.changed_at.elapsed() < DEBOUNCE {
+            return None;
+        }
+        current.synced = current.wanted;
+        let love = current.love();
+        self.outgoing(love)
+    }
+
+    /// Time left until [`take_due`](Self::take_due) has something, if a
+    /// change is waiting.
+    pub fn time_until_due(&self) -> Option<Duration> {
+        let current = self.current.as_ref()?;
+        (current.synced != current.wanted).then(|| DEBOUNCE.saturating_sub(current.changed_at.elapsed()))
+    }
+
+    /// Unlikes are only passed on when `sync_unlove` is on.
+    fn outgoing(&self, love: Love) -> Option<Love> {
+        (love.loved || self.sync_unlove).then_some(love)
+    }
+}
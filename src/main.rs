@@ -1,139 +1,498 @@
 #![windows_subsystem = "windows"]
 
+#[cfg(any(test, all(target_os = "windows", feature = "discord")))]
+mod activity;
+mod api;
+mod art_cache;
+mod autostart;
+mod cli;
+mod config;
+mod control;
+mod diagnostics;
+#[cfg(feature = "discord")]
 mod discord_ipc;
+mod event_stream;
+mod events;
+mod export;
+mod file_output;
+mod first_run;
+mod grpc;
+mod history;
+mod hooks;
+mod hotkeys;
+#[cfg(unix)]
+mod i3status;
+mod instance;
+mod ipc_protocol;
+mod lastfm;
+mod logging;
+mod love_sync;
+#[cfg(target_os = "windows")]
+mod media_sessions;
+mod mdns;
+mod metrics;
+mod mqtt;
+mod notification;
+mod osd;
+mod pipe_server;
+#[cfg(target_os = "windows")]
+mod platform;
+mod scrobble_queue;
+mod scrobble_rules;
+mod share_card;
+mod share_session;
+mod socket_server;
+mod state;
+mod stats;
+mod stats_window;
+mod status;
+mod summary;
+mod takeout;
+mod template;
+mod theme;
+mod tray;
+mod watchdog;
+mod webhooks;
+mod youtube_url;
 
 use std::{
-    fs::File,
-    io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
     process,
-    sync::{Arc, Mutex},
+    sync::{atomic::Ordering, mpsc, Arc, Mutex},
     thread,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::Duration,
+};
+#[cfg(all(target_os = "windows", feature = "discord"))]
+use std::{
+    fs::File,
+    io,
+    time::{SystemTime, UNIX_EPOCH},
 };
 use wry::{
-    application::event_loop::{ControlFlow, EventLoop},
+    application::clipboard::Clipboard,
+    application::event::{Event, TrayEvent, WindowEvent},
+    application::event_loop::{ControlFlow, EventLoop, EventLoopProxy},
     application::window::{Icon, Window, WindowBuilder},
-    webview::WebViewBuilder,
+    webview::{Url, WebContext, WebView, WebViewBuilder},
 };
 use image::{load_from_memory_with_format, ImageFormat};
+use serde::{Deserialize, Serialize};
 
-#[cfg(target_os = "windows")]
-use crate::discord_ipc::{connect, read_message, send_handshake, set_activity};
+use crate::api::long_poll::TrackWatch;
+use crate::art_cache::ArtCache;
+use crate::cli::Command;
+use crate::config::Mode;
+use crate::control::PlayerCommand;
+use crate::event_stream::EventStream;
+use crate::events::{EventBus, PlaybackTracker};
+use crate::history::{History, Retention};
+use crate::love_sync::LoveSync;
+use crate::osd::OsdWindow;
+use crate::metrics::{Counter, Metrics};
+use crate::hotkeys::{HotkeyAction, Hotkeys};
+use crate::scrobble_rules::ScrobbleRules;
+use crate::share_card::CardRenderer;
+use crate::stats_window::{StatsRequest, StatsWindow};
+use crate::status::{AccountTier, AppStatus, DiscordStatus};
+use crate::tray::{LastFmStatus, RecentPlay, Tray, TrayAction, RECENT_PLAYS_LIMIT};
+use crate::watchdog::PageWatchdog;
 
-#[cfg(target_os = "windows")]
+#[cfg(all(target_os = "windows", feature = "discord"))]
+use crate::discord_ipc::{
+    connect, handshake_error, read_message, read_response, send_handshake, set_activity, ConnectRetry, ConnectionEvent,
+    ConnectionState,
+};
+
+const YTM_HOST: &str = "music.youtube.com";
+const YTM_URL: &str = "https://music.youtube.com";
+const YOUTUBE_URL: &str = "https://youtube.com";
+/// Where video mode plays; the bare domain redirects here.
+const YOUTUBE_HOST: &str = "www.youtube.com";
+const YOUTUBE_HOME_URL: &str = "https://www.youtube.com";
+
+#[cfg(all(target_os = "windows", feature = "discord"))]
 const CLIENT_ID: &str = "1356377176563384371";
-const INIT_JS: &str = r#"
+const INIT_JS: &str = include_str!("js/init.js");
+const QUEUE_PANEL_JS: &str = include_str!("js/queue_panel.js");
+const VIDEO_JS: &str = include_str!("js/video.js");
+/// Matches the page's own scrape timer.
+const BACKGROUND_SCRAPE_INTERVAL: Duration = Duration::from_secs(5);
+/// Art size the concert mode overlay asks of the image CDN.
+const CONCERT_ART_SIZE: u32 = 1200;
 
-    function getElementByXpath(path) {
-        try {
-            return document.evaluate(path, document, null, XPathResult.FIRST_ORDERED_NODE_TYPE, null).singleNodeValue;
-        } catch (e) { return null; }
-    }
+/// The Discord connection state, holding the pipe once connected, and its
+/// status. The status has its own lock so it can be read while the pipe is
+/// busy.
+#[cfg(all(target_os = "windows", feature = "discord"))]
+struct DiscordConnection {
+    state: Mutex<ConnectionState>,
+    status: Arc<Mutex<DiscordStatus>>,
+    metrics: Arc<Metrics>,
+}
 
-    function parseTimeToSeconds(timeStr) {
-        if (!timeStr || typeof timeStr !== 'string') return null;
+#[cfg(all(target_os = "windows", feature = "discord"))]
+impl DiscordConnection {
+    fn set_status(&self, status: DiscordStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+}
 
-        const parts = timeStr.split(':').map(Number);
-        if (parts.some(isNaN) || parts.length > 3 || parts.length < 1) return null;
+#[derive(Debug)]
+enum UserEvent {
+    ToggleFocusMode,
+    SystemThemeChanged(theme::Theme),
+    LastFmConnected(String),
+    LastFmAuthFailed,
+    /// Last.fm stopped taking the session key; plays are kept queued.
+    LastFmSessionExpired,
+    PlayerCommand(PlayerCommand),
+    RecentPlaysChanged(Vec<RecentPlay>),
+    StatsRequest(StatsRequest),
+    /// A stats page query was answered; the script shows it.
+    StatsReply(String),
+    /// Time to scrape on the page's behalf while the window is out of sight.
+    BackgroundScrape,
+    /// Time to check the page still answers.
+    PingPage,
+    /// `config.toml` was saved; the hotkeys are re-read from it.
+    ConfigChanged,
+    /// Whether the current track has a video ID the copy-link items can use.
+    TrackLinkAvailable(bool),
+    /// The current track's art changed; the page's concert mode wants it full size.
+    ConcertArt(String),
+    /// Load a YouTube Music page in the player, from the local API.
+    OpenUrl(Url),
+    /// Bring the window to the front, e.g. for a second copy of ytune.
+    ShowWindow,
+    /// Copy the current track, from the local API.
+    CopyTrack,
+    /// Copy the current track's link, from the local API.
+    CopyLink,
+    /// Play a `ytune://session/` link, for a second copy of ytune.
+    OpenSession(share_session::Session),
+    /// A track started; show it in the overlay.
+    ShowOsd(osd::OsdTrack),
+    /// The overlay's time is up, unless a later track replaced this one.
+    HideOsd(u64),
+    /// Save a share card of the current track, under the file name when given.
+    ShareCard(Option<String>),
+    /// The card's art is in; draw it.
+    DrawShareCard(share_card::CardJob),
+    /// Where the card was saved, or why it wasn't.
+    ShareCardDone(Result<PathBuf, String>),
+}
 
-        let seconds = 0;
-        if (parts.length === 3) {
-            seconds = parts[0] * 3600 + parts[1] * 60 + parts[2];
-        } else if (parts.length === 2) {
-            seconds = parts[0] * 60 + parts[1];
-        } else {
-            seconds = parts[0];
-        }
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct LastTrackInfo {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    album_art: Option<String>,
+    video_id: Option<String>,
+    duration_sec: Option<u64>,
+    position_sec: Option<u64>,
+    is_playing: bool,
+    /// A music video rather than a song is playing.
+    is_video: bool,
+    /// The player bar shows the explicit badge.
+    explicit: bool,
+    /// Like status from the player bar; `None` when there is no like button.
+    liked: Option<bool>,
+    /// 1-based position in the play queue, when the queue has rendered.
+    queue_index: Option<u32>,
+    queue_total: Option<u32>,
+    /// Player volume from 0 to 100.
+    volume: Option<u8>,
+}
 
-        return seconds > 0 ? seconds : null;
+impl LastTrackInfo {
+    /// Whether anything shown in the Discord presence changed. The position
+    /// advances on every scrape, so it is deliberately left out; it only
+    /// anchors the start timestamp when an update is sent.
+    fn presence_differs(&self, other: &LastTrackInfo) -> bool {
+        track_has_changed(self, other)
+            || self.album != other.album
+            || self.album_art.as_deref().map(album_art_key)
+                != other.album_art.as_deref().map(album_art_key)
+            || self.duration_sec != other.duration_sec
+            || self.is_playing != other.is_playing
+            || self.is_video != other.is_video
+            || self.explicit != other.explicit
+            || self.queue_index != other.queue_index
+            || self.queue_total != other.queue_total
     }
+}
+
+/// Builds the track from a `trackUpdate` message. A field that is missing or
+/// null has no value; one of the wrong type is an error naming it, since it
+/// means the page script and ytune disagree about the message.
+fn track_from_update(update: &serde_json::Value) -> Result<LastTrackInfo, String> {
+    use serde_json::Value;
+
+    let string = |value: &Value| value.as_str().map(str::to_string);
+    let small_count = |value: &Value| value.as_u64().and_then(|n| u32::try_from(n).ok());
+    let percent = |value: &Value| value.as_u64().filter(|&n| n <= 100).map(|n| n as u8);
 
-    function getTrackInfo() {
-        const playerBar = document.querySelector('ytmusic-player-bar');
-        if (!playerBar) return;
+    Ok(LastTrackInfo {
+        title: update_field(update, "title", string, "a string")?,
+        artist: update_field(update, "artist", string, "a string")?,
+        album: update_field(update, "album", string, "a string")?,
+        album_art: update_field(update, "album_art", string, "a string")?,
+        video_id: update_field(update, "video_id", string, "a string")?,
+        duration_sec: update_field(update, "duration", Value::as_u64, "a whole number of seconds")?,
+        position_sec: update_field(update, "position", Value::as_u64, "a whole number of seconds")?,
+        is_playing: update_field(update, "is_playing", Value::as_bool, "a boolean")?.unwrap_or(false),
+        is_video: update_field(update, "is_video", Value::as_bool, "a boolean")?.unwrap_or(false),
+        explicit: update_field(update, "explicit", Value::as_bool, "a boolean")?.unwrap_or(false),
+        liked: update_field(update, "liked", Value::as_bool, "a boolean")?,
+        queue_index: update_field(update, "queue_index", small_count, "a whole number")?,
+        queue_total: update_field(update, "queue_total", small_count, "a whole number")?,
+        volume: update_field(update, "volume", percent, "a number from 0 to 100")?,
+    })
+}
 
-        const titleEl = playerBar.querySelector('.title.style-scope.ytmusic-player-bar');
-        const artistContainer = playerBar.querySelector('.byline.style-scope.ytmusic-player-bar');
-        const albumArtEl = playerBar.querySelector('img');
-        const durationEl = playerBar.querySelector('#progress-bar .time-info.style-scope.ytmusic-player-bar');
+fn update_field<T>(
+    update: &serde_json::Value,
+    key: &str,
+    read: impl Fn(&serde_json::Value) -> Option<T>,
+    expected: &str,
+) -> Result<Option<T>, String> {
+    match update.get(key) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(value) => read(value)
+            .map(Some)
+            .ok_or_else(|| format!("'{}' not {}: {}", key, expected, value)),
+    }
+}
 
-        const titleText = titleEl?.innerText.trim() || "";
-        let artistText = "";
-        if (artistContainer) {
-            const artistNodes = Array.from(artistContainer.querySelectorAll('yt-formatted-string a, yt-formatted-string'));
-            artistText = artistNodes.map(node => node.innerText.trim()).filter(text => text && !/^\d{4}$/.test(text)).join(', ');
-            artistText = artistText ? artistText.trim() : "";
+/// Whether `next` is a different track from `prev`, going by what identifies
+/// one rather than by every scraped field.
+fn track_has_changed(prev: &LastTrackInfo, next: &LastTrackInfo) -> bool {
+    prev.title != next.title || prev.artist != next.artist || prev.video_id != next.video_id
+}
+
+/// The album art URL without its size. The CDNs serve one image under many
+/// sizes, as a query on `i.ytimg.com` or a `=w60-h60-l90-rj` style suffix on
+/// `googleusercontent.com`, and the player bar switches between them.
+fn album_art_key(url: &str) -> &str {
+    let url = url.split_once('?').map_or(url, |(base, _)| base);
+    match url.rsplit_once('=') {
+        Some((base, size))
+            if size.starts_with(['w', 's', 'h'])
+                && size[1..].starts_with(|c: char| c.is_ascii_digit()) =>
+        {
+            base
         }
+        _ => url,
+    }
+}
+
+fn main() -> wry::Result<()> {
+
+    logging::init();
+
+    let args = cli::parse(std::env::args()).unwrap_or_else(|e| {
+        cli::attach_parent_console();
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+    config::init_portable(args.portable);
+    config::init_override_dir();
+    let command = args.command;
+    let start_minimized = args.minimized;
+    let open_session = args.open_session;
+    let config = config::load();
 
-        const albumArtUrl = albumArtEl?.getAttribute("src") || "";
+    if !matches!(command, Command::Run) {
+        cli::attach_parent_console();
+        process::exit(run_cli_command(command, &config));
+    }
 
-        let durationSeconds = null;
-        if (durationEl && durationEl.innerText) {
-            const timeText = durationEl.innerText.split('/').pop().trim();
-            if (timeText && timeText.toLowerCase() !== "live") {
-                durationSeconds = parseTimeToSeconds(timeText);
+    // A second copy would fight the first over the webview profile and the
+    // Discord presence, so it only brings the running one to the front:
+    // through the control server when it's on, which works on every
+    // platform, else through the single-instance lock.
+    if config.pipe_server_enabled {
+        // A session link opens in the running copy instead.
+        let request = match &open_session {
+            Some(session) => {
+                serde_json::json!({ "v": api::types::API_VERSION, "cmd": "open_session", "uri": session.to_uri() })
             }
+            None => serde_json::json!({ "v": api::types::API_VERSION, "cmd": "show" }),
+        };
+        if matches!(ipc_protocol::request(&config, &request), Ok(response) if response["ok"] == true) {
+            log::info!("ytune is already running, exiting");
+            process::exit(0);
         }
+    }
+    let _instance_lock = match instance::acquire_instance_lock() {
+        Ok(lock) => lock,
+        Err(e) => {
+            // Only the control server can hand the running copy a link.
+            if open_session.is_some() {
+                let body = if config.pipe_server_enabled {
+                    "The running ytune didn't answer, so the session link wasn't opened."
+                } else {
+                    "Set pipe_server_enabled = true in config.toml to open session links in the running ytune."
+                };
+                log::warn!("{}: {}", e, body);
+                notification::show("Session link not opened", body);
+            }
+            log::info!("{}, exiting", e);
+            process::exit(0);
+        }
+    };
+
+    // Before anything reads the settings the answers go into.
+    let first_run_answers = first_run::run(&config);
+    let config = if first_run_answers.is_some() { config::load() } else { config };
 
-        const cleanedTitle = titleText ? titleText.split(' • ')[0].trim() : "";
-        const cleanedArtist = artistText ? artistText.split(' • ')[0].trim() : "";
+    let window_icon = load_window_icon(config.window_icon_path.as_deref());
 
-        if (cleanedTitle || cleanedArtist) {
+    let discord_status = Arc::new(Mutex::new(DiscordStatus::Disabled));
+    let metrics = Arc::new(Metrics::default());
+    #[cfg(all(target_os = "windows", feature = "discord"))]
+    let discord_connection = Arc::new(DiscordConnection {
+        state: Mutex::new(ConnectionState::Disconnected),
+        status: Arc::clone(&discord_status),
+        metrics: Arc::clone(&metrics),
+    });
+    #[cfg(all(target_os = "windows", feature = "discord"))]
+    let discord_retry = ConnectRetry::new(config.discord_connect_timeout_ms, config.discord_connect_attempts);
+    let last_track = Arc::new(Mutex::new(LastTrackInfo::default()));
+    let track_watch = Arc::new(TrackWatch::default());
+    let app_status = Arc::new(Mutex::new(AppStatus::default()));
+    let event_bus = Arc::new(EventBus::default());
+    let playback_tracker = Arc::new(Mutex::new(PlaybackTracker::default()));
+    let mut event_consumers = Vec::new();
+    let event_loop = EventLoop::<UserEvent>::with_user_event();
 
-            window.ipc.postMessage(JSON.stringify({
-                cmd: 'trackUpdate',
-                title: cleanedTitle || null,
-                artist: cleanedArtist || null,
-                album_art: albumArtUrl || null,
-                duration: durationSeconds
+    let history = if config.history_enabled {
+        open_history(&config)
+            .map_err(|e| log::warn!("Play history disabled: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    if let Some(Err(e)) = history.as_ref().map(History::prune) {
+        log::warn!("Failed to prune play history: {}", e);
+    }
+    let history_proxy = event_loop.create_proxy();
+    let events = event_bus.subscribe();
+    match history {
+        Some(history) => {
+            let on_change = move |history: &History| {
+                let recent_plays = recent_plays(history);
+                let _ = history_proxy.send_event(UserEvent::RecentPlaysChanged(recent_plays));
+            };
+            event_consumers.push(thread::spawn(move || {
+                history::run_recorder(history, events, on_change)
             }));
         }
+        // The tray still lists what was played since startup.
+        None => {
+            let on_change = move |recent_plays: Vec<RecentPlay>| {
+                let _ = history_proxy.send_event(UserEvent::RecentPlaysChanged(recent_plays));
+            };
+            event_consumers.push(thread::spawn(move || tray::run_session_recents(events, on_change)));
+        }
     }
 
-    setInterval(getTrackInfo, 5000);
+    let counter_metrics = Arc::clone(&metrics);
+    let events = event_bus.subscribe();
+    event_consumers.push(thread::spawn(move || metrics::run_counter(&counter_metrics, events)));
 
-    if (document.readyState === 'loading') {
-        document.addEventListener('DOMContentLoaded', () => setTimeout(getTrackInfo, 1500));
-    } else {
-         setTimeout(getTrackInfo, 1500);
+    #[cfg(target_os = "windows")]
+    let has_media_sessions = platform::log_capabilities(&config);
+    if config.pause_when_others_play {
+        #[cfg(target_os = "windows")]
+        if has_media_sessions {
+            let last_track = Arc::clone(&last_track);
+            let proxy = event_loop.create_proxy();
+            thread::spawn(move || media_sessions::run_pause_for_others(last_track, proxy));
+        }
+        #[cfg(not(target_os = "windows"))]
+        log::warn!("pause_when_others_play only works on Windows");
     }
-"#;
 
-#[cfg(target_os = "windows")]
-type DiscordConnectionState = Option<(File, u32)>;
+    if config.summary.enabled {
+        match open_history(&config) {
+            Ok(history) => {
+                let summary_config = config.summary.clone();
+                thread::spawn(move || summary::run(summary_config, history));
+            }
+            Err(e) => log::warn!("Listening summary disabled: {}", e),
+        }
+    }
 
-#[derive(Clone, PartialEq, Debug, Default)]
-struct LastTrackInfo {
-    title: Option<String>,
-    artist: Option<String>,
-    album_art: Option<String>,
-    duration_sec: Option<u64>,
-}
+    if let Some(path) = config.file_output.path.clone() {
+        let file_output_config = config.file_output.clone();
+        let art_size = config.notification_album_art_size;
+        let events = event_bus.subscribe();
+        event_consumers.push(thread::spawn(move || {
+            file_output::run_writer(file_output_config, path, art_size, events)
+        }));
+    }
 
-fn main() -> wry::Result<()> {
+    match file_output::current_track_path() {
+        Some(path) => {
+            let events = event_bus.subscribe();
+            event_consumers.push(thread::spawn(move || file_output::run_track_writer(path, events)));
+        }
+        None => log::warn!("No directory for the current track file; --print-track won't work"),
+    }
 
-    let window_icon = load_window_icon();
+    if !config.webhook.urls.is_empty() {
+        let webhook_config = config.webhook.clone();
+        let events = event_bus.subscribe();
+        event_consumers.push(thread::spawn(move || webhooks::run_sender(webhook_config, events)));
+    }
 
-    #[cfg(target_os = "windows")]
-    let discord_connection: Arc<Mutex<DiscordConnectionState>> = Arc::new(Mutex::new(None));
-    let last_track = Arc::new(Mutex::new(LastTrackInfo::default()));
+    if !config.hooks.is_empty() {
+        let hooks_config = config.hooks.clone();
+        let events = event_bus.subscribe();
+        event_consumers.push(thread::spawn(move || hooks::run_hooks(hooks_config, events)));
+    }
 
-    #[cfg(target_os = "windows")]
-    {
+    let lastfm_credentials = lastfm::Credentials::from_config(&config);
+    // Hands a renewed session key to the running scrobbler.
+    let mut lastfm_session_sender = None;
+    let lastfm_status = match (&lastfm_credentials, &config.lastfm_session_key) {
+        (None, _) => LastFmStatus::Unavailable,
+        (Some(credentials), Some(session_key)) => {
+            let (consumer, session_sender) = start_scrobbler(
+                &event_bus,
+                &app_status,
+                &metrics,
+                &config,
+                credentials.clone(),
+                session_key.clone(),
+                event_loop.create_proxy(),
+            );
+            event_consumers.push(consumer);
+            lastfm_session_sender = Some(session_sender);
+            LastFmStatus::Connected
+        }
+        (Some(_), None) => LastFmStatus::Disconnected,
+    };
+
+    #[cfg(all(target_os = "windows", feature = "discord"))]
+    if config.discord_enabled {
         let conn_arc_clone = Arc::clone(&discord_connection);
         let client_id_clone = CLIENT_ID.to_string();
 
         thread::spawn(move || {
             let pid = process::id();
-            match connect_and_handshake(&client_id_clone, pid) {
-                Ok(file) => {
-
-                    let mut guard = conn_arc_clone.lock().unwrap();
-                    *guard = Some((file, pid));
+            match connect_and_handshake(&conn_arc_clone, &client_id_clone, pid, discord_retry) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    log::info!("Discord is not running. Will retry on track update.");
                 }
                 Err(e) => {
-                    eprintln!(
-                        "Initial Discord connection failed: {:?}. Will retry on track update.",
+                    log::warn!(
+                        "Initial Discord connection failed: {}. Will retry on track update.",
                         e
                     );
                 }
@@ -141,232 +500,1513 @@ fn main() -> wry::Result<()> {
         });
     }
 
-    let event_loop = EventLoop::new();
+    let mut app_state = state::load();
+
+    let start_hidden = start_minimized || config.start_minimized;
     let window = WindowBuilder::new()
         .with_title("ytune")
-        .with_window_icon(window_icon)
+        .with_window_icon(window_icon.clone())
+        .with_visible(!start_hidden)
         .build(&event_loop)?;
 
-    #[cfg(target_os = "windows")]
-    let conn_arc_clone_ipc = Arc::clone(&discord_connection);
+    // Only touches the system when the config asks for a change.
+    if let Some(wanted) = config.start_with_system {
+        if wanted != autostart::is_enabled() {
+            if let Err(e) = autostart::set_enabled(wanted) {
+                log::warn!("Failed to update start with system: {}", e);
+            }
+        }
+    }
+    let autostart = autostart::SUPPORTED.then(autostart::is_enabled);
+
+    let stats_window_icon = window_icon.clone();
+    let mut tray = window_icon.and_then(|icon| {
+        Tray::build(&event_loop, icon, app_state.focus_mode, autostart, lastfm_status)
+            .map_err(|e| log::warn!("Failed to create tray icon: {}", e))
+            .ok()
+    });
+    // Without a tray there would be no way to bring a hidden window back.
+    if start_hidden && tray.is_none() {
+        window.set_minimized(true);
+        window.set_visible(true);
+    }
+    let mut hotkeys = Hotkeys::register(&event_loop, &config);
+    let config_proxy = event_loop.create_proxy();
+    config::watch(move || {
+        let _ = config_proxy.send_event(UserEvent::ConfigChanged);
+    });
+    let hotkey_proxy = event_loop.create_proxy();
+
+    #[cfg(all(target_os = "windows", feature = "discord"))]
+    let presence_sender = spawn_presence_worker(Arc::clone(&discord_connection), discord_retry);
+    #[cfg(all(target_os = "windows", feature = "discord"))]
+    let ipc_config = config.clone();
     let last_track_clone = Arc::clone(&last_track);
+    let track_watch_clone = Arc::clone(&track_watch);
+    let app_status_clone = Arc::clone(&app_status);
+    let event_bus_clone = Arc::clone(&event_bus);
+    let playback_tracker_clone = Arc::clone(&playback_tracker);
+    #[cfg(target_os = "windows")]
+    if config.pipe_server_enabled {
+        pipe_server::start(Arc::clone(&last_track), event_loop.create_proxy());
+    }
+    #[cfg(unix)]
+    if config.pipe_server_enabled {
+        let path = config
+            .socket_path
+            .clone()
+            .unwrap_or_else(socket_server::default_path);
+        let events = event_bus.subscribe();
+        match socket_server::start(path, Arc::clone(&last_track), event_loop.create_proxy(), events) {
+            Ok(broadcaster) => event_consumers.push(broadcaster),
+            Err(e) => log::warn!("Control socket disabled: {}", e),
+        }
+    }
 
-    let _webview = WebViewBuilder::new(window)?
-        .with_url("https://music.youtube.com")?
-        .with_initialization_script(INIT_JS)
-        .with_ipc_handler(move |_window: &Window, req: String| {
+    #[cfg(feature = "grpc")]
+    if config.grpc_enabled {
+        let events = event_bus.subscribe();
+        event_consumers.push(grpc::start(
+            config.grpc_port,
+            Arc::clone(&last_track),
+            event_loop.create_proxy(),
+            events,
+        ));
+    }
+    #[cfg(not(feature = "grpc"))]
+    if config.grpc_enabled {
+        log::warn!("grpc_enabled is set, but this build has no gRPC support (build with --features grpc)");
+    }
 
-            if let Ok(obj) = serde_json::from_str::<serde_json::Value>(&req) {
-                if obj.get("cmd").and_then(|v| v.as_str()) == Some("trackUpdate") {
+    if config.mqtt.broker.is_some() {
+        let mqtt_config = config.mqtt.clone();
+        let mqtt_proxy = event_loop.create_proxy();
+        let events = event_bus.subscribe();
+        event_consumers.push(thread::spawn(move || mqtt::run(mqtt_config, events, mqtt_proxy)));
+    }
 
-                    let current_track = LastTrackInfo {
-                        title: obj.get("title").and_then(|v| v.as_str()).map(str::to_string),
-                        artist: obj.get("artist").and_then(|v| v.as_str()).map(str::to_string),
-                        album_art: obj.get("album_art").and_then(|v| v.as_str()).map(str::to_string),
-                        duration_sec: obj.get("duration").and_then(|v| v.as_u64()),
-                    };
+    // One download of the current art for /art.jpg and the overlay.
+    let art_size = config.notification_album_art_size;
+    let art_cache = ((config.api.enabled && config.api.serve_art) || config.osd.enabled).then(|| {
+        let cache = Arc::new(ArtCache::default());
+        let fetcher_cache = Arc::clone(&cache);
+        let events = event_bus.subscribe();
+        event_consumers.push(thread::spawn(move || art_cache::run_fetcher(fetcher_cache, art_size, events)));
+        cache
+    });
+
+    if let Some(art_cache) = art_cache.clone().filter(|_| config.osd.enabled) {
+        let osd_proxy = event_loop.create_proxy();
+        let events = event_bus.subscribe();
+        event_consumers.push(thread::spawn(move || osd::run_watcher(events, art_cache, art_size, osd_proxy)));
+    }
+
+    if config.api.enabled {
+        let art_cache = art_cache.clone().filter(|_| config.api.serve_art);
+        let api_status = match api::load_or_create_token(&config.api) {
+            Ok(token) => {
+                let event_stream = Arc::new(EventStream::default());
+                let stream_hub = Arc::clone(&event_stream);
+                let events = event_bus.subscribe();
+                event_consumers.push(thread::spawn(move || stream_hub.run(events)));
+
+                let advertise = config.api.advertise && !config.api.bind.is_loopback();
+                let context = api::ApiContext {
+                    art_cache,
+                    last_track: Arc::clone(&last_track),
+                    discord_status: Arc::clone(&discord_status),
+                    app_status: Arc::clone(&app_status),
+                    event_stream,
+                    site_host: config.mode.host(),
+                    send_event: {
+                        let proxy = event_loop.create_proxy();
+                        Box::new(move |event| proxy.send_event(event).is_ok())
+                    },
+                    token,
+                    // Off loopback, anyone on the network could read along.
+                    require_token_for_reads: config.api.require_token_for_reads || !config.api.bind.is_loopback(),
+                    allowed_origins: config.api.allowed_origins.clone(),
+                    mdns_host: advertise.then(mdns::host_name),
+                    now_playing_template: config.file_output.template.clone(),
+                    now_playing_idle_text: config.file_output.idle_text.clone(),
+                    action_limiter: api::RateLimiter::default(),
+                    metrics: Arc::clone(&metrics),
+                    track_watch: Arc::clone(&track_watch),
+                };
+                match api::start(config.api.bind, config.api.port, context) {
+                    Ok(address) => {
+                        if advertise {
+                            start_advertising(address, &event_bus, &mut event_consumers);
+                        }
+                        format!("listening on {}, remote control at {}", address, api::remote_url(address))
+                    }
+                    Err(e) => {
+                        log::warn!("Local API disabled, port {} unavailable: {}", config.api.port, e);
+                        format!("port {} unavailable: {}", config.api.port, e)
+                    }
+                }
+            }
+            Err(e) => {
+                log::warn!("Local API disabled, failed to set up its token: {}", e);
+                format!("no token: {}", e)
+            }
+        };
+        app_status.lock().unwrap().api = Some(api_status);
+    }
 
-                    let should_update_discord;
-                    {
+    // Hidden and minimized webviews throttle page timers, so the scrape
+    // would stall after starting in the tray. While the window is out of
+    // sight the event loop triggers it instead.
+    let scrape_proxy = event_loop.create_proxy();
+    thread::spawn(move || loop {
+        thread::sleep(BACKGROUND_SCRAPE_INTERVAL);
+        if scrape_proxy.send_event(UserEvent::BackgroundScrape).is_err() {
+            break;
+        }
+    });
 
+    let ping_proxy = event_loop.create_proxy();
+    thread::spawn(move || loop {
+        thread::sleep(watchdog::PING_INTERVAL);
+        if ping_proxy.send_event(UserEvent::PingPage).is_err() {
+            break;
+        }
+    });
+    let mut page_watchdog = PageWatchdog::default();
+    let page_answers = page_watchdog.answers();
+
+    let proxy = event_loop.create_proxy();
+    let lastfm_proxy = event_loop.create_proxy();
+    if first_run_answers.is_some_and(|answers| answers.connect_lastfm) {
+        if let Some(credentials) = lastfm_credentials.clone() {
+            if let Some(tray) = tray.as_mut() {
+                tray.set_lastfm_status(LastFmStatus::Authorizing);
+            }
+            start_lastfm_authorization(credentials, lastfm_proxy.clone());
+        }
+    }
+    let stats_proxy = event_loop.create_proxy();
+
+    let site_url = config.mode.url();
+    let mut init_script = match config.mode {
+        Mode::Music => format!("{}\n{}", INIT_JS, QUEUE_PANEL_JS),
+        Mode::Video => format!("{}\n{}", INIT_JS, VIDEO_JS),
+    };
+    if app_state.focus_mode {
+        init_script.push_str("\nwindow.__ytuneSetFocusMode(true);");
+    }
+    if config.suppress_browser_update_banner {
+        init_script.push_str("\nwindow.__ytuneSuppressBrowserBanner();");
+    }
+    if config.concert_mode {
+        init_script.push_str("\nwindow.ytune_concertMode = true;");
+    }
+    if let Some(selector) = &config.player_bar_selector {
+        init_script.push_str(&format!(
+            "\nwindow.__ytuneSetPlayerBarSelector({});",
+            serde_json::Value::from(selector.as_str())
+        ));
+    }
+
+    let initial_theme = if config.theme_follows_system {
+        theme::system_theme()
+    } else {
+        None
+    };
+    let start_url = match &open_session {
+        Some(session) => session.watch_url(site_url),
+        None => Url::parse(site_url)?,
+    };
+    let start_url = match initial_theme {
+        Some(initial_theme) => theme::with_theme(&start_url, initial_theme).to_string(),
+        None => start_url.to_string(),
+    };
+    if config.theme_follows_system {
+        let theme_proxy = event_loop.create_proxy();
+        theme::watch(initial_theme, move |theme| {
+            let _ = theme_proxy.send_event(UserEvent::SystemThemeChanged(theme));
+        });
+    }
+
+    let mut web_context = WebContext::new(config::webview_data_dir());
+    let extra_scripts = config
+        .extra_scripts_dir
+        .as_deref()
+        .map(load_extra_scripts)
+        .unwrap_or_default();
+    let mut webview_builder = WebViewBuilder::new(window)?
+        .with_web_context(&mut web_context)
+        .with_url(&start_url)?
+        .with_initialization_script(&init_script);
+    // Each on its own, so a syntax error in one doesn't take the rest, or
+    // ytune's own script, down with it.
+    for script in &extra_scripts {
+        webview_builder = webview_builder.with_initialization_script(script);
+    }
+    let webview = webview_builder
+        .with_ipc_handler(move |_window: &Window, req: String| {
+            let obj = match serde_json::from_str::<serde_json::Value>(&req) {
+                Ok(obj) => obj,
+                Err(e) => {
+                    log::debug!("Ignoring malformed IPC message ({}): {}", e, req);
+                    return;
+                }
+            };
+            if obj.get("cmd").and_then(|v| v.as_str()) == Some("pong") {
+                page_answers.store(0, Ordering::SeqCst);
+            } else if obj.get("cmd").and_then(|v| v.as_str()) == Some("shortcut") {
+                if obj.get("action").and_then(|v| v.as_str()) == Some("toggleFocusMode") {
+                    let _ = proxy.send_event(UserEvent::ToggleFocusMode);
+                }
+            } else if obj.get("cmd").and_then(|v| v.as_str()) == Some("browserUpdateBanner") {
+                let suppressed = obj.get("suppressed").and_then(|v| v.as_bool()).unwrap_or(false);
+                log::warn!(
+                    "YouTube Music reports the webview as outdated{}",
+                    if suppressed { "; hiding the banner" } else { "" }
+                );
+                if !suppressed {
+                    suggest_webview_update();
+                }
+            } else if obj.get("cmd").and_then(|v| v.as_str()) == Some("log") {
+                let message = obj.get("message").and_then(|v| v.as_str()).unwrap_or_default();
+                match obj.get("level").and_then(|v| v.as_str()) {
+                    Some("warn") => log::warn!("Page: {}", message),
+                    _ => log::info!("Page: {}", message),
+                }
+            } else if obj.get("cmd").and_then(|v| v.as_str()) == Some("scrapeMetrics") {
+                let mut status_guard = app_status_clone.lock().unwrap();
+                let metrics = &mut status_guard.scrape_metrics;
+                metrics.scrapes += obj.get("scrapes").and_then(|v| v.as_u64()).unwrap_or(0);
+                metrics.scrape_ms = obj.get("scrape_ms").and_then(|v| v.as_f64());
+                metrics.max_scrape_ms = obj.get("max_scrape_ms").and_then(|v| v.as_f64());
+            } else if matches!(obj.get("cmd").and_then(|v| v.as_str()), Some("trackUpdate" | "playerError")) {
+                let player_error = obj.get("cmd").and_then(|v| v.as_str()) == Some("playerError");
+                if player_error {
+                    let shown = obj.get("error").and_then(|v| v.as_bool()).unwrap_or(false);
+                    let reason = obj.get("reason").and_then(|v| v.as_str()).unwrap_or("playback");
+                    if shown {
+                        log::warn!("YouTube Music shows its {} error screen; clearing the presence", reason);
+                    } else {
+                        log::info!("YouTube Music's error screen is gone");
+                    }
+                    app_status_clone.lock().unwrap().player_error = shown.then(|| reason.to_string());
+                    // The page posts a fresh trackUpdate once it is back.
+                    if !shown {
+                        return;
+                    }
+                }
+                if player_error || obj.get("cleared").and_then(|v| v.as_bool()) == Some(true) {
+                    // The player bar emptied out, or the page can't play, so
+                    // end the play and the presence.
+                    if let Some(track_event) = playback_tracker_clone.lock().unwrap().stop() {
+                        event_bus_clone.publish(track_event);
+                    }
+                    #[cfg_attr(not(all(target_os = "windows", feature = "discord")), allow(unused_variables))]
+                    let (had_track, had_link) = {
                         let mut last_track_guard = last_track_clone.lock().unwrap();
-                        should_update_discord = *last_track_guard != current_track;
-                        *last_track_guard = current_track.clone();
+                        let previous = std::mem::take(&mut *last_track_guard);
+                        (previous != LastTrackInfo::default(), previous.video_id.is_some())
+                    };
+                    track_watch_clone.notify();
+                    if had_link {
+                        let _ = proxy.send_event(UserEvent::TrackLinkAvailable(false));
                     }
+                    #[cfg(all(target_os = "windows", feature = "discord"))]
+                    if had_track && ipc_config.discord_enabled && presence_sender.send("null".to_string()).is_err() {
+                        log::warn!("Discord presence worker has stopped");
+                    }
+                    return;
+                }
 
-                    if should_update_discord {
-                        #[cfg(target_os = "windows")]
-                        {
-                            let clean_title = current_track.title.as_deref().unwrap_or("");
-                            let clean_artist = current_track.artist.as_deref().unwrap_or("");
-                            let clean_album_art = current_track.album_art.as_deref().unwrap_or("");
 
-                            if clean_title.is_empty() && clean_artist.is_empty() {
-                                return;
-                            }
+                if let Some(tier) = obj.get("account_tier").and_then(|v| v.as_str()).and_then(AccountTier::from_label) {
+                    let mut status_guard = app_status_clone.lock().unwrap();
+                    if status_guard.account_tier != Some(tier) {
+                        log::info!("Detected YouTube Music account tier: {:?}", tier);
+                        status_guard.account_tier = Some(tier);
+                    }
+                }
+                {
+                    let mut status_guard = app_status_clone.lock().unwrap();
+                    status_guard.last_scrape_at = Some(events::unix_now());
+                    status_guard.scrape_metrics.track_updates += 1;
+                }
 
-                            let start_time = SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap_or_default()
-                                .as_secs();
-
-                            let end_time = current_track.duration_sec.map(|d| start_time + d);
-                            let timestamp_json = if let Some(end) = end_time {
-                                serde_json::json!({ "start": start_time, "end": end })
-                            } else {
-                                serde_json::json!({ "start": start_time })
-                            };
-
-                            let activity_payload = serde_json::json!({
-                                "timestamps": timestamp_json,
-                                "assets": {
-                                    "large_image": if clean_album_art.is_empty() { serde_json::Value::Null } else { clean_album_art.into() },
-                                    "small_image": "ytune",
-                                    "small_text": "ytune"
-                                },
-                                "details": if clean_title.is_empty() { serde_json::Value::Null } else { clean_title.into() },
-                                "state": if clean_artist.is_empty() { serde_json::Value::Null } else { format!("by {}", clean_artist).into() },
-                                "type": 2,
-                                "name": "ytune",
-                                "buttons": [
-                                    {
-                                        "label": "ytune",
-                                        "url": "https://github.com/yctwhy/ytune"
-                                    }
-                                ]
-                            });
+                let current_track = match track_from_update(&obj) {
+                    Ok(track) => track,
+                    Err(e) => {
+                        log::debug!("Ignoring trackUpdate with {}: {}", e, req);
+                        return;
+                    }
+                };
 
-                            let activity_data_str = serde_json::to_string(&activity_payload)
-                                .unwrap_or_else(|e| {
-                                    eprintln!("ERROR: Failed to serialize activity: {}", e);
-                                    String::new() 
-                                });
+                let track_events = playback_tracker_clone.lock().unwrap().update(&current_track);
+                for track_event in track_events {
+                    event_bus_clone.publish(track_event);
+                }
 
-                            if activity_data_str.is_empty() {
-                                return; 
-                            }
+                let should_update_discord;
+                let link_changed;
+                let art_changed;
+                {
 
-                            let mut connection_guard = conn_arc_clone_ipc.lock().unwrap();
-                            let pid_to_use: u32;
-                            if let Some((ref mut file, pid)) = *connection_guard {
-                                pid_to_use = pid;
-                                match set_activity(file, pid, &activity_data_str) {
-                                    Ok(_) => {
-
-                                        match read_message(file) {
-                                            Ok((_opcode, response_str)) => {
-                                                if response_str.contains("\"cmd\":\"SET_ACTIVITY\"") && response_str.contains("\"evt\":\"ERROR\"") {
-                                                    eprintln!("Discord SET_ACTIVITY Error: {}", response_str);
-                                                }
-                                            },
-                                            Err(e) => {
-                                                handle_ipc_error(e, Arc::clone(&conn_arc_clone_ipc), CLIENT_ID.to_string(), pid_to_use);
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        handle_ipc_error(e, Arc::clone(&conn_arc_clone_ipc), CLIENT_ID.to_string(), pid_to_use);
-                                    }
-                                }
-                            } else {
+                    let mut last_track_guard = last_track_clone.lock().unwrap();
+                    should_update_discord = last_track_guard.presence_differs(&current_track);
+                    link_changed =
+                        last_track_guard.video_id.is_some() != current_track.video_id.is_some();
+                    art_changed = last_track_guard.album_art != current_track.album_art;
+                    *last_track_guard = current_track.clone();
+                }
+                track_watch_clone.notify();
+                if link_changed {
+                    let available = current_track.video_id.is_some();
+                    let _ = proxy.send_event(UserEvent::TrackLinkAvailable(available));
+                }
+                if let Some(art) = current_track.album_art.clone().filter(|_| art_changed) {
+                    let _ = proxy.send_event(UserEvent::ConcertArt(art));
+                }
 
-                                let current_pid = process::id();
-                                drop(connection_guard); 
+                if should_update_discord {
+                    #[cfg(all(target_os = "windows", feature = "discord"))]
+                    if ipc_config.discord_enabled {
+                        let now = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs();
+                        let Some(activity_payload) = activity::build_activity(&current_track, &ipc_config, now) else {
+                            return;
+                        };
 
-                                let recon_conn_arc = Arc::clone(&conn_arc_clone_ipc);
-                                let recon_client_id = CLIENT_ID.to_string();
-                                thread::spawn(move || {
-                                    attempt_reconnect(recon_conn_arc, recon_client_id, current_pid);
-                                });
-                            }
+                        let activity_data_str = serde_json::to_string(&activity_payload)
+                            .unwrap_or_else(|e| {
+                                log::error!("Failed to serialize activity: {}", e);
+                                String::new() 
+                            });
+
+                        if activity_data_str.is_empty() {
+                            return; 
+                        }
+
+                        if presence_sender.send(activity_data_str).is_err() {
+                            log::warn!("Discord presence worker has stopped");
+                        } else {
+                            app_status_clone.lock().unwrap().scrape_metrics.activities_sent += 1;
                         }
                     }
+                } else {
+                    #[cfg(all(target_os = "windows", feature = "discord"))]
+                    if ipc_config.discord_enabled {
+                        app_status_clone.lock().unwrap().scrape_metrics.activities_skipped += 1;
+                    }
                 }
+            } else {
+                log::debug!("Ignoring IPC message without a known cmd: {}", req);
             }
         })
         .build()?;
 
-    event_loop.run(move |event, _, control_flow| {
+    let mut stats_window: Option<StatsWindow> = None;
+    let mut osd_window = if config.osd.enabled {
+        OsdWindow::open(&event_loop, &config.osd)
+            .map_err(|e| log::warn!("Failed to create the OSD window: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    let osd_proxy = event_loop.create_proxy();
+    let share_card_proxy = event_loop.create_proxy();
+    let mut share_card_renderer: Option<CardRenderer> = None;
+
+    event_loop.run(move |event, target, control_flow| {
         *control_flow = ControlFlow::Wait; 
+        // The profile has to stay alive as long as the webview using it.
+        let _ = &web_context;
+
+        let quit_requested = match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                window_id,
+                ..
+            } if stats_window.as_ref().is_some_and(|stats| stats.window_id() == window_id) => {
+                stats_window = None;
+                false
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => true,
+            Event::GlobalShortcutEvent(accelerator_id) => {
+                match hotkeys.action_for(accelerator_id) {
+                    // Through the event loop like tray and API commands.
+                    Some(HotkeyAction::Player(command)) => {
+                        let _ = hotkey_proxy.send_event(UserEvent::PlayerCommand(command));
+                    }
+                    Some(HotkeyAction::ShowHide) => {
+                        let window = webview.window();
+                        if window.is_visible() && !window.is_minimized() {
+                            window.set_visible(false);
+                        } else {
+                            show_window(window);
+                        }
+                    }
+                    Some(HotkeyAction::CopyTrack) => {
+                        copy_track(&last_track.lock().unwrap(), &config);
+                    }
+                    Some(HotkeyAction::CopyLink) => {
+                        copy_track_link(&last_track.lock().unwrap(), site_url, config.notify_on_copy);
+                    }
+                    Some(HotkeyAction::ShareCard) => {
+                        start_share_card(&last_track.lock().unwrap(), None, &share_card_proxy);
+                    }
+                    None => {}
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::CopyTrack) => {
+                copy_track(&last_track.lock().unwrap(), &config);
+                false
+            }
+            Event::UserEvent(UserEvent::CopyLink) => {
+                copy_track_link(&last_track.lock().unwrap(), site_url, config.notify_on_copy);
+                false
+            }
+            Event::UserEvent(UserEvent::ConfigChanged) => {
+                hotkeys.reload(&config::load());
+                false
+            }
+            Event::TrayEvent {
+                event: TrayEvent::LeftClick,
+                ..
+            } => {
+                show_window(webview.window());
+                false
+            }
+            Event::MenuEvent { menu_id, .. } => {
+                match tray.as_ref().and_then(|tray| tray.action_for(menu_id)) {
+                    Some(TrayAction::ShowWindow) => {
+                        show_window(webview.window());
+                        false
+                    }
+                    Some(TrayAction::ToggleFocusMode) => {
+                        toggle_focus_mode(&webview, &mut app_state, tray.as_mut());
+                        false
+                    }
+                    Some(TrayAction::PlayRecent(index)) => {
+                        if let Some(play) = tray.as_ref().and_then(|tray| tray.recent_play(index)) {
+                            let url = format!("{}/watch?v={}", site_url, play.video_id);
+                            webview.load_url(&url);
+                        }
+                        false
+                    }
+                    Some(TrayAction::ToggleAutostart) => {
+                        let enabled = !autostart::is_enabled();
+                        match autostart::set_enabled(enabled) {
+                            Ok(()) => {
+                                if let Err(e) = config::store_value("start_with_system", enabled) {
+                                    log::warn!("Failed to save start_with_system: {}", e);
+                                }
+                            }
+                            Err(e) => log::warn!("Failed to update start with system: {}", e),
+                        }
+                        if let Some(tray) = tray.as_mut() {
+                            tray.set_autostart(autostart::is_enabled());
+                        }
+                        false
+                    }
+                    Some(TrayAction::CopyTrack) => {
+                        copy_track(&last_track.lock().unwrap(), &config);
+                        false
+                    }
+                    Some(TrayAction::ShareCard) => {
+                        start_share_card(&last_track.lock().unwrap(), None, &share_card_proxy);
+                        false
+                    }
+                    Some(TrayAction::CopyMusicLink) => {
+                        copy_track_link(&last_track.lock().unwrap(), YTM_URL, config.notify_on_copy);
+                        false
+                    }
+                    Some(TrayAction::CopyYouTubeLink) => {
+                        copy_track_link(&last_track.lock().unwrap(), YOUTUBE_URL, config.notify_on_copy);
+                        false
+                    }
+                    Some(TrayAction::RefreshNowPlaying) => {
+                        if let Err(e) = webview.evaluate_script(&PlayerCommand::Refresh.script()) {
+                            log::warn!("Failed to refresh now playing: {}", e);
+                        }
+                        false
+                    }
+                    Some(TrayAction::ShowStats) => {
+                        match &stats_window {
+                            Some(stats) => stats.focus(),
+                            None => {
+                                let opened = open_history(&config).and_then(|history| {
+                                    StatsWindow::open(target, stats_window_icon.clone(), history, stats_proxy.clone())
+                                        .map_err(|e| e.to_string())
+                                });
+                                match opened {
+                                    Ok(stats) => stats_window = Some(stats),
+                                    Err(e) => log::warn!("Failed to open the stats window: {}", e),
+                                }
+                            }
+                        }
+                        false
+                    }
+                    Some(TrayAction::ExportHistory) => {
+                        export_history_from_tray(&config);
+                        false
+                    }
+                    Some(TrayAction::ShowDiagnostics) => {
+                        #[cfg(all(target_os = "windows", feature = "discord"))]
+                        let discord_status = discord_status.lock().unwrap().to_string();
+                        #[cfg(not(all(target_os = "windows", feature = "discord")))]
+                        let discord_status = if cfg!(feature = "discord") {
+                            "not supported on this platform"
+                        } else {
+                            "not included in this build"
+                        }
+                        .to_string();
+
+                        let report = diagnostics::render(
+                            &app_status.lock().unwrap(),
+                            &last_track.lock().unwrap(),
+                            &discord_status,
+                        );
+                        if let Err(e) = diagnostics::write_and_open(&report) {
+                            log::warn!("Failed to open diagnostics report: {}", e);
+                        }
+                        false
+                    }
+                    Some(TrayAction::ConnectLastFm) => {
+                        if let Some(credentials) = lastfm_credentials.clone() {
+                            if let Some(tray) = tray.as_mut() {
+                                tray.set_lastfm_status(LastFmStatus::Authorizing);
+                            }
+                            start_lastfm_authorization(credentials, lastfm_proxy.clone());
+                        }
+                        false
+                    }
+                    Some(TrayAction::Quit) => true,
+                    None => false,
+                }
+            }
+            Event::UserEvent(UserEvent::PlayerCommand(command)) => {
+                if let Err(e) = webview.evaluate_script(&command.script()) {
+                    log::warn!("Failed to run {:?}: {}", command, e);
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::RecentPlaysChanged(recent_plays)) => {
+                if let Some(tray) = tray.as_mut() {
+                    tray.set_recent_plays(recent_plays);
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::OpenUrl(url)) => {
+                webview.load_url(url.as_str());
+                false
+            }
+            Event::UserEvent(UserEvent::ShowWindow) => {
+                show_window(webview.window());
+                false
+            }
+            Event::UserEvent(UserEvent::ShowOsd(track)) => {
+                if let Some(generation) = osd_window.as_mut().and_then(|osd| osd.show(&track)) {
+                    let osd_proxy = osd_proxy.clone();
+                    thread::spawn(move || {
+                        thread::sleep(osd::SHOW_FOR);
+                        let _ = osd_proxy.send_event(UserEvent::HideOsd(generation));
+                    });
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::ConcertArt(source)) => {
+                let resized = youtube_url::resize_youtube_art_url(&source, CONCERT_ART_SIZE);
+                let script = format!(
+                    "window.__ytuneSetConcertArt({}, {});",
+                    serde_json::Value::String(source),
+                    serde_json::Value::String(resized)
+                );
+                if let Err(e) = webview.evaluate_script(&script) {
+                    log::warn!("Failed to update the concert mode art: {}", e);
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::HideOsd(generation)) => {
+                if let Some(osd) = &osd_window {
+                    osd.hide(generation);
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::ShareCard(file_name)) => {
+                start_share_card(&last_track.lock().unwrap(), file_name, &share_card_proxy);
+                false
+            }
+            Event::UserEvent(UserEvent::DrawShareCard(job)) => {
+                // Replacing the renderer would close it before its card is saved.
+                if share_card_renderer.is_some() {
+                    confirm(true, "Share card failed", "Another card is still being saved.".to_string());
+                } else {
+                    match CardRenderer::open(target, job, share_card_proxy.clone()) {
+                        Ok(renderer) => share_card_renderer = Some(renderer),
+                        Err(e) => {
+                            log::warn!("Failed to open the share card renderer: {}", e);
+                            confirm(true, "Share card failed", e.to_string());
+                        }
+                    }
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::ShareCardDone(result)) => {
+                share_card_renderer = None;
+                match result {
+                    Ok(path) => confirm(true, "Saved share card", path.display().to_string()),
+                    Err(e) => {
+                        log::warn!("Failed to save the share card: {}", e);
+                        confirm(true, "Share card failed", e);
+                    }
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::OpenSession(session)) => {
+                webview.load_url(session.watch_url(site_url).as_str());
+                show_window(webview.window());
+                false
+            }
+            Event::UserEvent(UserEvent::StatsRequest(StatsRequest::Play { video_id })) => {
+                webview.load_url(&format!("{}/watch?v={}", site_url, video_id));
+                show_window(webview.window());
+                false
+            }
+            Event::UserEvent(UserEvent::StatsReply(script)) => {
+                if let Some(stats) = &stats_window {
+                    stats.show_reply(&script);
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::TrackLinkAvailable(available)) => {
+                if let Some(tray) = tray.as_mut() {
+                    tray.set_track_link_available(available);
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::BackgroundScrape) => {
+                let window = webview.window();
+                if !window.is_visible() || window.is_minimized() {
+                    if let Err(e) = webview.evaluate_script(&PlayerCommand::Refresh.script()) {
+                        log::warn!("Failed to scrape in the background: {}", e);
+                    }
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::PingPage) => {
+                match page_watchdog.check() {
+                    watchdog::Check::Ping => {
+                        if let Err(e) = webview.evaluate_script(watchdog::PING_SCRIPT) {
+                            log::debug!("Failed to ping the page: {}", e);
+                        }
+                    }
+                    watchdog::Check::Reload { attempt, max } => {
+                        log::warn!(
+                            "Page stopped answering, the webview may have crashed; reloading ({} of {})",
+                            attempt,
+                            max
+                        );
+                        metrics.increment(Counter::ScraperWatchdogFirings);
+                        // Initialization scripts run again on the new page, so
+                        // scraping and the presence pick up where they were.
+                        let current_url = webview.url();
+                        let url = if current_url.host_str() == Some(config.mode.host()) {
+                            current_url.to_string()
+                        } else {
+                            site_url.to_string()
+                        };
+                        webview.load_url(&url);
+                    }
+                    watchdog::Check::GiveUp => {
+                        log::error!("Page still isn't answering after reloading it; restart ytune to recover");
+                    }
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::ToggleFocusMode) => {
+                toggle_focus_mode(&webview, &mut app_state, tray.as_mut());
+                false
+            }
+            Event::UserEvent(UserEvent::LastFmConnected(session_key)) => {
+                // A scrobbler whose session expired takes the new key and
+                // sends what it kept queued.
+                let session_key = match &lastfm_session_sender {
+                    Some(sender) => sender.send(session_key).err().map(|error| error.0),
+                    None => Some(session_key),
+                };
+                if let (Some(session_key), Some(credentials)) = (session_key, lastfm_credentials.clone()) {
+                    let (consumer, session_sender) = start_scrobbler(
+                        &event_bus,
+                        &app_status,
+                        &metrics,
+                        &config,
+                        credentials,
+                        session_key,
+                        lastfm_proxy.clone(),
+                    );
+                    event_consumers.push(consumer);
+                    lastfm_session_sender = Some(session_sender);
+                }
+                if let Some(tray) = tray.as_mut() {
+                    tray.set_lastfm_status(LastFmStatus::Connected);
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::LastFmSessionExpired) => {
+                if let Some(tray) = tray.as_mut() {
+                    tray.set_lastfm_status(LastFmStatus::Expired);
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::LastFmAuthFailed) => {
+                if let Some(tray) = tray.as_mut() {
+                    tray.set_lastfm_status(LastFmStatus::Disconnected);
+                }
+                false
+            }
+            Event::UserEvent(UserEvent::SystemThemeChanged(theme)) => {
+                // Pages outside the site (e.g. sign-in) don't know the parameter.
+                let current_url = webview.url();
+                let base_url = if current_url.host_str() == Some(config.mode.host()) {
+                    current_url
+                } else {
+                    Url::parse(site_url).expect("site URLs are valid")
+                };
+                webview.load_url(theme::with_theme(&base_url, theme).as_str());
+                false
+            }
+            _ => false,
+        };
+
+        if quit_requested {
+            // Let the consumers record the play that was in progress before exiting.
+            if let Some(track_event) = playback_tracker.lock().unwrap().stop() {
+                event_bus.publish(track_event);
+            }
+            event_bus.close();
+            for consumer in event_consumers.drain(..) {
+                let _ = consumer.join();
+            }
 
-        if let wry::application::event::Event::WindowEvent {
-            event: wry::application::event::WindowEvent::CloseRequested,
-            ..
-        } = event
-        {
             *control_flow = ControlFlow::Exit 
         }
     });
 }
 
-fn load_window_icon() -> Option<Icon> {
+fn start_advertising(address: SocketAddr, event_bus: &EventBus, event_consumers: &mut Vec<thread::JoinHandle<()>>) {
+    let ip = match address.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+        // Listening everywhere; advertise the address the LAN sees.
+        ip if ip.is_unspecified() => match api::lan_address() {
+            Some(IpAddr::V4(ip)) => Some(ip),
+            _ => None,
+        },
+        _ => None,
+    };
+    let Some(ip) = ip else {
+        log::warn!("Not advertising the API over mDNS, no IPv4 address to announce for {}", address);
+        return;
+    };
+    let port = address.port();
+    let events = event_bus.subscribe();
+    event_consumers.push(thread::spawn(move || mdns::run(ip, port, events)));
+}
+
+/// Starts the scrobbler, returning its thread and where to send it a new
+/// session key once the user reconnects.
+fn start_scrobbler(
+    event_bus: &EventBus,
+    app_status: &Arc<Mutex<AppStatus>>,
+    metrics: &Arc<Metrics>,
+    config: &config::Config,
+    credentials: lastfm::Credentials,
+    session_key: String,
+    proxy: EventLoopProxy<UserEvent>,
+) -> (thread::JoinHandle<()>, mpsc::Sender<String>) {
+    let client = lastfm::Client::new(credentials, Some(session_key));
+    let rules = ScrobbleRules::from_config(&config.scrobble);
+    let love_sync = LoveSync::new(config.lastfm_unlove_on_unlike);
+    let status = Arc::clone(app_status);
+    let metrics = Arc::clone(metrics);
+    let events = event_bus.subscribe();
+    let (session_sender, session_keys) = mpsc::channel();
+    let on_session_expired = move || {
+        let _ = proxy.send_event(UserEvent::LastFmSessionExpired);
+    };
+    let consumer = thread::spawn(move || {
+        lastfm::run_scrobbler(client, rules, love_sync, status, metrics, events, session_keys, on_session_expired)
+    });
+    (consumer, session_sender)
+}
+
+/// Walks the user through Last.fm's browser authorization on its own
+/// thread, then reports the outcome to the event loop.
+fn start_lastfm_authorization(credentials: lastfm::Credentials, proxy: EventLoopProxy<UserEvent>) {
+    thread::spawn(move || {
+        let user_event = match lastfm::authorize(credentials) {
+            Ok(session) => {
+                log::info!("Connected to Last.fm as {}", session.user_name);
+                if let Err(e) = config::store_secret("lastfm_session_key", &session.key) {
+                    log::warn!("Failed to save Last.fm session: {}", e);
+                }
+                UserEvent::LastFmConnected(session.key)
+            }
+            Err(e) => {
+                log::warn!("Last.fm authorization failed: {}", e);
+                UserEvent::LastFmAuthFailed
+            }
+        };
+        let _ = proxy.send_event(user_event);
+    });
+}
+
+fn show_window(window: &Window) {
+    window.set_visible(true);
+    window.set_minimized(false);
+    window.set_focus();
+}
+
+/// Puts the current track in `copy_track_template` on the clipboard. Only
+/// says so when no track has been seen yet. Must run on the event loop
+/// thread, which owns the clipboard on Linux.
+fn copy_track(track: &LastTrackInfo, config: &config::Config) {
+    if track.title.is_none() && track.artist.is_none() {
+        log::debug!("Nothing playing, not copying track");
+        confirm(config.notify_on_copy, "Nothing to copy", "No track is playing.".to_string());
+        return;
+    }
+    let text = template::render(&config.copy_track_template, track);
+    Clipboard::new().write_text(&text);
+    confirm(config.notify_on_copy, "Copied track", text);
+}
+
+/// Starts a share card of the current track, or says there is nothing to share.
+fn start_share_card(track: &LastTrackInfo, file_name: Option<String>, proxy: &EventLoopProxy<UserEvent>) {
+    if track.title.is_none() {
+        confirm(true, "Nothing to share", "No track is playing.".to_string());
+        return;
+    }
+    share_card::prepare(track.clone(), file_name, proxy.clone());
+}
+
+/// Writes the whole play history as CSV to the documents folder on its own
+/// thread, and says where it went.
+fn export_history_from_tray(config: &config::Config) {
+    let config = config.clone();
+    thread::spawn(move || {
+        let result = export::default_path()
+            .ok_or_else(|| "No folder to save to".to_string())
+            .and_then(|path| {
+                let records = open_history(&config)
+                    .and_then(|history| history.range(None, None).map_err(|e| e.to_string()))?;
+                export::export_history(&records, &path, export::ExportFormat::Csv)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                Ok((records.len(), path))
+            });
+        match result {
+            Ok((count, path)) => notification::show_transient(
+                "Exported play history",
+                &format!("{} plays to {}", count, path.display()),
+            ),
+            Err(e) => {
+                log::warn!("Failed to export play history: {}", e);
+                notification::show_transient("History export failed", &e);
+            }
+        }
+    });
+}
+
+/// Shows an action's outcome off the event loop thread, since the
+/// notification tools take a moment to start.
+fn confirm(enabled: bool, title: &'static str, body: String) {
+    if enabled {
+        thread::spawn(move || notification::show_transient(title, &body));
+    }
+}
+
+/// Tells the user how to get a newer webview, once per run. Runs on its own
+/// thread since the message box blocks until it is closed.
+fn suggest_webview_update() {
+    static SHOWN: std::sync::Once = std::sync::Once::new();
+    SHOWN.call_once(|| {
+        thread::spawn(|| {
+            #[cfg(target_os = "windows")]
+            {
+                use windows::{
+                    core::w,
+                    Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONWARNING, MB_OK},
+                };
+
+                unsafe {
+                    MessageBoxW(
+                        None,
+                        w!("YouTube Music says this browser is outdated, which can stop playback.\n\nUpdate the Microsoft Edge WebView2 Runtime through Windows Update or from https://developer.microsoft.com/microsoft-edge/webview2/, then restart ytune."),
+                        w!("ytune"),
+                        MB_OK | MB_ICONWARNING,
+                    );
+                }
+            }
+            #[cfg(not(target_os = "windows"))]
+            notification::show(
+                "ytune: webview outdated",
+                "YouTube Music says this browser is outdated. Update WebKitGTK (webkit2gtk) through your package manager, then restart ytune.",
+            );
+        });
+    });
+}
+
+/// Puts the `/watch` link of the current track on `base_url` on the
+/// clipboard, or a search for its title and artist when the page gave no
+/// video ID.
+fn copy_track_link(track: &LastTrackInfo, base_url: &str, notify: bool) {
+    let Some(link) = track_link(track, base_url) else {
+        log::debug!("Nothing playing, not copying a link");
+        confirm(notify, "Nothing to copy", "No track is playing.".to_string());
+        return;
+    };
+    Clipboard::new().write_text(&link);
+    confirm(notify, "Copied link", link);
+}
+
+fn track_link(track: &LastTrackInfo, base_url: &str) -> Option<String> {
+    if let Some(link) = track
+        .video_id
+        .as_deref()
+        .and_then(|video| youtube_url::share_url(base_url, video))
+    {
+        return Some(link);
+    }
+    let query = [track.title.as_deref(), track.artist.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if query.is_empty() {
+        return None;
+    }
+    // YouTube and YouTube Music search at different paths.
+    let (path, param) = if base_url == YTM_URL {
+        ("search", "q")
+    } else {
+        ("results", "search_query")
+    };
+    let mut url = Url::parse(&format!("{}/{}", base_url, path)).ok()?;
+    url.query_pairs_mut().append_pair(param, &query);
+    Some(url.into())
+}
+
+fn toggle_focus_mode(webview: &WebView, app_state: &mut state::State, tray: Option<&mut Tray>) {
+    app_state.focus_mode = !app_state.focus_mode;
+
+    let script = format!("window.__ytuneSetFocusMode({});", app_state.focus_mode);
+    if let Err(e) = webview.evaluate_script(&script) {
+        log::warn!("Failed to apply focus mode: {}", e);
+    }
+    if let Some(tray) = tray {
+        tray.set_focus_mode(app_state.focus_mode);
+    }
+    if let Err(e) = state::save(app_state) {
+        log::warn!("Failed to save state: {}", e);
+    }
+}
+
+/// The track the running ytune last wrote for `--print-track`, or what to
+/// tell the user when there is none.
+fn read_current_track<T: serde::de::DeserializeOwned>() -> Result<T, String> {
+    let path = file_output::current_track_path().ok_or("No directory to look for the current track in")?;
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err("ytune isn't running, or hasn't played anything yet".to_string());
+        }
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    serde_json::from_str(&contents).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+fn run_cli_command(command: Command, config: &config::Config) -> i32 {
+    match command {
+        Command::Run => 0,
+        Command::PrintTrack => match read_current_track::<serde_json::Value>() {
+            Ok(track) => {
+                println!("{}", track);
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        },
+        Command::ShareSession => {
+            let track = match read_current_track::<LastTrackInfo>() {
+                Ok(track) => track,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return 1;
+                }
+            };
+            match share_session::Session::from_track(&track) {
+                Some(session) => {
+                    println!("{}", session.to_uri());
+                    0
+                }
+                None => {
+                    eprintln!("Nothing with a video ID is playing");
+                    1
+                }
+            }
+        }
+        Command::InstallAutostart => {
+            let result = autostart::set_enabled(true)
+                .and_then(|()| config::store_value("start_with_system", true))
+                .and_then(|()| autostart::register_uri_scheme(share_session::SCHEME));
+            match result {
+                Ok(()) => {
+                    println!("ytune starts on login and opens {}:// session links", share_session::SCHEME);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Failed to install: {}", e);
+                    1
+                }
+            }
+        }
+        Command::Send { cmd } => {
+            let request = serde_json::json!({ "v": api::types::API_VERSION, "cmd": cmd });
+            match ipc_protocol::request(config, &request) {
+                Ok(response) => {
+                    println!("{}", response);
+                    if response["ok"] == true { 0 } else { 1 }
+                }
+                Err(e) => {
+                    eprintln!("Failed to reach ytune (is it running with pipe_server_enabled = true?): {}", e);
+                    1
+                }
+            }
+        }
+        #[cfg(unix)]
+        Command::I3StatusBlock => match i3status::run(config) {
+            Ok(()) => 0,
+            // i3bar closing the pipe is how the block is stopped.
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => 0,
+            Err(e) => {
+                eprintln!("Failed to write the block: {}", e);
+                1
+            }
+        },
+        #[cfg(not(unix))]
+        Command::I3StatusBlock => {
+            eprintln!("--i3status-block needs the Unix control socket, so it only works on Linux and macOS");
+            1
+        }
+        Command::History { limit } => {
+            match open_history(config).and_then(|history| {
+                history::print_recent(&history, limit).map_err(|e| e.to_string())
+            }) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("Failed to read play history: {}", e);
+                    1
+                }
+            }
+        }
+        Command::SearchHistory { query } => {
+            match open_history(config).and_then(|history| {
+                history::print_search(&history, &query).map_err(|e| e.to_string())
+            }) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("Failed to search play history: {}", e);
+                    1
+                }
+            }
+        }
+        Command::Stats { json } => {
+            let stats = match open_history(config)
+                .and_then(|history| stats::compute(&history).map_err(|e| e.to_string()))
+            {
+                Ok(stats) => stats,
+                Err(e) => {
+                    eprintln!("Failed to read play history: {}", e);
+                    return 1;
+                }
+            };
+            if json {
+                match serde_json::to_string_pretty(&stats) {
+                    Ok(output) => println!("{}", output),
+                    Err(e) => {
+                        eprintln!("Failed to serialize stats: {}", e);
+                        return 1;
+                    }
+                }
+            } else {
+                stats::print(&stats);
+            }
+            0
+        }
+        Command::ExportHistory { path, since, until } => {
+            let Some(format) = export::ExportFormat::from_path(&path) else {
+                eprintln!("Export path must end in .csv or .json: {}", path.display());
+                return 2;
+            };
+            let records = match open_history(config)
+                .and_then(|history| history.range(since, until).map_err(|e| e.to_string()))
+            {
+                Ok(records) => records,
+                Err(e) => {
+                    eprintln!("Failed to read play history: {}", e);
+                    return 1;
+                }
+            };
+            match export::export_history(&records, &path, format) {
+                Ok(()) => {
+                    println!("Exported {} plays to {}", records.len(), path.display());
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Failed to write {}: {}", path.display(), e);
+                    1
+                }
+            }
+        }
+        Command::ImportTakeout { path } => {
+            let history = match open_history(config) {
+                Ok(history) => history,
+                Err(e) => {
+                    eprintln!("Failed to open play history: {}", e);
+                    return 1;
+                }
+            };
+            let results = takeout::import(&history, &path);
+            if results.is_empty() {
+                eprintln!("No Takeout history files found in {}", path.display());
+                return 1;
+            }
+
+            let mut total = takeout::FileSummary::default();
+            let mut failed = 0;
+            for (file, result) in results {
+                match result {
+                    Ok(summary) => {
+                        println!(
+                            "{}: {} imported, {} already recorded, {} skipped",
+                            file.display(),
+                            summary.imported,
+                            summary.duplicates,
+                            summary.skipped
+                        );
+                        total.imported += summary.imported;
+                        total.duplicates += summary.duplicates;
+                        total.skipped += summary.skipped;
+                    }
+                    Err(e) => {
+                        eprintln!("{}: not imported: {}", file.display(), e);
+                        failed += 1;
+                    }
+                }
+            }
+            println!(
+                "Imported {} plays, {} already recorded, {} skipped",
+                total.imported, total.duplicates, total.skipped
+            );
+            if failed > 0 {
+                1
+            } else {
+                0
+            }
+        }
+        Command::LastFmLogin => {
+            let Some(credentials) = lastfm::Credentials::from_config(config) else {
+                eprintln!("This build has no Last.fm API key; set lastfm_api_key and lastfm_api_secret in config.toml.");
+                return 1;
+            };
+            match lastfm::login_interactive(credentials) {
+                Ok(session) => match config::store_secret("lastfm_session_key", &session.key) {
+                    Ok(()) => {
+                        println!("Connected to Last.fm as {}.", session.user_name);
+                        0
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to save the Last.fm session: {}", e);
+                        1
+                    }
+                },
+                Err(e) => {
+                    eprintln!("Last.fm login failed: {}", e);
+                    1
+                }
+            }
+        }
+    }
+}
+
+fn open_history(config: &config::Config) -> Result<History, String> {
+    let data_dir = config::data_dir().ok_or("No data directory available")?;
+    let retention = Retention {
+        max_entries: config.history_max_entries,
+        max_age_days: config.history_max_age_days,
+    };
+    History::open(&data_dir, retention).map_err(|e| e.to_string())
+}
+
+/// The latest distinct tracks for the tray, skipping plays without a video ID.
+fn recent_plays(history: &History) -> Vec<RecentPlay> {
+    let records = match history.recent(RECENT_PLAYS_LIMIT * 5) {
+        Ok(records) => records,
+        Err(e) => {
+            log::warn!("Failed to read recent plays: {}", e);
+            return Vec::new();
+        }
+    };
+    let mut recent_plays: Vec<RecentPlay> = Vec::new();
+    for play in records.iter().filter_map(RecentPlay::from_record) {
+        if !recent_plays.iter().any(|seen| seen.video_id == play.video_id) {
+            recent_plays.push(play);
+        }
+    }
+    recent_plays.truncate(RECENT_PLAYS_LIMIT);
+    recent_plays
+}
+
+/// Uses `window_icon_path` from the config when it decodes, otherwise the
+/// embedded icon.
+fn load_window_icon(custom_path: Option<&Path>) -> Option<Icon> {
+    if let Some(path) = custom_path {
+        match image::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|image| icon_from_image(image).map_err(|e| e.to_string()))
+        {
+            Ok(icon) => return Some(icon),
+            Err(e) => log::warn!(
+                "Failed to load window icon from {}: {}. Using the built-in icon.",
+                path.display(),
+                e
+            ),
+        }
+    }
+
     let icon_bytes = include_bytes!("assets/ytune.png");
     load_from_memory_with_format(icon_bytes, ImageFormat::Png)
+        .map_err(|e| e.to_string())
+        .and_then(|image| icon_from_image(image).map_err(|e| e.to_string()))
+        .map_err(|e| log::warn!("Failed to load the built-in window icon: {}", e))
         .ok()
-        .map(|image| {
-            let image = image.into_rgba8();
-            let (width, height) = image.dimensions();
-            let rgba = image.into_raw();
-            Icon::from_rgba(rgba, width, height).ok()
+}
+
+/// Reads the `*.js` files in `dir` in file name order. Files that can't be
+/// read are skipped with a warning.
+fn load_extra_scripts(dir: &Path) -> Vec<String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Failed to read extra_scripts_dir {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("js"))
         })
-        .flatten()
+        .collect();
+    paths.sort();
+
+    let scripts: Vec<String> = paths
+        .iter()
+        .filter_map(|path| match std::fs::read_to_string(path) {
+            Ok(script) => Some(script),
+            Err(e) => {
+                log::warn!("Skipping extra script {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+    log::info!("Loaded {} extra script(s) from {}", scripts.len(), dir.display());
+    scripts
 }
 
-#[cfg(target_os = "windows")]
-fn connect_and_handshake(client_id: &str, _pid: u32) -> io::Result<File> {
-    connect().and_then(|mut file| {
+fn icon_from_image(image: image::DynamicImage) -> Result<Icon, wry::application::window::BadIcon> {
+    let image = image.into_rgba8();
+    let (width, height) = image.dimensions();
+    Icon::from_rgba(image.into_raw(), width, height)
+}
+
+/// Connects to Discord and completes the handshake, storing the pipe in
+/// `connection` and keeping its status up to date. Does nothing while
+/// connected or while another attempt is under way.
+#[cfg(all(target_os = "windows", feature = "discord"))]
+fn connect_and_handshake(
+    connection: &DiscordConnection,
+    client_id: &str,
+    pid: u32,
+    retry: ConnectRetry,
+) -> io::Result<()> {
+    if !connection.state.lock().unwrap().transition(ConnectionEvent::Attempt) {
+        return Ok(());
+    }
+    // A reconnect keeps saying so until it is through.
+    {
+        let mut status = connection.status.lock().unwrap();
+        if *status != DiscordStatus::Reconnecting {
+            *status = DiscordStatus::Connecting;
+        }
+    }
+
+    match open_pipe(client_id, retry) {
+        Ok(file) => {
+            connection.state.lock().unwrap().transition(ConnectionEvent::Established(file, pid));
+            connection.set_status(DiscordStatus::Connected);
+            Ok(())
+        }
+        Err(e) => {
+            connection.state.lock().unwrap().transition(ConnectionEvent::AttemptFailed);
+            let error = if e.kind() == io::ErrorKind::NotFound {
+                "Discord is not running".to_string()
+            } else {
+                e.to_string()
+            };
+            connection.set_status(DiscordStatus::Error(error));
+            Err(e)
+        }
+    }
+}
+
+#[cfg(all(target_os = "windows", feature = "discord"))]
+fn open_pipe(client_id: &str, retry: ConnectRetry) -> io::Result<File> {
+    connect(retry).and_then(|mut file| {
         send_handshake(&mut file, client_id)?;
-        match read_message(&mut file) {
-            Ok((1, response_str)) => {
-                match serde_json::from_str::<serde_json::Value>(&response_str) {
-                    Ok(json_response) => {
-                        if json_response.get("cmd").and_then(|v| v.as_str()) == Some("DISPATCH")
-                            && json_response.get("evt").and_then(|v| v.as_str()) == Some("READY")
-                        {
-                            Ok(file)
-                        } else {
-                            Err(io::Error::new(
-                                io::ErrorKind::InvalidData,
-                                "Handshake not READY",
-                            ))
-                        }
-                    }
-                    Err(e) => Err(io::Error::new(
+        let (opcode, response_str) = read_message(&mut file)?;
+        let json_response = serde_json::from_str::<serde_json::Value>(&response_str).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Parse handshake JSON failed: {}", e),
+            )
+        });
+        match opcode {
+            1 => {
+                let json_response = json_response?;
+                match (
+                    json_response.get("cmd").and_then(|v| v.as_str()),
+                    json_response.get("evt").and_then(|v| v.as_str()),
+                ) {
+                    (Some("DISPATCH"), Some("READY")) => Ok(file),
+                    (_, Some("ERROR")) => Err(io::Error::new(
                         io::ErrorKind::InvalidData,
-                        format!("Parse handshake JSON failed: {}", e),
+                        handshake_error(json_response.get("data").unwrap_or(&serde_json::Value::Null)),
+                    )),
+                    _ => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Handshake not READY: {}", response_str),
                     )),
                 }
             }
-            Ok((opcode, _)) => Err(io::Error::new(
+            // A refused handshake comes back as a close frame.
+            2 => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                handshake_error(&json_response?),
+            )),
+            opcode => Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 format!("Unexpected opcode {} in handshake", opcode),
             )),
-            Err(e) => Err(e),
         }
     })
 }
 
-#[cfg(target_os = "windows")]
+/// Sends presence updates from a dedicated thread so pipe I/O never blocks the
+/// UI. Updates that queue up while one is in flight collapse to the newest.
+#[cfg(all(target_os = "windows", feature = "discord"))]
+fn spawn_presence_worker(
+    connection_arc: Arc<DiscordConnection>,
+    retry: ConnectRetry,
+) -> mpsc::Sender<String> {
+    let (sender, receiver) = mpsc::channel::<String>();
+
+    thread::spawn(move || {
+        while let Ok(mut activity) = receiver.recv() {
+            while let Ok(newer) = receiver.try_recv() {
+                activity = newer;
+            }
+            send_activity(&connection_arc, &activity, retry);
+        }
+    });
+
+    sender
+}
+
+#[cfg(all(target_os = "windows", feature = "discord"))]
+fn send_activity(
+    connection_arc: &Arc<DiscordConnection>,
+    activity: &str,
+    retry: ConnectRetry,
+) {
+    let mut connection_guard = connection_arc.state.lock().unwrap();
+    if let ConnectionState::Connected(ref mut file, pid) = *connection_guard {
+        let result = set_activity(file, pid, activity).and_then(|nonce| read_response(file, &nonce));
+        match result {
+            Ok(response) => {
+                if response.get("evt").and_then(|v| v.as_str()) == Some("ERROR") {
+                    log::warn!("Discord SET_ACTIVITY Error: {}", response);
+                }
+            }
+            Err(e) => {
+                drop(connection_guard);
+                handle_ipc_error(e, Arc::clone(connection_arc), CLIENT_ID.to_string(), pid, retry);
+            }
+        }
+    } else if connection_guard.can_attempt() {
+        drop(connection_guard);
+
+        let recon_conn_arc = Arc::clone(connection_arc);
+        let current_pid = process::id();
+        thread::spawn(move || {
+            attempt_reconnect(recon_conn_arc, CLIENT_ID.to_string(), current_pid, retry);
+        });
+    }
+}
+
+#[cfg(all(target_os = "windows", feature = "discord"))]
 fn handle_ipc_error(
     error: io::Error,
-    connection_arc: Arc<Mutex<DiscordConnectionState>>,
+    connection_arc: Arc<DiscordConnection>,
     client_id: String,
     pid: u32,
+    retry: ConnectRetry,
 ) {
     if matches!(
         error.kind(),
         io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset | io::ErrorKind::UnexpectedEof
     ) {
-        eprintln!("Discord pipe broken. Clearing state and attempting reconnect...");
-        {
-            let mut guard = connection_arc.lock().unwrap();
-            *guard = None;
-        }
+        log::warn!("Discord pipe broken. Clearing state and attempting reconnect...");
+        connection_arc.state.lock().unwrap().transition(ConnectionEvent::Broken);
+        connection_arc.set_status(DiscordStatus::Reconnecting);
+        connection_arc.metrics.increment(Counter::DiscordReconnects);
 
         thread::spawn(move || {
             thread::sleep(Duration::from_secs(2));
-            attempt_reconnect(connection_arc, client_id, pid);
+            attempt_reconnect(connection_arc, client_id, pid, retry);
         });
+    } else {
+        connection_arc.set_status(DiscordStatus::Error(error.to_string()));
     }
 }
 
-#[cfg(target_os = "windows")]
+#[cfg(all(target_os = "windows", feature = "discord"))]
 fn attempt_reconnect(
-    connection_arc: Arc<Mutex<DiscordConnectionState>>,
+    connection_arc: Arc<DiscordConnection>,
     client_id: String,
     pid: u32,
+    retry: ConnectRetry,
 ) {
-    match connect_and_handshake(&client_id, pid) {
-        Ok(new_file) => {
-            let mut guard = connection_arc.lock().unwrap();
-            *guard = Some((new_file, pid));
-        }
-        Err(e) => {
-            eprintln!("Discord reconnection attempt failed: {:?}", e);
-            let mut guard = connection_arc.lock().unwrap();
-            if guard.is_some() {
-                *guard = None;
-            }
-        }
+    if let Err(e) = connect_and_handshake(&connection_arc, &client_id, pid, retry) {
+        log::warn!("Discord reconnection attempt failed: {}", e);
     }
 }
\ No newline at end of file
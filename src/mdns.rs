@@ -0,0 +1,560 @@
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::events::TrackEvent;
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+const SERVICE: &str = "_ytune._tcp.local";
+const SERVICE_ENUMERATION: &str = "_services._dns-sd._udp.local";
+/// RFC 6762 section 10: host records live shorter than the rest.
+const HOST_TTL: u32 = 120;
+const OTHER_TTL: u32 = 4500;
+/// How often the responder looks up from the socket to see if it should stop.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Set on records only this host answers for, so caches replace them.
+const CACHE_FLUSH: u16 = 0x8000;
+/// Set on questions whose asker wants the answer sent back to it directly.
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+struct Record {
+    name: String,
+    rtype: u16,
+    unique: bool,
+    ttl: u32,
+    data: Vec<u8>,
+}
+
+/// The records that make up the advertisement: the service type, the
+/// instance with its port and path, and a host name of its own so that a
+/// goodbye doesn't flush the machine's real `.local` name from caches.
+struct Service {
+    instance: String,
+    records: Vec<Record>,
+}
+
+impl Service {
+    fn new(ip: Ipv4Addr, port: u16) -> Self {
+        let instance = format!("{}.{}", truncate_label(&format!("ytune on {}", hostname())), SERVICE);
+        let host = host_name();
+
+        let mut srv = Vec::new();
+        srv.extend_from_slice(&0u16.to_be_bytes()); // priority
+        srv.extend_from_slice(&0u16.to_be_bytes()); // weight
+        srv.extend_from_slice(&port.to_be_bytes());
+        write_name(&mut srv, &host);
+
+        let mut txt = Vec::new();
+        for entry in ["path=/remote", concat!("version=", env!("CARGO_PKG_VERSION"))] {
+            txt.push(entry.len() as u8);
+            txt.extend_from_slice(entry.as_bytes());
+        }
+
+        let mut service_name = Vec::new();
+        write_name(&mut service_name, SERVICE);
+        let mut instance_name = Vec::new();
+        write_name(&mut instance_name, &instance);
+
+        let record = |name: &str, rtype, unique, ttl, data: Vec<u8>| Record {
+            name: name.to_string(),
+            rtype,
+            unique,
+            ttl,
+            data,
+        };
+        let records = vec![
+            record(SERVICE, TYPE_PTR, false, OTHER_TTL, instance_name),
+            record(&instance, TYPE_SRV, true, HOST_TTL, srv),
+            record(&instance, TYPE_TXT, true, OTHER_TTL, txt),
+            record(&host, TYPE_A, true, HOST_TTL, ip.octets().to_vec()),
+            record(SERVICE_ENUMERATION, TYPE_PTR, false, OTHER_TTL, service_name),
+        ];
+        Service { instance, records }
+    }
+
+    /// Indexes of the records answering `name`/`qtype`.
+    fn answers(&self, name: &str, qtype: u16) -> Vec<usize> {
+        self.records
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| {
+                record.name.eq_ignore_ascii_case(name) && (qtype == TYPE_ANY || qtype == record.rtype)
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+/// Advertises the local API as `_ytune._tcp.local.` on the network `ip`
+/// belongs to, answering mDNS queries for it, so the remote page and other
+/// clients can find ytune without being told its address. Returns when the
+/// event bus is closed, after telling the network the service is gone.
+pub fn run(ip: Ipv4Addr, port: u16, events: Receiver<TrackEvent>) {
+    let socket = match open_socket(ip) {
+        Ok(socket) => Arc::new(socket),
+        Err(e) => {
+            log::warn!("Not advertising the API over mDNS, failed to open port {}: {}", MDNS_PORT, e);
+            return;
+        }
+    };
+    let service = Arc::new(Service::new(ip, port));
+    let shutting_down = Arc::new(AtomicBool::new(false));
+    log::info!("Advertising the API over mDNS as {}", service.instance);
+
+    let responder = {
+        let socket = Arc::clone(&socket);
+        let service = Arc::clone(&service);
+        let shutting_down = Arc::clone(&shutting_down);
+        thread::spawn(move || respond(&socket, &service, &shutting_down))
+    };
+
+    // RFC 6762 section 8.3: announce at least twice, a second apart.
+    for attempt in 0..2 {
+        if attempt > 0 {
+            thread::sleep(ANNOUNCE_INTERVAL);
+        }
+        send_multicast(&socket, &response(&service, &all(&service), &[], None, None));
+    }
+
+    // Only the end of the stream matters here.
+    for _ in events {}
+
+    shutting_down.store(true, Ordering::Relaxed);
+    let _ = responder.join();
+    send_multicast(&socket, &response(&service, &all(&service), &[], None, Some(0)));
+}
+
+/// The `.local` name the advertisement points at, which browsers that
+/// found ytune over mDNS open the remote page on.
+pub fn host_name() -> String {
+    format!("ytune-{}.local", host_label(&hostname()))
+}
+
+fn open_socket(ip: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // The system's own responder usually has the port already.
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_GROUP, &ip)?;
+    socket.set_multicast_if_v4(&ip)?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    Ok(socket.into())
+}
+
+fn respond(socket: &UdpSocket, service: &Service, shutting_down: &AtomicBool) {
+    let mut buffer = [0u8; 9000];
+    while !shutting_down.load(Ordering::Relaxed) {
+        let (length, source) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                log::debug!("mDNS receive failed: {}", e);
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        let Some(query) = parse_query(&buffer[..length]) else {
+            continue;
+        };
+
+        let mut answers = Vec::new();
+        let mut unicast = false;
+        for question in &query.questions {
+            for index in service.answers(&question.name, question.qtype) {
+                if !answers.contains(&index) {
+                    answers.push(index);
+                }
+                unicast |= question.class & UNICAST_RESPONSE != 0;
+            }
+        }
+        if answers.is_empty() {
+            continue;
+        }
+        // Whoever asked for the service will want the rest of it too.
+        let additional: Vec<usize> = all(service).into_iter().filter(|index| !answers.contains(index)).collect();
+
+        if source.port() != MDNS_PORT {
+            // A plain DNS resolver (RFC 6762 section 6.7) expects its ID and
+            // question back, over unicast.
+            let message = response(service, &answers, &additional, Some(&query), None);
+            let _ = socket.send_to(&message, source);
+        } else if unicast {
+            let _ = socket.send_to(&response(service, &answers, &additional, None, None), source);
+        } else {
+            send_multicast(socket, &response(service, &answers, &additional, None, None));
+        }
+    }
+}
+
+fn send_multicast(socket: &UdpSocket, message: &[u8]) {
+    if let Err(e) = socket.send_to(message, SocketAddrV4::new(MDNS_GROUP, MDNS_PORT)) {
+        log::debug!("mDNS send failed: {}", e);
+    }
+}
+
+fn all(service: &Service) -> Vec<usize> {
+    (0..service.records.len()).collect()
+}
+
+struct Question {
+    name: String,
+    qtype: u16,
+    class: u16,
+}
+
+struct Query {
+    id: u16,
+    questions: Vec<Question>,
+}
+
+fn parse_query(message: &[u8]) -> Option<Query> {
+    let id = read_u16(message, 0)?;
+    let flags = read_u16(message, 2)?;
+    // Responses, and anything but a standard query, aren't for us.
+    if flags & 0xf800 != 0 {
+        return None;
+    }
+    let count = read_u16(message, 4)?;
+
+    let mut offset = 12;
+    let mut questions = Vec::new();
+    for _ in 0..count {
+        let (name, next) = read_name(message, offset)?;
+        questions.push(Question {
+            name,
+            qtype: read_u16(message, next)?,
+            class: read_u16(message, next + 2)?,
+        });
+        offset = next + 4;
+    }
+    Some(Query { id, questions })
+}
+
+/// Reads a possibly compressed name, returning it without the trailing dot
+/// and the offset just past it.
+fn read_name(message: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Each pointer must go backwards, which also rules out loops.
+    let mut limit = offset;
+    loop {
+        let length = *message.get(offset)? as usize;
+        match length {
+            0 => {
+                end.get_or_insert(offset + 1);
+                break;
+            }
+            length if length & 0xc0 == 0xc0 => {
+                let target = (read_u16(message, offset)? & 0x3fff) as usize;
+                if target >= limit {
+                    return None;
+                }
+                end.get_or_insert(offset + 2);
+                limit = target;
+                offset = target;
+            }
+            length if length & 0xc0 == 0 => {
+                let label = message.get(offset + 1..offset + 1 + length)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + length;
+            }
+            _ => return None,
+        }
+    }
+    Some((labels.join("."), end?))
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Option<u16> {
+    let bytes = message.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Builds a response carrying `answers` and `additional` records by index.
+/// `query` is echoed for plain DNS resolvers; `ttl` overrides every record's,
+/// 0 being a goodbye.
+fn response(
+    service: &Service,
+    answers: &[usize],
+    additional: &[usize],
+    query: Option<&Query>,
+    ttl: Option<u32>,
+) -> Vec<u8> {
+    let questions = query.map_or(&[][..], |query| &query.questions[..]);
+    let mut message = Vec::new();
+    message.extend_from_slice(&query.map_or(0, |query| query.id).to_be_bytes());
+    message.extend_from_slice(&0x8400u16.to_be_bytes()); // response, authoritative
+    message.extend_from_slice(&(questions.len() as u16).to_be_bytes());
+    message.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    message.extend_from_slice(&0u16.to_be_bytes());
+    message.extend_from_slice(&(additional.len() as u16).to_be_bytes());
+
+    for question in questions {
+        write_name(&mut message, &question.name);
+        message.extend_from_slice(&question.qtype.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    }
+    for &index in answers.iter().chain(additional) {
+        let record = &service.records[index];
+        write_name(&mut message, &record.name);
+        message.extend_from_slice(&record.rtype.to_be_bytes());
+        // Plain resolvers don't know the cache-flush bit.
+        let class = if record.unique && query.is_none() { CLASS_IN | CACHE_FLUSH } else { CLASS_IN };
+        message.extend_from_slice(&class.to_be_bytes());
+        message.extend_from_slice(&ttl.unwrap_or(record.ttl).to_be_bytes());
+        message.extend_from_slice(&(record.data.len() as u16).to_be_bytes());
+        message.extend_from_slice(&record.data);
+    }
+    message
+}
+
+fn write_name(buffer: &mut Vec<u8>, name: &str) {
+    // None of the labels used here contain dots of their own.
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        buffer.push(label.len() as u8);
+        buffer.extend_from_slice(label.as_bytes());
+    }
+    buffer.push(0);
+}
+
+fn hostname() -> String {
+    let name = std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .unwrap_or_default();
+    // Only the first part of a fully qualified name.
+    let name = name.trim().split('.').next().unwrap_or_default().to_string();
+    if name.is_empty() {
+        "desktop".to_string()
+    } else {
+        name
+    }
+}
+
+/// The instance label is free text, but no longer than 63 bytes and without
+/// the dots `write_name` splits on.
+fn truncate_label(label: &str) -> String {
+    let mut result = String::new();
+    for c in label.chars().map(|c| if c == '.' { '-' } else { c }) {
+        if result.len() + c.len_utf8() > 63 {
+            break;
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn host_label(hostname: &str) -> String {
+    let label: String = hostname
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .take(57)
+        .collect();
+    let label = label.trim_matches('-');
+    if label.is_empty() {
+        "desktop".to_string()
+    } else {
+        label.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(id: u16, questions: u16) -> Vec<u8> {
+        let mut message = Vec::new();
+        message.extend_from_slice(&id.to_be_bytes());
+        message.extend_from_slice(&[0, 0]);
+        message.extend_from_slice(&questions.to_be_bytes());
+        message.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        message
+    }
+
+    fn question(message: &mut Vec<u8>, name: &str, qtype: u16, class: u16) {
+        write_name(message, name);
+        message.extend_from_slice(&qtype.to_be_bytes());
+        message.extend_from_slice(&class.to_be_bytes());
+    }
+
+    fn service() -> Service {
+        let instance = format!("ytune on desk.{}", SERVICE);
+        let records = vec![
+            Record {
+                name: SERVICE.to_string(),
+                rtype: TYPE_PTR,
+                unique: false,
+                ttl: OTHER_TTL,
+                data: vec![1, b'x', 0],
+            },
+            Record {
+                name: "ytune-desk.local".to_string(),
+                rtype: TYPE_A,
+                unique: true,
+                ttl: HOST_TTL,
+                data: vec![192, 168, 1, 20],
+            },
+        ];
+        Service { instance, records }
+    }
+
+    #[test]
+    fn ptr_and_srv_questions_are_read_and_answered() {
+        let mut message = header(0x1234, 2);
+        question(&mut message, SERVICE, TYPE_PTR, CLASS_IN | UNICAST_RESPONSE);
+        question(&mut message, &format!("ytune on desk.{}", SERVICE), TYPE_SRV, CLASS_IN);
+
+        let query = parse_query(&message).unwrap();
+        assert_eq!(query.id, 0x1234);
+        assert_eq!(query.questions.len(), 2);
+        assert_eq!(query.questions[0].name, SERVICE);
+        assert_eq!(query.questions[0].qtype, TYPE_PTR);
+        assert_eq!(query.questions[0].class, CLASS_IN | UNICAST_RESPONSE);
+        assert_eq!(query.questions[1].name, "ytune on desk._ytune._tcp.local");
+        assert_eq!(query.questions[1].qtype, TYPE_SRV);
+
+        let service = Service::new(Ipv4Addr::new(192, 168, 1, 20), 9863);
+        assert_eq!(service.answers("_YTUNE._tcp.local", TYPE_PTR), vec![0]);
+        assert_eq!(service.answers(&service.instance, TYPE_SRV), vec![1]);
+        assert_eq!(service.answers(&service.instance, TYPE_ANY), vec![1, 2]);
+        assert_eq!(service.answers(&host_name(), TYPE_A), vec![3]);
+        assert!(service.answers("_http._tcp.local", TYPE_PTR).is_empty());
+    }
+
+    #[test]
+    fn srv_record_points_at_the_port_and_host() {
+        let service = Service::new(Ipv4Addr::new(192, 168, 1, 20), 9863);
+        let srv = &service.records[1].data;
+        assert_eq!(srv[..6], [0, 0, 0, 0, 0x26, 0x87]);
+        let (target, end) = read_name(srv, 6).unwrap();
+        assert_eq!(target, host_name());
+        assert_eq!(end, srv.len());
+        assert_eq!(service.records[3].data, [192, 168, 1, 20]);
+    }
+
+    #[test]
+    fn compressed_names_follow_pointers_back() {
+        let mut message = header(0, 2);
+        question(&mut message, SERVICE, TYPE_PTR, CLASS_IN);
+        // "desk" followed by a pointer to "_tcp.local" in the first name.
+        let second = message.len();
+        message.extend_from_slice(&[4, b'd', b'e', b's', b'k', 0xc0, 12 + 7]);
+        message.extend_from_slice(&TYPE_SRV.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+        let query = parse_query(&message).unwrap();
+        assert_eq!(query.questions[1].name, "desk._tcp.local");
+        assert_eq!(query.questions[1].qtype, TYPE_SRV);
+        assert_eq!(read_name(&message, second).unwrap().1, second + 7);
+    }
+
+    #[test]
+    fn pointer_loops_and_forward_pointers_are_rejected() {
+        let mut to_itself = header(0, 1);
+        to_itself.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1]);
+        assert!(parse_query(&to_itself).is_none());
+
+        let mut through_a_label = header(0, 1);
+        through_a_label.extend_from_slice(&[1, b'a', 0xc0, 12, 0, 1, 0, 1]);
+        assert!(parse_query(&through_a_label).is_none());
+
+        let mut forward = header(0, 1);
+        forward.extend_from_slice(&[0xc0, 14, 1, b'a', 0, 0, 1, 0, 1]);
+        assert!(parse_query(&forward).is_none());
+    }
+
+    #[test]
+    fn truncated_queries_are_rejected() {
+        assert!(parse_query(&[0x12, 0x34, 0, 0, 0]).is_none());
+        assert!(parse_query(&header(0, 1)).is_none());
+
+        let mut message = header(0, 1);
+        question(&mut message, SERVICE, TYPE_PTR, CLASS_IN);
+        for length in 12..message.len() {
+            assert!(parse_query(&message[..length]).is_none(), "cut at {}", length);
+        }
+        assert!(parse_query(&message).is_some());
+    }
+
+    #[test]
+    fn responses_are_not_taken_as_queries() {
+        let mut message = header(0, 1);
+        message[2] = 0x84;
+        question(&mut message, SERVICE, TYPE_PTR, CLASS_IN);
+        assert!(parse_query(&message).is_none());
+    }
+
+    #[test]
+    fn announcement_sets_cache_flush_on_unique_records() {
+        let message = response(&service(), &[0], &[1], None, None);
+        let mut expected = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 1];
+        write_name(&mut expected, SERVICE);
+        expected.extend_from_slice(&[0, 12, 0, 1]);
+        expected.extend_from_slice(&OTHER_TTL.to_be_bytes());
+        expected.extend_from_slice(&[0, 3, 1, b'x', 0]);
+        write_name(&mut expected, "ytune-desk.local");
+        expected.extend_from_slice(&[0, 1, 0x80, 1]);
+        expected.extend_from_slice(&HOST_TTL.to_be_bytes());
+        expected.extend_from_slice(&[0, 4, 192, 168, 1, 20]);
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn goodbye_sends_every_record_with_a_zero_ttl() {
+        let message = response(&service(), &[0, 1], &[], None, Some(0));
+        let mut expected = vec![0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 0];
+        write_name(&mut expected, SERVICE);
+        expected.extend_from_slice(&[0, 12, 0, 1, 0, 0, 0, 0, 0, 3, 1, b'x', 0]);
+        write_name(&mut expected, "ytune-desk.local");
+        expected.extend_from_slice(&[0, 1, 0x80, 1, 0, 0, 0, 0, 0, 4, 192, 168, 1, 20]);
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn plain_dns_answers_echo_the_query_without_cache_flush() {
+        let mut message = header(0xbeef, 1);
+        question(&mut message, "ytune-desk.local", TYPE_A, CLASS_IN);
+        let query = parse_query(&message).unwrap();
+
+        let answer = response(&service(), &[1], &[], Some(&query), None);
+        let mut expected = vec![0xbe, 0xef, 0x84, 0, 0, 1, 0, 1, 0, 0, 0, 0];
+        question(&mut expected, "ytune-desk.local", TYPE_A, CLASS_IN);
+        write_name(&mut expected, "ytune-desk.local");
+        expected.extend_from_slice(&[0, 1, 0, 1]);
+        expected.extend_from_slice(&HOST_TTL.to_be_bytes());
+        expected.extend_from_slice(&[0, 4, 192, 168, 1, 20]);
+        assert_eq!(answer, expected);
+    }
+
+    #[test]
+    fn host_labels_are_lowercase_alphanumerics_and_dashes() {
+        assert_eq!(host_label("My Desk.PC"), "my-desk-pc");
+        assert_eq!(host_label("--"), "desktop");
+        assert_eq!(host_label(&"a".repeat(80)).len(), 57);
+        assert_eq!(truncate_label("ytune on a.b"), "ytune on a-b");
+        assert!(truncate_label(&"é".repeat(40)).len() <= 63);
+    }
+}
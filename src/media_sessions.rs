@@ -0,0 +1,82 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use windows::{
+    Media::Control::{
+        GlobalSystemMediaTransportControlsSessionManager as SessionManager,
+        GlobalSystemMediaTransportControlsSessionPlaybackStatus as PlaybackStatus,
+    },
+    Win32::System::WinRT::{RoInitialize, RO_INIT_MULTITHREADED},
+};
+use wry::application::event_loop::EventLoopProxy;
+
+use crate::{control::PlayerCommand, LastTrackInfo, UserEvent};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pauses the player whenever another app's media session starts playing,
+/// going by the sessions Windows shows in its media flyout. Only other
+/// sessions starting count, never ytune's own or the player being resumed,
+/// so pausing can't set anything off again. Returns when the event loop is
+/// gone.
+pub fn run_pause_for_others(last_track: Arc<Mutex<LastTrackInfo>>, proxy: EventLoopProxy<UserEvent>) {
+    if let Err(e) = unsafe { RoInitialize(RO_INIT_MULTITHREADED) } {
+        log::warn!("Not pausing for other media, WinRT is unavailable: {}", e);
+        return;
+    }
+    let manager = match SessionManager::RequestAsync().and_then(|request| request.get()) {
+        Ok(manager) => manager,
+        Err(e) => {
+            log::warn!("Not pausing for other media, no media sessions: {}", e);
+            return;
+        }
+    };
+    // The webview's audio shows up as ytune.exe, or as the runtime itself.
+    let own_names: Vec<String> = std::env::current_exe()
+        .ok()
+        .and_then(|path| path.file_name().map(|name| name.to_string_lossy().to_lowercase()))
+        .into_iter()
+        .chain(["msedgewebview2".to_string()])
+        .collect();
+
+    let mut playing = others_playing(&manager, &own_names).unwrap_or_default();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let now_playing = match others_playing(&manager, &own_names) {
+            Ok(now_playing) => now_playing,
+            Err(e) => {
+                log::debug!("Failed to read media sessions: {}", e);
+                continue;
+            }
+        };
+
+        if let Some(app) = now_playing.iter().find(|app| !playing.contains(*app)) {
+            if last_track.lock().unwrap().is_playing {
+                log::info!("{} started playing, pausing", app);
+                if proxy.send_event(UserEvent::PlayerCommand(PlayerCommand::Pause)).is_err() {
+                    return;
+                }
+            }
+        }
+        playing = now_playing;
+    }
+}
+
+fn others_playing(manager: &SessionManager, own_names: &[String]) -> windows::core::Result<HashSet<String>> {
+    let mut playing = HashSet::new();
+    for session in manager.GetSessions()? {
+        let app = session.SourceAppUserModelId()?.to_string();
+        let lowercase = app.to_lowercase();
+        if own_names.iter().any(|own| lowercase.contains(own.as_str())) {
+            continue;
+        }
+        if session.GetPlaybackInfo()?.PlaybackStatus()? == PlaybackStatus::Playing {
+            playing.insert(app);
+        }
+    }
+    Ok(playing)
+}
@@ -0,0 +1,199 @@
+use std::{
+    fmt::Write,
+    sync::{mpsc::Receiver, Mutex},
+};
+
+use crate::{
+    events::{TrackEvent, TrackEventKind},
+    scrobble_rules::ListenTimer,
+    LastTrackInfo,
+};
+
+/// What `/metrics` counts. They are kept since startup and reset with it.
+#[derive(Clone, Copy, Debug)]
+pub enum Counter {
+    TracksPlayed,
+    ListeningSeconds,
+    DiscordReconnects,
+    ScrobbleFailures,
+    ScraperWatchdogFirings,
+}
+
+const COUNTERS: [Counter; 5] = [
+    Counter::TracksPlayed,
+    Counter::ListeningSeconds,
+    Counter::DiscordReconnects,
+    Counter::ScrobbleFailures,
+    Counter::ScraperWatchdogFirings,
+];
+
+impl Counter {
+    fn name(self) -> &'static str {
+        match self {
+            Counter::TracksPlayed => "tracks_played_total",
+            Counter::ListeningSeconds => "listening_seconds_total",
+            Counter::DiscordReconnects => "discord_reconnects_total",
+            Counter::ScrobbleFailures => "scrobble_failures_total",
+            Counter::ScraperWatchdogFirings => "scraper_watchdog_firings_total",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Counter::TracksPlayed => "Tracks started since ytune started.",
+            Counter::ListeningSeconds => "Seconds of playback heard since ytune started.",
+            Counter::DiscordReconnects => "Times the Discord connection broke and was retried.",
+            Counter::ScrobbleFailures => "Last.fm scrobble submissions that failed.",
+            Counter::ScraperWatchdogFirings => "Times the page stopped answering and was reloaded.",
+        }
+    }
+}
+
+/// The counters behind `/metrics`, shared by the threads that keep them.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<[u64; COUNTERS.len()]>,
+}
+
+impl Metrics {
+    pub fn add(&self, counter: Counter, amount: u64) {
+        self.counters.lock().unwrap()[counter as usize] += amount;
+    }
+
+    pub fn increment(&self, counter: Counter) {
+        self.add(counter, 1);
+    }
+
+    /// The counters and the current track in the Prometheus text format.
+    pub fn render(&self, track: &LastTrackInfo) -> String {
+        let counters = *self.counters.lock().unwrap();
+        let mut out = String::new();
+        for counter in COUNTERS {
+            let name = counter.name();
+            let _ = writeln!(out, "# HELP {} {}", name, counter.help());
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, counters[counter as usize]);
+        }
+
+        let _ = writeln!(out, "# HELP now_playing The current track, 1 while playing and 0 while paused.");
+        let _ = writeln!(out, "# TYPE now_playing gauge");
+        if track.title.is_some() {
+            let _ = writeln!(
+                out,
+                "now_playing{{title=\"{}\",artist=\"{}\"}} {}",
+                escape_label(track.title.as_deref().unwrap_or_default()),
+                escape_label(track.artist.as_deref().unwrap_or_default()),
+                u8::from(track.is_playing)
+            );
+        }
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counts tracks started and time listened from track events. Returns when
+/// the event bus is closed.
+pub fn run_counter(metrics: &Metrics, events: Receiver<TrackEvent>) {
+    let mut listen_timer = ListenTimer::default();
+    let mut counted_sec = 0;
+
+    for event in events {
+        if event.kind == TrackEventKind::Started {
+            metrics.increment(Counter::TracksPlayed);
+            counted_sec = 0;
+        }
+        // The timer starts over when the track is seeked back.
+        let listened_sec = listen_timer.observe(&event);
+        if listened_sec > counted_sec {
+            metrics.add(Counter::ListeningSeconds, listened_sec - counted_sec);
+        }
+        counted_sec = listened_sec;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn render_describes_every_counter() {
+        let metrics = Metrics::default();
+        metrics.increment(Counter::DiscordReconnects);
+        metrics.add(Counter::ListeningSeconds, 90);
+        let rendered = metrics.render(&LastTrackInfo::default());
+
+        assert_eq!(
+            rendered.lines().take(6).collect::<Vec<_>>(),
+            [
+                "# HELP tracks_played_total Tracks started since ytune started.",
+                "# TYPE tracks_played_total counter",
+                "tracks_played_total 0",
+                "# HELP listening_seconds_total Seconds of playback heard since ytune started.",
+                "# TYPE listening_seconds_total counter",
+                "listening_seconds_total 90",
+            ]
+        );
+        for counter in COUNTERS {
+            let name = counter.name();
+            assert!(name.ends_with("_total"), "{}", name);
+            assert!(rendered.contains(&format!("# TYPE {} counter\n", name)), "{}", name);
+        }
+        assert!(rendered.contains("\ndiscord_reconnects_total 1\n"));
+        // The exposition format ends every line, the last included.
+        assert!(rendered.ends_with("# TYPE now_playing gauge\n"));
+    }
+
+    #[test]
+    fn now_playing_escapes_its_labels() {
+        let track = LastTrackInfo {
+            title: Some("Say \"Hi\"\nback\\slash".to_string()),
+            artist: Some("Artist".to_string()),
+            is_playing: true,
+            ..LastTrackInfo::default()
+        };
+        let rendered = Metrics::default().render(&track);
+        assert!(rendered.ends_with("now_playing{title=\"Say \\\"Hi\\\"\\nback\\\\slash\",artist=\"Artist\"} 1\n"));
+
+        let paused = LastTrackInfo {
+            is_playing: false,
+            ..track
+        };
+        assert!(Metrics::default().render(&paused).ends_with("} 0\n"));
+    }
+
+    #[test]
+    fn counter_counts_plays_and_listening_time() {
+        let metrics = Metrics::default();
+        let (sender, receiver) = mpsc::channel();
+        let playing = |kind, position_sec, timestamp| TrackEvent {
+            kind,
+            track: LastTrackInfo {
+                title: Some("Song".to_string()),
+                position_sec: Some(position_sec),
+                is_playing: true,
+                ..LastTrackInfo::default()
+            },
+            timestamp,
+        };
+        sender.send(playing(TrackEventKind::Started, 0, 100)).unwrap();
+        sender.send(playing(TrackEventKind::Position, 30, 130)).unwrap();
+        // Seeking back starts the timer over without taking time away.
+        sender.send(playing(TrackEventKind::Position, 0, 131)).unwrap();
+        sender.send(playing(TrackEventKind::Position, 10, 141)).unwrap();
+        sender.send(playing(TrackEventKind::Started, 0, 150)).unwrap();
+        drop(sender);
+        run_counter(&metrics, receiver);
+
+        let rendered = metrics.render(&LastTrackInfo::default());
+        assert!(rendered.contains("\ntracks_played_total 2\n"));
+        assert!(rendered.contains("\nlistening_seconds_total 40\n"));
+    }
+}
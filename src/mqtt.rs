@@ -0,0 +1,241 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use rumqttc::{Client, Event, LastWill, MqttOptions, Outgoing, Packet, QoS, Transport};
+use serde_json::json;
+use wry::{application::event_loop::EventLoopProxy, webview::Url};
+
+use crate::{
+    api::types::{MqttState, MqttTrack, PlaybackState},
+    config::MqttConfig,
+    control::PlayerCommand,
+    events::{TrackEvent, TrackEventKind},
+    LastTrackInfo, UserEvent,
+};
+
+const DEFAULT_PORT: u16 = 1883;
+const DEFAULT_TLS_PORT: u16 = 8883;
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const REQUEST_CAPACITY: usize = 32;
+
+struct Topics {
+    state: String,
+    track: String,
+    availability: String,
+    command: String,
+    discovery: String,
+}
+
+impl Topics {
+    fn new(config: &MqttConfig) -> Self {
+        let base = config.base_topic.trim_end_matches('/');
+        Topics {
+            state: format!("{}/state", base),
+            track: format!("{}/track", base),
+            availability: format!("{}/availability", base),
+            command: format!("{}/command", base),
+            discovery: format!(
+                "{}/sensor/ytune/now_playing/config",
+                config.discovery_prefix.trim_end_matches('/')
+            ),
+        }
+    }
+}
+
+/// Mirrors playback to an MQTT broker for Home Assistant and takes
+/// play/pause/next/previous commands from `<base>/command`. Returns when the
+/// event bus is closed, after marking ytune offline.
+pub fn run(config: MqttConfig, events: Receiver<TrackEvent>, proxy: EventLoopProxy<UserEvent>) {
+    let Some(options) = options(&config) else {
+        return;
+    };
+    let topics = Arc::new(Topics::new(&config));
+    let (client, mut connection) = Client::new(options, REQUEST_CAPACITY);
+    let shutting_down = Arc::new(AtomicBool::new(false));
+
+    let connection_thread = {
+        let client = client.clone();
+        let topics = Arc::clone(&topics);
+        let shutting_down = Arc::clone(&shutting_down);
+        thread::spawn(move || {
+            // Iterating drives the connection, including reconnects.
+            for notification in connection.iter() {
+                match notification {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        log::info!("Connected to the MQTT broker");
+                        announce(&client, &topics);
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) if publish.topic == topics.command => {
+                        let payload = String::from_utf8_lossy(&publish.payload);
+                        match parse_command(payload.trim()) {
+                            Some(command) => {
+                                let _ = proxy.send_event(UserEvent::PlayerCommand(command));
+                            }
+                            None => log::debug!("Ignoring MQTT command '{}'", payload),
+                        }
+                    }
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                    Ok(_) => {}
+                    Err(_) if shutting_down.load(Ordering::Relaxed) => break,
+                    Err(e) => {
+                        log::warn!("MQTT connection error, retrying: {}", e);
+                        thread::sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        })
+    };
+
+    for event in events {
+        let state = match event.kind {
+            TrackEventKind::Started | TrackEventKind::Resumed if event.track.is_playing => {
+                PlaybackState::Playing
+            }
+            TrackEventKind::Started | TrackEventKind::Paused => PlaybackState::Paused,
+            TrackEventKind::Stopped => PlaybackState::Stopped,
+            TrackEventKind::Resumed | TrackEventKind::Position => continue,
+        };
+        publish(&client, &topics.state, state_payload(state));
+        publish(&client, &topics.track, track_payload(&event.track));
+    }
+
+    // Clear the retained track and go offline before disconnecting.
+    shutting_down.store(true, Ordering::Relaxed);
+    publish(&client, &topics.state, state_payload(PlaybackState::Stopped));
+    publish(&client, &topics.track, "");
+    publish(&client, &topics.availability, "offline");
+    let _ = client.disconnect();
+    let _ = connection_thread.join();
+}
+
+fn options(config: &MqttConfig) -> Option<MqttOptions> {
+    let broker = config.broker.as_deref()?;
+    let url = match Url::parse(broker) {
+        Ok(url) => url,
+        Err(e) => {
+            log::warn!("Invalid MQTT broker URL '{}': {}", broker, e);
+            return None;
+        }
+    };
+    let tls = match url.scheme() {
+        "mqtt" | "tcp" => false,
+        "mqtts" | "ssl" => true,
+        other => {
+            log::warn!("Unsupported MQTT scheme '{}', use mqtt:// or mqtts://", other);
+            return None;
+        }
+    };
+    let host = url.host_str()?.to_string();
+    let port = url
+        .port()
+        .unwrap_or(if tls { DEFAULT_TLS_PORT } else { DEFAULT_PORT });
+
+    // Only needs to differ between running copies; `RandomState` is seeded
+    // randomly per process.
+    let suffix = RandomState::new().build_hasher().finish() as u32;
+    let client_id = format!("ytune-{:08x}", suffix);
+    let mut options = MqttOptions::new(client_id, host, port);
+    options.set_keep_alive(KEEP_ALIVE);
+    options.set_last_will(LastWill::new(
+        Topics::new(config).availability,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+    }
+    if tls {
+        options.set_transport(Transport::tls_with_default_config());
+    }
+    Some(options)
+}
+
+/// Sent on every (re)connect: availability, Home Assistant discovery and
+/// the command subscription.
+fn announce(client: &Client, topics: &Topics) {
+    let discovery = json!({
+        "name": "ytune now playing",
+        "unique_id": "ytune_now_playing",
+        "icon": "mdi:music",
+        "state_topic": topics.state,
+        "value_template": "{{ value_json.state }}",
+        "json_attributes_topic": topics.track,
+        "availability_topic": topics.availability,
+        "device": { "identifiers": ["ytune"], "name": "ytune", "sw_version": env!("CARGO_PKG_VERSION") },
+    });
+    publish(client, &topics.discovery, discovery.to_string());
+    publish(client, &topics.availability, "online");
+    if let Err(e) = client.try_subscribe(topics.command.as_str(), QoS::AtLeastOnce) {
+        log::warn!("Failed to subscribe to {}: {}", topics.command, e);
+    }
+}
+
+/// Never blocks: while the broker is away the request queue fills up, and
+/// waiting on it from the connection thread would stall reconnecting. Every
+/// message is retained and re-sent on the next event, so dropping is fine.
+fn publish(client: &Client, topic: &str, payload: impl Into<Vec<u8>>) {
+    if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, true, payload) {
+        log::warn!("Failed to publish to {}: {}", topic, e);
+    }
+}
+
+fn state_payload(state: PlaybackState) -> String {
+    serde_json::to_string(&MqttState::new(state)).unwrap_or_default()
+}
+
+fn track_payload(track: &LastTrackInfo) -> String {
+    serde_json::to_string(&MqttTrack::new(track)).unwrap_or_default()
+}
+
+fn parse_command(payload: &str) -> Option<PlayerCommand> {
+    match payload.to_ascii_lowercase().as_str() {
+        "play" => Some(PlayerCommand::Play),
+        "pause" => Some(PlayerCommand::Pause),
+        "playpause" | "play_pause" | "toggle" => Some(PlayerCommand::PlayPause),
+        "next" => Some(PlayerCommand::Next),
+        "previous" | "prev" => Some(PlayerCommand::Previous),
+        "refresh" => Some(PlayerCommand::Refresh),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_are_the_versioned_wire_types() {
+        assert_eq!(state_payload(PlaybackState::Playing), r#"{"v":1,"state":"playing"}"#);
+        let track = LastTrackInfo {
+            title: Some("Song".to_string()),
+            artist: Some("Artist".to_string()),
+            position_sec: Some(42),
+            ..LastTrackInfo::default()
+        };
+        let payload: serde_json::Value = serde_json::from_str(&track_payload(&track)).unwrap();
+        assert_eq!(payload["v"], 1);
+        assert_eq!(payload["title"], "Song");
+        assert_eq!(payload["artist"], "Artist");
+        assert_eq!(payload["position_sec"], 42);
+        assert_eq!(payload["album"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn commands_ignore_case_and_take_aliases() {
+        assert_eq!(parse_command("PLAY"), Some(PlayerCommand::Play));
+        assert_eq!(parse_command("toggle"), Some(PlayerCommand::PlayPause));
+        assert_eq!(parse_command("prev"), Some(PlayerCommand::Previous));
+        assert_eq!(parse_command("rewind"), None);
+    }
+}
@@ -0,0 +1,82 @@
+use std::process::Command;
+
+/// PowerShell's own app ID; toasts from an unregistered ID are dropped.
+#[cfg(target_os = "windows")]
+const TOAST_APP_ID: &str = r"{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe";
+
+#[cfg(target_os = "windows")]
+const TOAST_SCRIPT: &str = r#"
+$manager = [Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime]
+$xml = $manager::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)
+$text = $xml.GetElementsByTagName('text')
+[void]$text.Item(0).AppendChild($xml.CreateTextNode($env:YTUNE_TITLE))
+[void]$text.Item(1).AppendChild($xml.CreateTextNode($env:YTUNE_BODY))
+$toast = [Windows.UI.Notifications.ToastNotification, Windows.UI.Notifications, ContentType = WindowsRuntime]::new($xml)
+$manager::CreateToastNotifier($env:YTUNE_APP_ID).Show($toast)
+"#;
+
+/// Shows a desktop notification with the platform's own tools: a toast
+/// through PowerShell on Windows, `osascript` on macOS and `notify-send`
+/// elsewhere. The text goes through the environment, so it needs no quoting.
+/// Failures are only logged.
+pub fn show(title: &str, body: &str) {
+    notify(title, body, false);
+}
+
+/// Like `show`, for confirmations not worth keeping: notification servers
+/// that keep a history, like Dunst, leave these out of it.
+pub fn show_transient(title: &str, body: &str) {
+    notify(title, body, true);
+}
+
+fn notify(title: &str, body: &str, transient: bool) {
+    let mut command = platform_command(transient);
+    command.env("YTUNE_TITLE", title).env("YTUNE_BODY", body);
+
+    match command.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::warn!("Notification command exited with {}", status),
+        Err(e) => log::warn!("Failed to show a notification: {}", e),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn platform_command(_transient: bool) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    /// Keeps PowerShell from flashing a console window.
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let mut command = Command::new("powershell");
+    command
+        .args(["-NoProfile", "-NonInteractive", "-Command", TOAST_SCRIPT])
+        .env("YTUNE_APP_ID", TOAST_APP_ID)
+        .creation_flags(CREATE_NO_WINDOW);
+    command
+}
+
+#[cfg(target_os = "macos")]
+fn platform_command(_transient: bool) -> Command {
+    let mut command = Command::new("osascript");
+    command.args([
+        "-e",
+        r#"display notification (system attribute "YTUNE_BODY") with title (system attribute "YTUNE_TITLE")"#,
+    ]);
+    command
+}
+
+/// The category and desktop entry hints let Dunst and the like group and
+/// style ytune's notifications, and find its icon.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_command(transient: bool) -> Command {
+    let mut command = Command::new("sh");
+    command.args([
+        "-c",
+        r#"exec notify-send --app-name=ytune --category=x-gnome.music --hint=string:desktop-entry:ytune "$@" "$YTUNE_TITLE" "$YTUNE_BODY""#,
+        "notify-send",
+    ]);
+    if transient {
+        command.arg("--hint=boolean:transient:true");
+    }
+    command
+}
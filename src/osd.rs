@@ -0,0 +1,218 @@
+use std::{
+    sync::{mpsc::Receiver, Arc},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+use wry::{
+    application::{
+        dpi::{LogicalSize, PhysicalPosition},
+        event_loop::{EventLoopProxy, EventLoopWindowTarget},
+        window::{Window, WindowBuilder},
+    },
+    webview::{WebView, WebViewBuilder},
+};
+
+use crate::{
+    art_cache::ArtCache,
+    config::{OsdConfig, OsdCorner},
+    events::{TrackEvent, TrackEventKind},
+    UserEvent,
+};
+
+const OSD_HTML: &str = include_str!("assets/osd.html");
+/// How long the overlay stays up; the page's fade takes the same time.
+pub const SHOW_FOR: Duration = Duration::from_secs(4);
+/// How long a track change waits for its album art before showing without.
+const ART_WAIT: Duration = Duration::from_millis(1500);
+const WIDTH: f64 = 360.0;
+const HEIGHT: f64 = 96.0;
+
+/// What the overlay shows for a track.
+#[derive(Clone, Debug, Serialize)]
+pub struct OsdTrack {
+    pub title: String,
+    pub artist: String,
+    /// The album art as a `data:` URL, when it was cached in time.
+    pub art: Option<String>,
+}
+
+/// A borderless, click-through, always-on-top window in a corner of the
+/// screen that shows the new track for a few seconds. It is made once and
+/// only shown and hidden after that.
+pub struct OsdWindow {
+    webview: WebView,
+    config: OsdConfig,
+    /// Bumped for every track shown, so only the latest hides it.
+    generation: u64,
+}
+
+impl OsdWindow {
+    pub fn open(target: &EventLoopWindowTarget<UserEvent>, config: &OsdConfig) -> wry::Result<Self> {
+        let builder = WindowBuilder::new()
+            .with_title("ytune OSD")
+            .with_inner_size(LogicalSize::new(WIDTH, HEIGHT))
+            .with_decorations(false)
+            .with_resizable(false)
+            .with_transparent(true)
+            .with_always_on_top(true)
+            .with_focused(false)
+            .with_visible(false);
+        #[cfg(target_os = "windows")]
+        let builder = {
+            use wry::application::platform::windows::WindowBuilderExtWindows;
+            builder.with_skip_taskbar(true)
+        };
+        #[cfg(target_os = "linux")]
+        let builder = {
+            use wry::application::platform::unix::WindowBuilderExtUnix;
+            builder.with_skip_taskbar(true)
+        };
+        let window = builder.build(target)?;
+        if let Err(e) = window.set_ignore_cursor_events(true) {
+            log::warn!("The OSD will catch clicks: {}", e);
+        }
+        let webview = WebViewBuilder::new(window)?
+            .with_transparent(true)
+            .with_html(OSD_HTML)?
+            .build()?;
+        Ok(OsdWindow {
+            webview,
+            config: config.clone(),
+            generation: 0,
+        })
+    }
+
+    /// Shows `track` in the configured corner, unless a fullscreen app is
+    /// in front. Returns the generation to pass to `hide`, or None when it
+    /// wasn't shown.
+    pub fn show(&mut self, track: &OsdTrack) -> Option<u64> {
+        if fullscreen_app_running() {
+            log::debug!("Not showing the OSD over a fullscreen app");
+            return None;
+        }
+        let script = format!(
+            "window.__ytuneShowOsd({});",
+            serde_json::to_string(track).unwrap_or_default()
+        );
+        if let Err(e) = self.webview.evaluate_script(&script) {
+            log::warn!("Failed to update the OSD: {}", e);
+            return None;
+        }
+
+        let window = self.webview.window();
+        if let Some(position) = corner_position(window, &self.config) {
+            window.set_outer_position(position);
+        }
+        show_without_focus(window);
+        self.generation += 1;
+        Some(self.generation)
+    }
+
+    /// Hides the overlay, unless another track was shown since `generation`.
+    pub fn hide(&self, generation: u64) {
+        if generation == self.generation {
+            self.webview.window().set_visible(false);
+        }
+    }
+}
+
+/// The window's top-left corner for the configured corner of the
+/// configured display, falling back to the primary one.
+fn corner_position(window: &Window, config: &OsdConfig) -> Option<PhysicalPosition<i32>> {
+    let monitor = match config.monitor {
+        0 => None,
+        n => window.available_monitors().nth(n - 1),
+    }
+    .or_else(|| window.primary_monitor())
+    .or_else(|| window.current_monitor())?;
+
+    let scale = monitor.scale_factor();
+    let origin = monitor.position();
+    let size = monitor.size();
+    let width = (WIDTH * scale) as i32;
+    let height = (HEIGHT * scale) as i32;
+    let margin = (f64::from(config.margin) * scale) as i32;
+    let left = origin.x + margin;
+    let right = origin.x + size.width as i32 - width - margin;
+    let top = origin.y + margin;
+    let bottom = origin.y + size.height as i32 - height - margin;
+    let (x, y) = match config.corner {
+        OsdCorner::TopLeft => (left, top),
+        OsdCorner::TopRight => (right, top),
+        OsdCorner::BottomLeft => (left, bottom),
+        OsdCorner::BottomRight => (right, bottom),
+    };
+    Some(PhysicalPosition::new(x, y))
+}
+
+/// `set_visible` activates the window on Windows, which would take focus
+/// from whatever the user is typing in.
+#[cfg(target_os = "windows")]
+fn show_without_focus(window: &Window) {
+    use windows::Win32::{
+        Foundation::HWND,
+        UI::WindowsAndMessaging::{ShowWindow, SW_SHOWNOACTIVATE},
+    };
+    use wry::application::platform::windows::WindowExtWindows;
+
+    unsafe {
+        ShowWindow(HWND(window.hwnd() as isize), SW_SHOWNOACTIVATE);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn show_without_focus(window: &Window) {
+    window.set_visible(true);
+}
+
+/// Whether a fullscreen game, video or presentation is in front, going by
+/// what Windows tells apps deciding whether to notify.
+#[cfg(target_os = "windows")]
+fn fullscreen_app_running() -> bool {
+    use windows::Win32::UI::Shell::{
+        SHQueryUserNotificationState, QUNS_BUSY, QUNS_PRESENTATION_MODE, QUNS_RUNNING_D3D_FULL_SCREEN,
+    };
+
+    match unsafe { SHQueryUserNotificationState() } {
+        Ok(state) => [QUNS_BUSY, QUNS_RUNNING_D3D_FULL_SCREEN, QUNS_PRESENTATION_MODE].contains(&state),
+        Err(_) => false,
+    }
+}
+
+/// There is no portable way to tell elsewhere.
+#[cfg(not(target_os = "windows"))]
+fn fullscreen_app_running() -> bool {
+    false
+}
+
+/// Asks the event loop to show the overlay for every track that starts,
+/// with its album art from `art_cache` when that arrives in time. Returns
+/// when the event bus is closed.
+pub fn run_watcher(
+    events: Receiver<TrackEvent>,
+    art_cache: Arc<ArtCache>,
+    art_size: u32,
+    proxy: EventLoopProxy<UserEvent>,
+) {
+    for event in events {
+        if event.kind != TrackEventKind::Started {
+            continue;
+        }
+        let track = event.track;
+        let art = track
+            .album_art
+            .as_deref()
+            .and_then(|url| art_cache.wait_for(url, art_size, ART_WAIT))
+            .map(|art| format!("data:{};base64,{}", art.content_type, STANDARD.encode(art.bytes.as_slice())));
+        let osd_track = OsdTrack {
+            title: track.title.unwrap_or_default(),
+            artist: track.artist.unwrap_or_default(),
+            art,
+        };
+        if proxy.send_event(UserEvent::ShowOsd(osd_track)).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,96 @@
+#![cfg(target_os = "windows")]
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    os::windows::io::FromRawHandle,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE},
+        Storage::FileSystem::PIPE_ACCESS_DUPLEX,
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        },
+    },
+};
+use wry::application::event_loop::EventLoopProxy;
+
+use crate::{ipc_protocol, LastTrackInfo, UserEvent};
+
+pub const PIPE_NAME: &str = r"\\.\pipe\ytune-control";
+const BUFFER_SIZE: u32 = 4096;
+
+/// Serves `\\.\pipe\ytune-control` on a background thread. Clients write one
+/// JSON command per line and get one JSON response line back.
+pub fn start(last_track: Arc<Mutex<LastTrackInfo>>, proxy: EventLoopProxy<UserEvent>) {
+    thread::spawn(move || loop {
+        let pipe = match accept() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                log::error!("Control pipe stopped: {}", e);
+                return;
+            }
+        };
+        let last_track = Arc::clone(&last_track);
+        let proxy = proxy.clone();
+        thread::spawn(move || {
+            if let Err(e) = serve_client(pipe, &last_track, &proxy) {
+                log::debug!("Control pipe client disconnected: {}", e);
+            }
+        });
+    });
+}
+
+/// Creates a new pipe instance and waits for a client to connect to it.
+fn accept() -> io::Result<File> {
+    let name: Vec<u16> = PIPE_NAME.encode_utf16().chain(std::iter::once(0)).collect();
+
+    let handle = unsafe {
+        CreateNamedPipeW(
+            PCWSTR(name.as_ptr()),
+            PIPE_ACCESS_DUPLEX,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            BUFFER_SIZE,
+            BUFFER_SIZE,
+            0,
+            None,
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+    // Owning the handle through `File` closes it when the client is done.
+    let pipe = unsafe { File::from_raw_handle(handle.0 as _) };
+
+    match unsafe { ConnectNamedPipe(handle, None) } {
+        Ok(()) => Ok(pipe),
+        // The client connected between creating and waiting; that's fine.
+        Err(e) if e.code() == ERROR_PIPE_CONNECTED.to_hresult() => Ok(pipe),
+        Err(e) => Err(io::Error::other(e)),
+    }
+}
+
+fn serve_client(
+    pipe: File,
+    last_track: &Mutex<LastTrackInfo>,
+    proxy: &EventLoopProxy<UserEvent>,
+) -> io::Result<()> {
+    let mut writer = pipe.try_clone()?;
+    for line in BufReader::new(pipe).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = ipc_protocol::handle_request(&line, last_track, |event| proxy.send_event(event).is_ok());
+        writeln!(writer, "{}", response)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+
+use windows::{
+    core::{w, PCWSTR},
+    Win32::System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, REG_ROUTINE_FLAGS, RRF_RT_REG_DWORD, RRF_RT_REG_SZ},
+};
+
+use crate::config::Config;
+
+/// The media session manager `pause_when_others_play` reads arrived in
+/// Windows 10 version 1809.
+const MEDIA_SESSIONS_BUILD: u32 = 17763;
+const WINDOWS_11_BUILD: u32 = 22000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowsVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+}
+
+impl WindowsVersion {
+    /// Windows 11 still reports itself as 10.0, so only the build tells.
+    pub fn is_windows_11(self) -> bool {
+        self.major == 10 && self.build >= WINDOWS_11_BUILD
+    }
+
+    pub fn has_media_sessions(self) -> bool {
+        self.major > 10 || (self.major == 10 && self.build >= MEDIA_SESSIONS_BUILD)
+    }
+}
+
+impl fmt::Display for WindowsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = if self.is_windows_11() { "11" } else { "10" };
+        write!(f, "Windows {} ({}.{}.{})", name, self.major, self.minor, self.build)
+    }
+}
+
+/// The version as the registry records it; `GetVersionEx` would lie to an
+/// executable without a compatibility manifest. None when it can't be read.
+pub fn check_windows_version() -> Option<WindowsVersion> {
+    let major = read_dword(w!("CurrentMajorVersionNumber"))?;
+    let minor = read_dword(w!("CurrentMinorVersionNumber"))?;
+    let build = read_string(w!("CurrentBuildNumber"))?.trim().parse().ok()?;
+    Some(WindowsVersion { major, minor, build })
+}
+
+/// Whether Discord has an IPC pipe open, i.e. is running. Lists the pipes
+/// rather than opening one, which would take a connection from Discord.
+#[cfg(feature = "discord")]
+pub fn is_discord_ipc_available() -> bool {
+    std::fs::read_dir(r"\\.\pipe\").is_ok_and(|pipes| {
+        pipes
+            .flatten()
+            .any(|pipe| pipe.file_name().to_string_lossy().starts_with("discord-ipc-"))
+    })
+}
+
+/// Logs what the enabled features can't do on this system, and returns
+/// whether `pause_when_others_play` can run.
+pub fn log_capabilities(config: &Config) -> bool {
+    let version = check_windows_version();
+    match version {
+        Some(version) => log::info!("Running on {}", version),
+        None => log::warn!("Couldn't read the Windows version; assuming every feature is available"),
+    }
+    #[cfg(feature = "discord")]
+    if config.discord_enabled && !is_discord_ipc_available() {
+        log::info!("Discord isn't running; the presence starts once it is");
+    }
+
+    let media_sessions = match version {
+        Some(version) => version.has_media_sessions(),
+        None => true,
+    };
+    if config.pause_when_others_play && !media_sessions {
+        log::warn!("pause_when_others_play needs Windows 10 version 1809 or later; it is off");
+    }
+    media_sessions
+}
+
+const CURRENT_VERSION_KEY: PCWSTR = w!("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion");
+
+fn read_dword(name: PCWSTR) -> Option<u32> {
+    let mut value: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    read_value(name, RRF_RT_REG_DWORD, &mut value as *mut u32 as *mut std::ffi::c_void, &mut size)?;
+    Some(value)
+}
+
+fn read_string(name: PCWSTR) -> Option<String> {
+    let mut buffer = [0u16; 64];
+    let mut size = std::mem::size_of_val(&buffer) as u32;
+    read_value(name, RRF_RT_REG_SZ, buffer.as_mut_ptr() as *mut std::ffi::c_void, &mut size)?;
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    Some(String::from_utf16_lossy(&buffer[..len]))
+}
+
+fn read_value(name: PCWSTR, flags: REG_ROUTINE_FLAGS, data: *mut std::ffi::c_void, size: &mut u32) -> Option<()> {
+    let result = unsafe {
+        RegGetValueW(HKEY_LOCAL_MACHINE, CURRENT_VERSION_KEY, name, flags, None, Some(data), Some(size))
+    };
+    result.ok()
+}
@@ -0,0 +1,256 @@
+use std::{collections::VecDeque, fs, io, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    config,
+    lastfm::{Love, Scrobble},
+};
+
+const QUEUE_FILE_NAME: &str = "scrobble_queue.json";
+/// Oldest entries are dropped beyond this; Last.fm ignores scrobbles more
+/// than two weeks old anyway.
+const MAX_ENTRIES: usize = 5_000;
+
+/// Scrobbles and love changes waiting to be submitted, kept on disk so they
+/// survive restarts.
+pub struct ScrobbleQueue {
+    path: Option<PathBuf>,
+    entries: VecDeque<Scrobble>,
+    loves: VecDeque<Love>,
+}
+
+#[derive(Default, Deserialize)]
+struct QueueFile {
+    scrobbles: VecDeque<Scrobble>,
+    #[serde(default)]
+    loves: VecDeque<Love>,
+}
+
+/// Queues written before love sync existed are a bare list of scrobbles.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredQueue {
+    Current(QueueFile),
+    Legacy(VecDeque<Scrobble>),
+}
+
+impl ScrobbleQueue {
+    /// Loads the queue left over from previous runs, starting empty when
+    /// there is none or it can't be read.
+    pub fn load() -> Self {
+        Self::load_from(config::data_dir().map(|dir| dir.join(QUEUE_FILE_NAME)))
+    }
+
+    /// Loads the queue kept at `path`; without one it lives in memory only.
+    fn load_from(path: Option<PathBuf>) -> Self {
+        let stored: Option<StoredQueue> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| {
+                serde_json::from_str(&contents)
+                    .map_err(|e| log::warn!("Discarding unreadable scrobble queue: {}", e))
+                    .ok()
+            });
+        let file = match stored {
+            Some(StoredQueue::Current(file)) => file,
+            Some(StoredQueue::Legacy(scrobbles)) => QueueFile {
+                scrobbles,
+                loves: VecDeque::new(),
+            },
+            None => QueueFile::default(),
+        };
+        ScrobbleQueue {
+            path,
+            entries: file.scrobbles,
+            loves: file.loves,
+        }
+    }
+
+    /// Number of queued scrobbles; love changes are not counted.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Queues a scrobble unless the same play is already queued.
+    pub fn push(&mut self, scrobble: Scrobble) {
+        let duplicate = self.entries.iter().any(|queued| {
+            queued.timestamp == scrobble.timestamp
+                && queued.track == scrobble.track
+                && queued.artist == scrobble.artist
+        });
+        if duplicate {
+            return;
+        }
+
+        self.entries.push_back(scrobble);
+        while self.entries.len() > MAX_ENTRIES {
+            if let Some(dropped) = self.entries.pop_front() {
+                log::warn!("Scrobble queue full, dropping {} — {}", dropped.track, dropped.artist);
+            }
+        }
+        self.save();
+    }
+
+    /// Up to `limit` of the oldest entries.
+    pub fn front(&self, limit: usize) -> Vec<Scrobble> {
+        self.entries.iter().take(limit).cloned().collect()
+    }
+
+    pub fn remove_front(&mut self, count: usize) {
+        self.entries.drain(..count.min(self.entries.len()));
+        self.save();
+    }
+
+    pub fn has_loves(&self) -> bool {
+        !self.loves.is_empty()
+    }
+
+    /// Queues a love change. An older change for the same track is replaced,
+    /// since only the final state matters.
+    pub fn push_love(&mut self, love: Love) {
+        self.loves
+            .retain(|queued| queued.track != love.track || queued.artist != love.artist);
+        self.loves.push_back(love);
+        self.save();
+    }
+
+    pub fn front_love(&self) -> Option<Love> {
+        self.loves.front().cloned()
+    }
+
+    pub fn remove_front_love(&mut self) {
+        self.loves.pop_front();
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = write_atomically(path, &self.entries, &self.loves) {
+            log::warn!("Failed to save scrobble queue to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Writes through a temporary file so a crash mid-write can't lose the queue.
+fn write_atomically(
+    path: &PathBuf,
+    scrobbles: &VecDeque<Scrobble>,
+    loves: &VecDeque<Love>,
+) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let contents = serde_json::json!({ "scrobbles": scrobbles, "loves": loves }).to_string();
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, contents)?;
+    fs::rename(temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrobble(track: &str, timestamp: u64) -> Scrobble {
+        Scrobble {
+            artist: "Artist".to_string(),
+            track: track.to_string(),
+            album: None,
+            duration_sec: Some(200),
+            timestamp,
+        }
+    }
+
+    fn tracks(queue: &ScrobbleQueue) -> Vec<String> {
+        queue.front(usize::MAX).into_iter().map(|scrobble| scrobble.track).collect()
+    }
+
+    fn queue_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ytune-queue-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join(QUEUE_FILE_NAME)
+    }
+
+    #[test]
+    fn the_same_play_is_queued_once() {
+        let mut queue = ScrobbleQueue::load_from(None);
+        queue.push(scrobble("Song", 1000));
+        queue.push(scrobble("Song", 1000));
+        // Played again later, or another track started the same second.
+        queue.push(scrobble("Song", 1200));
+        queue.push(scrobble("Other", 1000));
+        queue.push(Scrobble {
+            artist: "Someone else".to_string(),
+            ..scrobble("Song", 1000)
+        });
+        assert_eq!(queue.len(), 4);
+    }
+
+    #[test]
+    fn the_oldest_entries_are_dropped_when_full() {
+        let mut queue = ScrobbleQueue::load_from(None);
+        for timestamp in 0..MAX_ENTRIES as u64 + 2 {
+            queue.push(scrobble(&timestamp.to_string(), timestamp));
+        }
+        assert_eq!(queue.len(), MAX_ENTRIES);
+        assert_eq!(queue.front(1)[0].track, "2");
+        assert_eq!(tracks(&queue).last().unwrap(), &(MAX_ENTRIES + 1).to_string());
+    }
+
+    #[test]
+    fn legacy_bare_lists_load_as_scrobbles() {
+        let path = queue_path("legacy");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let legacy = serde_json::to_string(&[scrobble("First", 1000), scrobble("Second", 1200)]).unwrap();
+        fs::write(&path, legacy).unwrap();
+
+        let queue = ScrobbleQueue::load_from(Some(path.clone()));
+        assert_eq!(tracks(&queue), ["First", "Second"]);
+        assert!(!queue.has_loves());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn unreadable_queues_start_empty() {
+        let path = queue_path("unreadable");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "{ not json").unwrap();
+        assert!(ScrobbleQueue::load_from(Some(path.clone())).is_empty());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn queue_survives_a_restart() {
+        let path = queue_path("restart");
+        let mut queue = ScrobbleQueue::load_from(Some(path.clone()));
+        queue.push(scrobble("First", 1000));
+        queue.push(scrobble("Second", 1200));
+        queue.push(scrobble("Third", 1400));
+        queue.remove_front(1);
+        queue.push_love(Love {
+            artist: "Artist".to_string(),
+            track: "Second".to_string(),
+            loved: true,
+        });
+        drop(queue);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        let mut restarted = ScrobbleQueue::load_from(Some(path.clone()));
+        assert_eq!(tracks(&restarted), ["Second", "Third"]);
+        let love = restarted.front_love().unwrap();
+        assert_eq!((love.track.as_str(), love.loved), ("Second", true));
+
+        restarted.remove_front(2);
+        restarted.remove_front_love();
+        let emptied = ScrobbleQueue::load_from(Some(path.clone()));
+        assert!(emptied.is_empty());
+        assert!(!emptied.has_loves());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
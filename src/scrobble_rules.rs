@@ -0,0 +1,206 @@
+use crate::{
+    config::ScrobbleConfig,
+    events::{TrackEvent, TrackEventKind},
+    LastTrackInfo,
+};
+
+/// Tracks this short are never scrobbled when `skip_short_tracks` is on,
+/// matching Last.fm's rules.
+const SHORT_TRACK_SECONDS: u64 = 30;
+/// A play always counts after this long, however long the track is.
+const MAX_THRESHOLD_SECONDS: u64 = 4 * 60;
+/// Allowance for the scrape timer and the page's rounding when comparing how
+/// far the position moved with how much time passed.
+const LISTEN_SLACK_SECONDS: u64 = 2;
+
+/// Decides whether and when a play counts. Every scrobbling backend goes
+/// through these rules so they all agree.
+#[derive(Clone, Debug)]
+pub struct ScrobbleRules {
+    threshold_percent: u64,
+    min_seconds: u64,
+    skip_short_tracks: bool,
+    /// Lowercased patterns matched against the title and artist.
+    ignore: Vec<String>,
+}
+
+impl ScrobbleRules {
+    pub fn from_config(config: &ScrobbleConfig) -> Self {
+        ScrobbleRules {
+            threshold_percent: u64::from(config.threshold_percent.min(100)),
+            min_seconds: config.min_seconds,
+            skip_short_tracks: config.skip_short_tracks,
+            ignore: config
+                .ignore
+                .iter()
+                .map(|pattern| pattern.trim().to_lowercase())
+                .filter(|pattern| !pattern.is_empty())
+                .collect(),
+        }
+    }
+
+    /// True when the title or artist contains one of the ignore patterns,
+    /// ignoring case.
+    pub fn is_ignored(&self, track: &LastTrackInfo) -> bool {
+        let fields = [track.title.as_deref(), track.artist.as_deref()];
+        fields.into_iter().flatten().any(|field| {
+            let field = field.to_lowercase();
+            self.ignore.iter().any(|pattern| field.contains(pattern.as_str()))
+        })
+    }
+
+    /// Position in seconds at which a play of a track of `duration_sec`
+    /// seconds counts, or `None` when it can never be scrobbled.
+    pub fn threshold(&self, duration_sec: Option<u64>) -> Option<u64> {
+        let duration = duration_sec?;
+        if duration == 0 || (self.skip_short_tracks && duration <= SHORT_TRACK_SECONDS) {
+            return None;
+        }
+        let by_percent = (duration * self.threshold_percent / 100).min(MAX_THRESHOLD_SECONDS);
+        Some(by_percent.max(self.min_seconds))
+    }
+
+    /// Whether `listened_sec` seconds of listening to `track` reach its
+    /// threshold.
+    pub fn should_scrobble(&self, track: &LastTrackInfo, listened_sec: u64) -> bool {
+        if self.is_ignored(track) {
+            return false;
+        }
+        self.threshold(track.duration_sec)
+            .is_some_and(|threshold| listened_sec >= threshold)
+    }
+}
+
+/// Seconds actually listened to in the current play, from the position
+/// reported while the track plays. Paused time doesn't count, a jump forward
+/// counts only as long as it really took, and a jump back or a new play
+/// starts over.
+#[derive(Debug, Default)]
+pub struct ListenTimer {
+    listened_sec: u64,
+    /// Position and time of the last update while playing.
+    last_playing: Option<(u64, u64)>,
+}
+
+impl ListenTimer {
+    /// Counts the time since the previous event and returns the total.
+    pub fn observe(&mut self, event: &TrackEvent) -> u64 {
+        if event.kind == TrackEventKind::Started {
+            *self = ListenTimer::default();
+        }
+
+        let position = event.track.position_sec;
+        if let (Some((last_position, last_time)), Some(position)) = (self.last_playing, position) {
+            if position < last_position {
+                self.listened_sec = 0;
+            } else {
+                let elapsed = event.timestamp.saturating_sub(last_time) + LISTEN_SLACK_SECONDS;
+                self.listened_sec += (position - last_position).min(elapsed);
+            }
+        }
+
+        self.last_playing = match (event.kind, position) {
+            (TrackEventKind::Stopped, _) => None,
+            (_, Some(position)) if event.track.is_playing => Some((position, event.timestamp)),
+            _ => None,
+        };
+        self.listened_sec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(ignore: &[&str]) -> ScrobbleRules {
+        ScrobbleRules::from_config(&ScrobbleConfig {
+            ignore: ignore.iter().map(|pattern| pattern.to_string()).collect(),
+            ..ScrobbleConfig::default()
+        })
+    }
+
+    fn track(title: &str, artist: &str, duration_sec: u64) -> LastTrackInfo {
+        LastTrackInfo {
+            title: Some(title.to_string()),
+            artist: Some(artist.to_string()),
+            duration_sec: Some(duration_sec),
+            ..LastTrackInfo::default()
+        }
+    }
+
+    fn playing(kind: TrackEventKind, position_sec: u64, timestamp: u64) -> TrackEvent {
+        TrackEvent {
+            kind,
+            track: LastTrackInfo {
+                position_sec: Some(position_sec),
+                is_playing: true,
+                ..track("Song", "Artist", 200)
+            },
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn ignore_patterns_match_title_or_artist_ignoring_case() {
+        let rules = rules(&["  Podcast ", "white noise", ""]);
+        assert!(rules.is_ignored(&track("Daily PODCAST #12", "Someone", 200)));
+        assert!(rules.is_ignored(&track("Rain", "White Noise Sleep", 200)));
+        assert!(!rules.is_ignored(&track("Pod", "Cast", 200)));
+        assert!(!rules.should_scrobble(&track("The podcast", "Someone", 200), 200));
+        assert!(rules.should_scrobble(&track("Song", "Someone", 200), 200));
+    }
+
+    #[test]
+    fn no_patterns_ignore_nothing() {
+        assert!(!rules(&[]).is_ignored(&track("Anything", "Anyone", 200)));
+        assert!(!rules(&["  "]).is_ignored(&track("Anything", "Anyone", 200)));
+    }
+
+    #[test]
+    fn short_tracks_are_never_scrobbled() {
+        let rules = rules(&[]);
+        assert_eq!(rules.threshold(Some(SHORT_TRACK_SECONDS)), None);
+        assert_eq!(rules.threshold(Some(SHORT_TRACK_SECONDS + 1)), Some(15));
+        assert_eq!(rules.threshold(Some(0)), None);
+        assert_eq!(rules.threshold(None), None);
+        assert!(!rules.should_scrobble(&track("Intro", "Artist", 20), 20));
+
+        let keep_short = ScrobbleRules::from_config(&ScrobbleConfig {
+            skip_short_tracks: false,
+            ..ScrobbleConfig::default()
+        });
+        assert_eq!(keep_short.threshold(Some(20)), Some(10));
+        assert_eq!(keep_short.threshold(Some(0)), None);
+    }
+
+    #[test]
+    fn threshold_is_capped_at_four_minutes_and_floored_by_min_seconds() {
+        assert_eq!(rules(&[]).threshold(Some(60 * 60)), Some(MAX_THRESHOLD_SECONDS));
+        let strict = ScrobbleRules::from_config(&ScrobbleConfig {
+            threshold_percent: 200,
+            min_seconds: 90,
+            ..ScrobbleConfig::default()
+        });
+        assert_eq!(strict.threshold(Some(60)), Some(90));
+        assert_eq!(strict.threshold(Some(100)), Some(100));
+    }
+
+    #[test]
+    fn listen_timer_counts_only_time_actually_played() {
+        let mut timer = ListenTimer::default();
+        assert_eq!(timer.observe(&playing(TrackEventKind::Started, 0, 1000)), 0);
+        assert_eq!(timer.observe(&playing(TrackEventKind::Position, 10, 1010)), 10);
+
+        // Seeking 100 s ahead within 1 s counts the second and the slack only.
+        assert_eq!(timer.observe(&playing(TrackEventKind::Position, 110, 1011)), 13);
+
+        let mut paused = playing(TrackEventKind::Paused, 110, 1012);
+        paused.track.is_playing = false;
+        assert_eq!(timer.observe(&paused), 13);
+        assert_eq!(timer.observe(&playing(TrackEventKind::Resumed, 110, 1500)), 13);
+        assert_eq!(timer.observe(&playing(TrackEventKind::Position, 120, 1510)), 23);
+
+        assert_eq!(timer.observe(&playing(TrackEventKind::Position, 5, 1515)), 0);
+        assert_eq!(timer.observe(&playing(TrackEventKind::Started, 0, 1520)), 0);
+    }
+}
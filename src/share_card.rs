@@ -0,0 +1,138 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::ImageFormat;
+use serde::Serialize;
+use wry::{
+    application::{
+        dpi::LogicalSize,
+        event_loop::{EventLoopProxy, EventLoopWindowTarget},
+        window::{Window, WindowBuilder},
+    },
+    webview::{WebView, WebViewBuilder},
+};
+
+use crate::{art_cache, config, youtube_url, LastTrackInfo, UserEvent};
+
+const SHARE_CARD_HTML: &str = include_str!("assets/share_card.html");
+/// Art size asked of the CDN; the card shows it at 510 px.
+const ART_SIZE: u32 = 1024;
+const PNG_DATA_URL_PREFIX: &str = "data:image/png;base64,";
+
+/// A card ready to draw: the track, its art, and where the PNG goes.
+#[derive(Debug, Serialize)]
+pub struct CardJob {
+    title: String,
+    artist: String,
+    album: Option<String>,
+    /// The album art as a `data:` URL; a solid card stands in without it.
+    art: Option<String>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+/// Downloads the art for `track` on its own thread, then hands the card to
+/// the event loop to draw. It is saved in the pictures folder, as
+/// `file_name` when given.
+pub fn prepare(track: LastTrackInfo, file_name: Option<String>, proxy: EventLoopProxy<UserEvent>) {
+    thread::spawn(move || {
+        let Some(dir) = output_dir() else {
+            let _ = proxy.send_event(UserEvent::ShareCardDone(Err("No folder to save to".to_string())));
+            return;
+        };
+        let path = dir.join(file_name.unwrap_or_else(default_file_name));
+        let art = track.album_art.as_deref().and_then(|url| {
+            let url = youtube_url::resize_youtube_art_url(url, ART_SIZE);
+            let agent = ureq::AgentBuilder::new().timeout(art_cache::FETCH_TIMEOUT).build();
+            art_cache::fetch(&agent, &url)
+                .map_err(|e| log::warn!("Drawing the share card without art, fetching {} failed: {}", url, e))
+                .ok()
+        });
+        let job = CardJob {
+            title: track.title.unwrap_or_default(),
+            artist: track.artist.unwrap_or_default(),
+            album: track.album,
+            art: art.map(|art| format!("data:{};base64,{}", art.content_type, STANDARD.encode(art.bytes.as_slice()))),
+            path,
+        };
+        let _ = proxy.send_event(UserEvent::DrawShareCard(job));
+    });
+}
+
+/// Whether `name` can be given to `prepare`: a plain `.png` file name, so
+/// a card can't be written outside the pictures folder.
+pub fn is_valid_file_name(name: &str) -> bool {
+    let path = Path::new(name);
+    path.file_name().is_some_and(|file_name| file_name == name)
+        && path.extension().is_some_and(|extension| extension == "png")
+}
+
+/// The pictures folder, or the data directory when there is none.
+fn output_dir() -> Option<PathBuf> {
+    dirs::picture_dir().or_else(config::data_dir)
+}
+
+/// `ytune-<date>-<time>.png`.
+fn default_file_name() -> String {
+    format!("ytune-{}.png", chrono::Local::now().format("%Y-%m-%d-%H%M%S"))
+}
+
+/// A hidden window whose page draws one card on a canvas with the system's
+/// fonts and posts it back as a PNG. Dropping it closes the window.
+pub struct CardRenderer {
+    _webview: WebView,
+}
+
+impl CardRenderer {
+    pub fn open(
+        target: &EventLoopWindowTarget<UserEvent>,
+        job: CardJob,
+        proxy: EventLoopProxy<UserEvent>,
+    ) -> wry::Result<Self> {
+        let window = WindowBuilder::new()
+            .with_title("ytune share card")
+            .with_inner_size(LogicalSize::new(1200.0, 630.0))
+            .with_visible(false)
+            .build(target)?;
+        let card_script = format!(
+            "window.__ytuneCard = {};",
+            serde_json::to_string(&job).unwrap_or_default()
+        );
+        let path = job.path;
+        let webview = WebViewBuilder::new(window)?
+            .with_initialization_script(&card_script)
+            .with_html(SHARE_CARD_HTML)?
+            .with_ipc_handler(move |_window: &Window, data_url: String| {
+                let result = save_png(&data_url, &path).map(|()| path.clone());
+                let _ = proxy.send_event(UserEvent::ShareCardDone(result));
+            })
+            .build()?;
+        Ok(CardRenderer { _webview: webview })
+    }
+}
+
+fn save_png(data_url: &str, path: &Path) -> Result<(), String> {
+    let bytes = data_url
+        .strip_prefix(PNG_DATA_URL_PREFIX)
+        .and_then(|encoded| STANDARD.decode(encoded).ok())
+        .filter(|bytes| image::guess_format(bytes).is_ok_and(|format| format == ImageFormat::Png))
+        .ok_or("The card page sent no PNG")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    // Never replaces a file, whoever asked for the card.
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(&bytes))
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => format!("{} already exists", path.display()),
+            _ => format!("Failed to write {}: {}", path.display(), e),
+        })
+}
@@ -0,0 +1,148 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use wry::webview::Url;
+
+use crate::{youtube_url, LastTrackInfo};
+
+/// The URI scheme `--install-autostart` registers for session links.
+pub const SCHEME: &str = "ytune";
+const PREFIX: &str = "ytune://session/";
+
+/// A track and a position in it, passed from one ytune user to another as
+/// a `ytune://session/…` link. There is no server; the link is the session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub video_id: String,
+    pub position_sec: u64,
+}
+
+impl Session {
+    /// None while nothing with a video ID is playing.
+    pub fn from_track(track: &LastTrackInfo) -> Option<Self> {
+        let video_id = track.video_id.clone().filter(|id| youtube_url::is_video_id(id))?;
+        Some(Session {
+            video_id,
+            position_sec: track.position_sec.unwrap_or(0),
+        })
+    }
+
+    /// `ytune://session/` followed by `<video ID>:<seconds>` in URL-safe base64.
+    pub fn to_uri(&self) -> String {
+        let payload = format!("{}:{}", self.video_id, self.position_sec);
+        format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(payload))
+    }
+
+    /// Reads a link made by `to_uri`, saying what is wrong with it otherwise.
+    pub fn parse(uri: &str) -> Result<Self, String> {
+        let encoded = uri
+            .trim()
+            .strip_prefix(PREFIX)
+            .ok_or_else(|| format!("Not a ytune session link: {}", uri))?;
+        // Some launchers hand the link over with a trailing slash.
+        let payload = URL_SAFE_NO_PAD
+            .decode(encoded.trim_end_matches('/'))
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or("The session link is damaged")?;
+        let (video_id, position) = payload.split_once(':').ok_or("The session link is damaged")?;
+        if !youtube_url::is_video_id(video_id) {
+            return Err("The session link names no video".to_string());
+        }
+        let position_sec = position
+            .parse()
+            .map_err(|_| "The session link has no valid position".to_string())?;
+        Ok(Session {
+            video_id: video_id.to_string(),
+            position_sec,
+        })
+    }
+
+    /// The watch page on `site_url` that plays the track from the position.
+    pub fn watch_url(&self, site_url: &str) -> Url {
+        let mut url = Url::parse(&format!("{}/watch", site_url)).expect("watch URL is valid");
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("v", &self.video_id);
+            if self.position_sec > 0 {
+                query.append_pair("t", &self.position_sec.to_string());
+            }
+        }
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(position_sec: u64) -> Session {
+        Session {
+            video_id: "dQw4w9WgXcQ".to_string(),
+            position_sec,
+        }
+    }
+
+    fn link(payload: &str) -> String {
+        format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(payload))
+    }
+
+    #[test]
+    fn links_round_trip() {
+        let uri = session(93).to_uri();
+        assert!(uri.starts_with("ytune://session/"));
+        assert_eq!(Session::parse(&uri), Ok(session(93)));
+        assert_eq!(Session::parse(&session(0).to_uri()), Ok(session(0)));
+    }
+
+    #[test]
+    fn trailing_slash_and_whitespace_are_ignored() {
+        let uri = session(93).to_uri();
+        assert_eq!(Session::parse(&format!("{}/", uri)), Ok(session(93)));
+        assert_eq!(Session::parse(&format!("  {}\n", uri)), Ok(session(93)));
+    }
+
+    #[test]
+    fn other_links_are_not_sessions() {
+        let encoded = session(93).to_uri().replace(PREFIX, "");
+        for uri in [
+            format!("ytune://other/{}", encoded),
+            format!("https://session/{}", encoded),
+            encoded,
+        ] {
+            let error = Session::parse(&uri).unwrap_err();
+            assert!(error.starts_with("Not a ytune session link"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn damaged_links_are_refused() {
+        let damaged = Err("The session link is damaged".to_string());
+        assert_eq!(Session::parse("ytune://session/not*base64!"), damaged);
+        assert_eq!(Session::parse(&format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode([0xff, 0xfe]))), damaged);
+        assert_eq!(Session::parse(&link("dQw4w9WgXcQ")), damaged);
+    }
+
+    #[test]
+    fn links_need_a_video_id_and_a_position() {
+        assert_eq!(
+            Session::parse(&link("not a video:93")),
+            Err("The session link names no video".to_string())
+        );
+        assert_eq!(Session::parse(&link(":93")), Err("The session link names no video".to_string()));
+        let no_position = Err("The session link has no valid position".to_string());
+        assert_eq!(Session::parse(&link("dQw4w9WgXcQ:1m33s")), no_position);
+        assert_eq!(Session::parse(&link("dQw4w9WgXcQ:-5")), no_position);
+        assert_eq!(Session::parse(&link("dQw4w9WgXcQ:")), no_position);
+    }
+
+    #[test]
+    fn watch_url_starts_at_the_position() {
+        assert_eq!(
+            session(93).watch_url("https://music.youtube.com").as_str(),
+            "https://music.youtube.com/watch?v=dQw4w9WgXcQ&t=93"
+        );
+        assert_eq!(
+            session(0).watch_url("https://www.youtube.com").as_str(),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+#![cfg(unix)]
+
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use wry::application::event_loop::EventLoopProxy;
+
+use crate::{api::types::ControlEvent, events::TrackEvent, ipc_protocol, LastTrackInfo, UserEvent};
+
+const SOCKET_NAME: &str = "ytune.sock";
+/// A client that stops reading is dropped rather than stalling the others.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Connected clients; each is written to by its own thread and the broadcaster.
+type Clients = Arc<Mutex<Vec<Arc<Mutex<UnixStream>>>>>;
+
+/// `$XDG_RUNTIME_DIR/ytune.sock`, or the temp dir where there is no runtime
+/// dir (macOS).
+pub fn default_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join(SOCKET_NAME)
+}
+
+/// Serves the control socket on a background thread. Clients speak the same
+/// line-based JSON protocol as the Windows pipe and also get a
+/// `trackUpdate` line for every playback event. Returns the broadcaster,
+/// which ends and removes the socket when the event bus is closed.
+pub fn start(
+    path: PathBuf,
+    last_track: Arc<Mutex<LastTrackInfo>>,
+    proxy: EventLoopProxy<UserEvent>,
+    events: Receiver<TrackEvent>,
+) -> io::Result<thread::JoinHandle<()>> {
+    let listener = bind(&path)?;
+    log::info!("Control socket listening on {}", path.display());
+    let clients = Clients::default();
+
+    let accepting = Arc::clone(&clients);
+    let send_event = move |event| proxy.send_event(event).is_ok();
+    thread::spawn(move || accept_clients(listener, &accepting, &last_track, send_event));
+
+    Ok(thread::spawn(move || {
+        broadcast(&clients, events);
+        let _ = fs::remove_file(&path);
+    }))
+}
+
+/// Replaces a socket left behind by a crashed instance, but not one that
+/// another running instance still answers on.
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another ytune is already listening",
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Answers each client that connects on a thread of its own.
+fn accept_clients(
+    listener: UnixListener,
+    clients: &Clients,
+    last_track: &Arc<Mutex<LastTrackInfo>>,
+    send_event: impl Fn(UserEvent) -> bool + Clone + Send + 'static,
+) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::debug!("Failed to accept control socket client: {}", e);
+                continue;
+            }
+        };
+        let last_track = Arc::clone(last_track);
+        let send_event = send_event.clone();
+        let clients = Arc::clone(clients);
+        thread::spawn(move || {
+            if let Err(e) = serve_client(stream, &clients, &last_track, send_event) {
+                log::debug!("Control socket client disconnected: {}", e);
+            }
+        });
+    }
+}
+
+fn serve_client(
+    stream: UnixStream,
+    clients: &Clients,
+    last_track: &Mutex<LastTrackInfo>,
+    send_event: impl Fn(UserEvent) -> bool,
+) -> io::Result<()> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    clients.lock().unwrap().push(Arc::clone(&writer));
+
+    let result = answer_requests(stream, &writer, last_track, send_event);
+    clients
+        .lock()
+        .unwrap()
+        .retain(|client| !Arc::ptr_eq(client, &writer));
+    result
+}
+
+fn answer_requests(
+    stream: UnixStream,
+    writer: &Mutex<UnixStream>,
+    last_track: &Mutex<LastTrackInfo>,
+    send_event: impl Fn(UserEvent) -> bool,
+) -> io::Result<()> {
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = ipc_protocol::handle_request(&line, last_track, &send_event);
+        write_line(writer, &response.to_string())?;
+    }
+    Ok(())
+}
+
+fn broadcast(clients: &Clients, events: Receiver<TrackEvent>) {
+    for event in events {
+        let Ok(line) = serde_json::to_string(&ControlEvent::track_update(event.kind, &event.track)) else {
+            continue;
+        };
+        // Clients that can't be written to have gone away.
+        clients
+            .lock()
+            .unwrap()
+            .retain(|client| write_line(client, &line).is_ok());
+    }
+}
+
+fn write_line(stream: &Mutex<UnixStream>, line: &str) -> io::Result<()> {
+    let mut stream = stream.lock().unwrap();
+    writeln!(stream, "{}", line)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{config::Config, control::PlayerCommand};
+
+    /// A control socket served in-process, whose commands land in the
+    /// returned receiver.
+    fn serve(name: &str) -> (PathBuf, mpsc::Receiver<UserEvent>) {
+        let path = env::temp_dir().join(format!("ytune-test-{}-{}.sock", name, std::process::id()));
+        let listener = bind(&path).unwrap();
+        let last_track = Arc::new(Mutex::new(LastTrackInfo {
+            title: Some("Song".to_string()),
+            ..LastTrackInfo::default()
+        }));
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            accept_clients(listener, &Clients::default(), &last_track, move |event| sender.send(event).is_ok())
+        });
+        (path, receiver)
+    }
+
+    #[test]
+    fn client_talks_to_the_server() {
+        let (path, events) = serve("client");
+        let config = Config {
+            socket_path: Some(path.clone()),
+            ..Config::default()
+        };
+
+        let response = ipc_protocol::request(&config, &json!({ "v": 1, "cmd": "now_playing" })).unwrap();
+        assert_eq!(response["ok"], true);
+        assert_eq!(response["track"]["title"], "Song");
+
+        let response = ipc_protocol::request(&config, &json!({ "v": 1, "cmd": "next" })).unwrap();
+        assert_eq!(response, json!({ "v": 1, "ok": true }));
+        assert!(matches!(
+            events.recv_timeout(Duration::from_secs(5)),
+            Ok(UserEvent::PlayerCommand(PlayerCommand::Next))
+        ));
+
+        let response = ipc_protocol::request(&config, &json!({ "v": 9, "cmd": "next" })).unwrap();
+        assert_eq!(response["ok"], false);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn each_line_gets_one_response() {
+        let (path, _events) = serve("lines");
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        // Blank lines are skipped; two requests in one write are two requests.
+        stream
+            .write_all(b"\n{\"cmd\":\"show\"}\n{\"cmd\":\"bogus\"}\n")
+            .unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let first: serde_json::Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        let second: serde_json::Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(first["ok"], true);
+        assert_eq!(second["error"], "unknown command 'bogus'");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_live_socket_is_not_taken_over() {
+        let (path, _events) = serve("live");
+        assert_eq!(bind(&path).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        fs::remove_file(path).unwrap();
+    }
+}
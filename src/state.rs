@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::PathBuf};
+
+use crate::config;
+
+const STATE_FILE_NAME: &str = "state.toml";
+
+/// Settings toggled from within the app, as opposed to the user-edited
+/// `config.toml`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub focus_mode: bool,
+}
+
+fn state_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join(STATE_FILE_NAME))
+}
+
+pub fn load() -> State {
+    state_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| toml::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(state: &State) -> io::Result<()> {
+    let path = state_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory available"))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let contents = toml::to_string(state).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(path, contents)
+}
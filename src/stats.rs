@@ -0,0 +1,267 @@
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use crate::history::History;
+
+const TOP_LIMIT: i64 = 10;
+const RECENT_DAYS: u64 = 30;
+/// Plays separated by a longer pause than this start a new session.
+const SESSION_GAP_SECONDS: i64 = 5 * 60;
+
+/// Seconds actually listened to in a play: the played share of the
+/// duration, or the whole duration when the completion wasn't recorded.
+const LISTENED_SQL: &str = "CAST(COALESCE(duration_sec * completion, duration_sec, 0) AS INTEGER)";
+
+#[derive(Debug, Serialize)]
+pub struct TrackCount {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub plays: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArtistCount {
+    pub artist: String,
+    pub plays: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Session {
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub duration_sec: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub total_plays: u64,
+    pub total_listening_sec: u64,
+    pub top_tracks: Vec<TrackCount>,
+    pub top_artists: Vec<ArtistCount>,
+    /// Local hour of day (0–23) with the most plays.
+    pub busiest_hour: Option<u32>,
+    pub longest_session: Option<Session>,
+    /// Listening time per day averaged over the last 30 days.
+    pub daily_average_sec_last_30_days: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyListening {
+    /// Local date, `YYYY-MM-DD`.
+    pub date: String,
+    pub listening_sec: u64,
+}
+
+/// Stats for a recent range of days. Days without plays are left out of `daily`.
+#[derive(Debug, Serialize)]
+pub struct RangeStats {
+    pub days: u64,
+    pub total_plays: u64,
+    pub total_listening_sec: u64,
+    pub top_tracks: Vec<TrackCount>,
+    pub top_artists: Vec<ArtistCount>,
+    pub daily: Vec<DailyListening>,
+}
+
+pub fn compute(history: &History) -> rusqlite::Result<Stats> {
+    let conn = history.connection();
+
+    let (total_plays, total_listening_sec): (u64, u64) = conn.query_row(
+        &format!("SELECT COUNT(*), COALESCE(SUM({}), 0) FROM plays", LISTENED_SQL),
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let top_tracks = top_tracks(conn, 0)?;
+    let top_artists = top_artists(conn, 0)?;
+
+    let busiest_hour = conn
+        .prepare(
+            "SELECT CAST(strftime('%H', played_at, 'unixepoch', 'localtime') AS INTEGER) AS hour
+             FROM plays GROUP BY hour ORDER BY COUNT(*) DESC LIMIT 1",
+        )?
+        .query_map([], |row| row.get(0))?
+        .next()
+        .transpose()?;
+
+    // A play continues the session when it starts within the gap of the
+    // previous one ending; the running sum of session starts numbers them.
+    let longest_session = conn
+        .prepare(&format!(
+            "WITH spans AS (
+                SELECT played_at AS started_at, played_at + {} AS ended_at FROM plays
+            ), marked AS (
+                SELECT started_at, ended_at,
+                    CASE WHEN started_at - LAG(ended_at) OVER (ORDER BY started_at) <= ?1
+                        THEN 0 ELSE 1 END AS new_session
+                FROM spans
+            ), numbered AS (
+                SELECT started_at, ended_at,
+                    SUM(new_session) OVER (ORDER BY started_at ROWS UNBOUNDED PRECEDING) AS session
+                FROM marked
+            )
+            SELECT MIN(started_at), MAX(ended_at) FROM numbered
+            GROUP BY session ORDER BY MAX(ended_at) - MIN(started_at) DESC LIMIT 1",
+            LISTENED_SQL
+        ))?
+        .query_map(params![SESSION_GAP_SECONDS], |row| {
+            let started_at: u64 = row.get(0)?;
+            let ended_at: u64 = row.get(1)?;
+            Ok(Session {
+                started_at,
+                ended_at,
+                duration_sec: ended_at.saturating_sub(started_at),
+            })
+        })?
+        .next()
+        .transpose()?;
+
+    let since = crate::events::unix_now().saturating_sub(RECENT_DAYS * 86_400);
+    let recent_listening_sec: u64 = conn.query_row(
+        &format!("SELECT COALESCE(SUM({}), 0) FROM plays WHERE played_at >= ?1", LISTENED_SQL),
+        params![since],
+        |row| row.get(0),
+    )?;
+
+    Ok(Stats {
+        total_plays,
+        total_listening_sec,
+        top_tracks,
+        top_artists,
+        busiest_hour,
+        longest_session,
+        daily_average_sec_last_30_days: recent_listening_sec / RECENT_DAYS,
+    })
+}
+
+/// Listening over the last `days` days, for the stats window.
+pub fn compute_range(history: &History, days: u64) -> rusqlite::Result<RangeStats> {
+    let conn = history.connection();
+    let since = crate::events::unix_now().saturating_sub(days * 86_400);
+
+    let (total_plays, total_listening_sec): (u64, u64) = conn.query_row(
+        &format!(
+            "SELECT COUNT(*), COALESCE(SUM({}), 0) FROM plays WHERE played_at >= ?1",
+            LISTENED_SQL
+        ),
+        params![since],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let daily = conn
+        .prepare(&format!(
+            "SELECT date(played_at, 'unixepoch', 'localtime') AS day, SUM({}) FROM plays
+             WHERE played_at >= ?1 GROUP BY day ORDER BY day",
+            LISTENED_SQL
+        ))?
+        .query_map(params![since], |row| {
+            Ok(DailyListening {
+                date: row.get(0)?,
+                listening_sec: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(RangeStats {
+        days,
+        total_plays,
+        total_listening_sec,
+        top_tracks: top_tracks(conn, since)?,
+        top_artists: top_artists(conn, since)?,
+        daily,
+    })
+}
+
+fn top_tracks(conn: &Connection, since: u64) -> rusqlite::Result<Vec<TrackCount>> {
+    conn.prepare(
+        "SELECT title, artist, COUNT(*) AS plays FROM plays
+         WHERE title IS NOT NULL AND played_at >= ?2
+         GROUP BY COALESCE(video_id, title || char(31) || COALESCE(artist, ''))
+         ORDER BY plays DESC, MAX(played_at) DESC LIMIT ?1",
+    )?
+    .query_map(params![TOP_LIMIT, since], |row| {
+        Ok(TrackCount {
+            title: row.get(0)?,
+            artist: row.get(1)?,
+            plays: row.get(2)?,
+        })
+    })?
+    .collect()
+}
+
+fn top_artists(conn: &Connection, since: u64) -> rusqlite::Result<Vec<ArtistCount>> {
+    conn.prepare(
+        "SELECT artist, COUNT(*) AS plays FROM plays
+         WHERE artist IS NOT NULL AND played_at >= ?2
+         GROUP BY artist ORDER BY plays DESC, MAX(played_at) DESC LIMIT ?1",
+    )?
+    .query_map(params![TOP_LIMIT, since], |row| {
+        Ok(ArtistCount {
+            artist: row.get(0)?,
+            plays: row.get(1)?,
+        })
+    })?
+    .collect()
+}
+
+/// Prints the summary for the `--stats` command.
+pub fn print(stats: &Stats) {
+    if stats.total_plays == 0 {
+        println!("No plays recorded yet.");
+        return;
+    }
+
+    println!(
+        "Total listening time: {} over {} plays",
+        format_duration(stats.total_listening_sec),
+        stats.total_plays
+    );
+    println!(
+        "Daily average (last {} days): {}",
+        RECENT_DAYS,
+        format_duration(stats.daily_average_sec_last_30_days)
+    );
+    if let Some(hour) = stats.busiest_hour {
+        println!("Busiest hour: {:02}:00–{:02}:00", hour, (hour + 1) % 24);
+    }
+    if let Some(session) = &stats.longest_session {
+        let started_at = chrono::DateTime::from_timestamp(session.started_at as i64, 0)
+            .map(|time| {
+                time.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        println!(
+            "Longest session: {} (started {})",
+            format_duration(session.duration_sec),
+            started_at
+        );
+    }
+
+    println!("\nTop tracks:");
+    for (rank, track) in stats.top_tracks.iter().enumerate() {
+        println!(
+            "{:>3}. {} — {} ({} plays)",
+            rank + 1,
+            track.title.as_deref().unwrap_or("Unknown title"),
+            track.artist.as_deref().unwrap_or("Unknown artist"),
+            track.plays
+        );
+    }
+
+    println!("\nTop artists:");
+    for (rank, artist) in stats.top_artists.iter().enumerate() {
+        println!("{:>3}. {} ({} plays)", rank + 1, artist.artist, artist.plays);
+    }
+}
+
+pub fn format_duration(seconds: u64) -> String {
+    let hours = seconds / 3600;
+    let minutes = seconds % 3600 / 60;
+    if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use wry::{
+    application::{
+        dpi::LogicalSize,
+        event_loop::{EventLoopProxy, EventLoopWindowTarget},
+        window::{Icon, Window, WindowBuilder, WindowId},
+    },
+    webview::{WebView, WebViewBuilder},
+};
+
+use crate::{
+    config,
+    export::{self, ExportFormat},
+    history::{History, SEARCH_LIMIT},
+    stats,
+    youtube_url::is_video_id_char,
+    UserEvent,
+};
+
+const STATS_HTML: &str = include_str!("assets/stats.html");
+/// Longest range the page may ask for.
+const MAX_DAYS: u64 = 3650;
+
+/// What the stats page asks for over IPC.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StatsRequest {
+    Show { days: u64 },
+    ExportCsv { days: u64 },
+    Search { query: String },
+    /// Play a search result in the main window.
+    Play { video_id: String },
+}
+
+impl StatsRequest {
+    fn parse(request: &str) -> Option<Self> {
+        let request: serde_json::Value = serde_json::from_str(request).ok()?;
+        let days = || request.get("days")?.as_u64().map(|days| days.clamp(1, MAX_DAYS));
+        let text = |key: &str| request.get(key)?.as_str().map(str::to_string);
+        match request.get("cmd")?.as_str()? {
+            "stats" => Some(StatsRequest::Show { days: days()? }),
+            "exportCsv" => Some(StatsRequest::ExportCsv { days: days()? }),
+            "search" => Some(StatsRequest::Search { query: text("query")? }),
+            // Goes into a URL, so only the characters video IDs are made of.
+            "play" => text("videoId")
+                .filter(|id| !id.is_empty() && id.chars().all(is_video_id_char))
+                .map(|video_id| StatsRequest::Play { video_id }),
+            _ => None,
+        }
+    }
+}
+
+/// Secondary window showing listening stats from the local history. All the
+/// data comes from the history database; the page loads nothing remote.
+/// Queries run on a thread of their own holding the database open while the
+/// window is.
+pub struct StatsWindow {
+    webview: WebView,
+}
+
+impl StatsWindow {
+    pub fn open(
+        target: &EventLoopWindowTarget<UserEvent>,
+        icon: Option<Icon>,
+        history: History,
+        proxy: EventLoopProxy<UserEvent>,
+    ) -> wry::Result<Self> {
+        let window = WindowBuilder::new()
+            .with_title("ytune — Listening stats")
+            .with_window_icon(icon)
+            .with_inner_size(LogicalSize::new(760.0, 680.0))
+            .build(target)?;
+        let (queries, query_receiver) = mpsc::channel();
+        let reply_proxy = proxy.clone();
+        let webview = WebViewBuilder::new(window)?
+            .with_html(STATS_HTML)?
+            .with_ipc_handler(move |_window: &Window, request: String| {
+                match StatsRequest::parse(&request) {
+                    Some(request @ StatsRequest::Play { .. }) => {
+                        let _ = proxy.send_event(UserEvent::StatsRequest(request));
+                    }
+                    Some(request) => {
+                        let _ = queries.send(request);
+                    }
+                    None => log::debug!("Ignoring stats page message: {}", request),
+                }
+            })
+            .build()?;
+        // Ends when the webview, holding the sender, is dropped.
+        thread::spawn(move || run_queries(history, query_receiver, reply_proxy));
+        Ok(StatsWindow { webview })
+    }
+
+    pub fn window_id(&self) -> WindowId {
+        self.webview.window().id()
+    }
+
+    pub fn focus(&self) {
+        crate::show_window(self.webview.window());
+    }
+
+    /// Runs a script from `run_queries` in the page.
+    pub fn show_reply(&self, script: &str) {
+        if let Err(e) = self.webview.evaluate_script(script) {
+            log::warn!("Failed to update the stats window: {}", e);
+        }
+    }
+}
+
+/// Answers the page's queries until the window closes, sending each answer
+/// to the event loop as a script for `StatsWindow::show_reply`.
+fn run_queries(history: History, requests: Receiver<StatsRequest>, proxy: EventLoopProxy<UserEvent>) {
+    for request in requests {
+        if let Some(script) = reply(&history, request) {
+            if proxy.send_event(UserEvent::StatsReply(script)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The script that shows the answer to `request` in the page.
+fn reply(history: &History, request: StatsRequest) -> Option<String> {
+    let script = match request {
+        StatsRequest::Show { days } => match stats::compute_range(history, days) {
+            Ok(stats) => format!(
+                "window.__ytuneShowStats({});",
+                serde_json::to_string(&stats).unwrap_or_default()
+            ),
+            Err(e) => {
+                log::warn!("Failed to compute listening stats: {}", e);
+                return None;
+            }
+        },
+        StatsRequest::ExportCsv { days } => {
+            let message = match export_csv(history, days) {
+                Ok(path) => format!("Exported to {}", path.display()),
+                Err(e) => format!("Export failed: {}", e),
+            };
+            format!(
+                "window.__ytuneExportDone({});",
+                serde_json::Value::String(message)
+            )
+        }
+        StatsRequest::Search { query } => match history.search(&query, SEARCH_LIMIT) {
+            Ok(records) => format!(
+                "window.__ytuneShowSearch({}, {});",
+                serde_json::Value::String(query),
+                serde_json::to_string(&records).unwrap_or_default()
+            ),
+            Err(e) => {
+                log::warn!("Failed to search play history: {}", e);
+                return None;
+            }
+        },
+        // Handled by the main window.
+        StatsRequest::Play { .. } => return None,
+    };
+    Some(script)
+}
+
+/// Writes the plays of the last `days` days to the downloads folder, or the
+/// data directory when there is none.
+fn export_csv(history: &History, days: u64) -> Result<PathBuf, String> {
+    let since = crate::events::unix_now().saturating_sub(days * 86_400);
+    let records = history.range(Some(since), None).map_err(|e| e.to_string())?;
+    let dir = dirs::download_dir()
+        .or_else(config::data_dir)
+        .ok_or("No folder to export to")?;
+    let file_name = format!(
+        "ytune-history-{}-days-{}.csv",
+        days,
+        chrono::Local::now().format("%Y-%m-%d")
+    );
+    let path = dir.join(file_name);
+    export::export_history(&records, &path, ExportFormat::Csv).map_err(|e| e.to_string())?;
+    Ok(path)
+}
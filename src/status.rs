@@ -0,0 +1,85 @@
+use std::fmt;
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountTier {
+    Free,
+    Premium,
+}
+
+impl AccountTier {
+    /// Reads the tier the page reports, e.g. "premium".
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "free" => Some(AccountTier::Free),
+            "premium" => Some(AccountTier::Premium),
+            _ => None,
+        }
+    }
+}
+
+/// Live state of the Discord presence connection.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", content = "error", rename_all = "lowercase")]
+// Only the Windows presence code connects.
+#[cfg_attr(not(all(target_os = "windows", feature = "discord")), allow(dead_code))]
+pub enum DiscordStatus {
+    /// Not in this build or not on this platform.
+    #[default]
+    Disabled,
+    Connecting,
+    Connected,
+    /// The pipe broke and a new connection is being made.
+    Reconnecting,
+    /// The last attempt failed; the next presence update tries again.
+    Error(String),
+}
+
+impl fmt::Display for DiscordStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiscordStatus::Disabled => f.write_str("disabled"),
+            DiscordStatus::Connecting => f.write_str("connecting"),
+            DiscordStatus::Connected => f.write_str("connected"),
+            DiscordStatus::Reconnecting => f.write_str("reconnecting"),
+            DiscordStatus::Error(error) => write!(f, "error: {}", error),
+        }
+    }
+}
+
+/// Runtime state shared across threads for diagnostics.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AppStatus {
+    pub account_tier: Option<AccountTier>,
+    /// Unix time in seconds of the last track update from the page.
+    pub last_scrape_at: Option<u64>,
+    /// Scrobbles waiting to be submitted; `None` while Last.fm isn't connected.
+    pub scrobbles_pending: Option<usize>,
+    /// Outcome of the last Last.fm love or unlove, e.g. "Loved Song — Artist".
+    pub last_love_sync: Option<String>,
+    /// Local API state, e.g. "listening on 127.0.0.1:9863"; `None` while
+    /// it's disabled.
+    pub api: Option<String>,
+    /// What YouTube Music's error screen is about, `offline` or `playback`,
+    /// while the page shows one.
+    pub player_error: Option<String>,
+    pub scrape_metrics: ScrapeMetrics,
+}
+
+/// How often the page scrapes and what comes of it, since ytune started.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ScrapeMetrics {
+    /// `trackUpdate` messages from the page.
+    pub track_updates: u64,
+    /// Updates that changed the Discord presence and were sent to it.
+    pub activities_sent: u64,
+    /// Updates that left the presence as it was.
+    pub activities_skipped: u64,
+    /// Scrapes the page has timed, posted or not.
+    pub scrapes: u64,
+    /// Mean and longest scrape over the page's last reporting minute.
+    pub scrape_ms: Option<f64>,
+    pub max_scrape_ms: Option<f64>,
+}
@@ -0,0 +1,102 @@
+use std::{thread, time::Duration};
+
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, Weekday};
+
+use crate::{
+    config::{SummaryConfig, SummaryPeriod},
+    history::History,
+    notification, stats,
+};
+
+/// Checked this often rather than sleeping until the configured time, so a
+/// summary that fell due while the machine slept is shown after it wakes.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Weekly summaries go out at the end of the week.
+const WEEKLY_SUMMARY_DAY: Weekday = Weekday::Sun;
+
+impl SummaryPeriod {
+    fn days(self) -> u64 {
+        match self {
+            SummaryPeriod::Day => 1,
+            SummaryPeriod::Week => 7,
+        }
+    }
+
+    fn is_due_on(self, date: NaiveDate) -> bool {
+        match self {
+            SummaryPeriod::Day => true,
+            SummaryPeriod::Week => date.weekday() == WEEKLY_SUMMARY_DAY,
+        }
+    }
+}
+
+/// Shows the listening summary on start and at the configured time, as the
+/// config asks. Never returns.
+pub fn run(config: SummaryConfig, history: History) {
+    if config.on_start {
+        show(&history, config.period);
+    }
+
+    let Some(at) = config.at.as_deref() else {
+        return;
+    };
+    let at = match NaiveTime::parse_from_str(at, "%H:%M") {
+        Ok(at) => at,
+        Err(e) => {
+            log::warn!("Ignoring summary.at = {:?}, expected HH:MM: {}", at, e);
+            return;
+        }
+    };
+
+    // A time that already passed today counts as handled; starting up late
+    // shouldn't show a summary nobody asked for.
+    let now = Local::now();
+    let mut last_shown = (now.time() >= at).then(|| now.date_naive());
+
+    loop {
+        thread::sleep(CHECK_INTERVAL);
+        let now = Local::now();
+        let today = now.date_naive();
+        if now.time() >= at && last_shown != Some(today) && config.period.is_due_on(today) {
+            last_shown = Some(today);
+            show(&history, config.period);
+        }
+    }
+}
+
+fn show(history: &History, period: SummaryPeriod) {
+    let stats = match stats::compute_range(history, period.days()) {
+        Ok(stats) => stats,
+        Err(e) => {
+            log::warn!("Failed to compute the listening summary: {}", e);
+            return;
+        }
+    };
+
+    let title = match period {
+        SummaryPeriod::Day => "Today on ytune",
+        SummaryPeriod::Week => "This week on ytune",
+    };
+    let body = if stats.total_plays == 0 {
+        "No listening recorded yet.".to_string()
+    } else {
+        let mut lines = vec![format!(
+            "{} over {} plays",
+            stats::format_duration(stats.total_listening_sec),
+            stats.total_plays
+        )];
+        if let Some(artist) = stats.top_artists.first() {
+            lines.push(format!("Top artist: {}", artist.artist));
+        }
+        if let Some(track) = stats.top_tracks.first() {
+            lines.push(format!(
+                "Top track: {} — {}",
+                track.title.as_deref().unwrap_or("Unknown title"),
+                track.artist.as_deref().unwrap_or("Unknown artist")
+            ));
+        }
+        lines.join("\n")
+    };
+
+    notification::show(title, &body);
+}
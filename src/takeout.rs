@@ -0,0 +1,155 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::history::{History, PlayRecord, RECORD_VERSION};
+
+/// Takeout and ytune record the same play a few seconds apart, so a play of
+/// the same video this close to an existing one is taken as a duplicate.
+const DUPLICATE_WINDOW_SEC: u64 = 5 * 60;
+/// Takeout prefixes every title with the action, in English exports at least.
+const TITLE_PREFIXES: [&str; 2] = ["Watched ", "Listened to "];
+/// Auto-generated artist channels are named "Artist - Topic".
+const TOPIC_SUFFIX: &str = " - Topic";
+
+/// One entry of `watch-history.json`. Music plays come with the header
+/// "YouTube Music"; the generic YouTube export mixes them with videos, which
+/// are told apart by their `music.youtube.com` link.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TakeoutEntry {
+    header: Option<String>,
+    title: Option<String>,
+    title_url: Option<String>,
+    #[serde(default)]
+    subtitles: Vec<Subtitle>,
+    time: String,
+}
+
+#[derive(Deserialize)]
+struct Subtitle {
+    name: Option<String>,
+}
+
+/// Outcome of importing one file.
+#[derive(Debug, Default)]
+pub struct FileSummary {
+    pub imported: usize,
+    pub duplicates: usize,
+    /// Malformed entries, ones that are not music or can't be replayed, and
+    /// ones older than the history's retention limits keep.
+    pub skipped: usize,
+}
+
+/// Imports `path`, or every `.json` file below it when it is a directory. A
+/// file that can't be read is reported and the others still go in.
+pub fn import(history: &History, path: &Path) -> Vec<(PathBuf, Result<FileSummary, String>)> {
+    let mut files = Vec::new();
+    collect_json_files(path, &mut files);
+    files
+        .into_iter()
+        .map(|file| {
+            let result = import_file(history, &file);
+            (file, result)
+        })
+        .collect()
+}
+
+fn collect_json_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return;
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        files.push(path.to_path_buf());
+        return;
+    };
+    let mut children: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    children.sort();
+    for child in children {
+        if child.is_dir() {
+            collect_json_files(&child, files);
+        } else if child
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+        {
+            files.push(child);
+        }
+    }
+}
+
+fn import_file(history: &History, path: &Path) -> Result<FileSummary, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let entries: Vec<Value> = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+
+    let mut summary = FileSummary::default();
+    let mut records = Vec::new();
+    for entry in entries {
+        match serde_json::from_value::<TakeoutEntry>(entry).ok().and_then(to_record) {
+            Some(record) => records.push(record),
+            None => summary.skipped += 1,
+        }
+    }
+
+    let (imported, duplicates, pruned) = history
+        .import(&records, DUPLICATE_WINDOW_SEC)
+        .map_err(|e| e.to_string())?;
+    summary.imported = imported;
+    summary.duplicates = duplicates;
+    summary.skipped += pruned;
+    Ok(summary)
+}
+
+fn to_record(entry: TakeoutEntry) -> Option<PlayRecord> {
+    let url = entry.title_url.as_deref()?;
+    let is_music = entry.header.as_deref() == Some("YouTube Music") || url.contains("music.youtube.com/");
+    if !is_music {
+        return None;
+    }
+
+    let played_at = DateTime::parse_from_rfc3339(&entry.time).ok()?.timestamp();
+    let video_id = video_id_from_url(url)?;
+    let title = entry.title.as_deref().map(|title| {
+        TITLE_PREFIXES
+            .iter()
+            .find_map(|prefix| title.strip_prefix(prefix))
+            .unwrap_or(title)
+            .trim()
+            .to_string()
+    });
+    // Removed videos only have their link as the title.
+    let title = title.filter(|title| !title.is_empty() && !title.starts_with("https://"));
+    let artist = entry
+        .subtitles
+        .first()
+        .and_then(|subtitle| subtitle.name.as_deref())
+        .map(|name| name.strip_suffix(TOPIC_SUFFIX).unwrap_or(name).trim().to_string())
+        .filter(|name| !name.is_empty());
+
+    Some(PlayRecord {
+        version: RECORD_VERSION,
+        video_id: Some(video_id),
+        title,
+        artist,
+        album: None,
+        duration_sec: None,
+        played_at: u64::try_from(played_at).ok()?,
+        completion: None,
+    })
+}
+
+fn video_id_from_url(url: &str) -> Option<String> {
+    let query = url.split_once('?')?.1;
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("v="))
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+}
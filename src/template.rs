@@ -0,0 +1,107 @@
+use crate::LastTrackInfo;
+
+/// Fills in `{title}`, `{artist}`, `{album}`, `{position}`, `{duration}` and
+/// `{remaining}`; unknown values become empty. The template is read once,
+/// left to right, so braces in a value are never taken as placeholders.
+pub fn render(template: &str, track: &LastTrackInfo) -> String {
+    let remaining = track
+        .duration_sec
+        .zip(track.position_sec)
+        .map(|(duration, position)| duration.saturating_sub(position));
+
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let value = match &rest[1..end] {
+                "title" => track.title.clone().unwrap_or_default(),
+                "artist" => track.artist.clone().unwrap_or_default(),
+                "album" => track.album.clone().unwrap_or_default(),
+                "position" => format_time(track.position_sec),
+                "duration" => format_time(track.duration_sec),
+                "remaining" => format_time(remaining),
+                _ => return None,
+            };
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                text.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            // Not a placeholder; the brace is kept as it is.
+            None => {
+                text.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Like `render`, but `None` when none of the placeholders had a value, so
+/// "by {artist}" doesn't come out as a bare "by".
+#[cfg(any(test, all(target_os = "windows", feature = "discord")))]
+pub fn render_if_filled(template: &str, track: &LastTrackInfo) -> Option<String> {
+    let text = render(template, track);
+    let unfilled = render(template, &LastTrackInfo::default());
+    (text != unfilled || unfilled == template).then_some(text)
+}
+
+fn format_time(seconds: Option<u64>) -> String {
+    match seconds {
+        Some(seconds) => format!("{}:{:02}", seconds / 60, seconds % 60),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track() -> LastTrackInfo {
+        LastTrackInfo {
+            title: Some("Song".to_string()),
+            artist: Some("Artist".to_string()),
+            album: Some("Album".to_string()),
+            duration_sec: Some(200),
+            position_sec: Some(65),
+            ..LastTrackInfo::default()
+        }
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        assert_eq!(
+            render("{title} by {artist} on {album} {position}/{duration} (-{remaining})", &track()),
+            "Song by Artist on Album 1:05/3:20 (-2:15)"
+        );
+        assert_eq!(render("{title} — {artist}", &LastTrackInfo::default()), " — ");
+    }
+
+    #[test]
+    fn values_are_inserted_once_and_never_rendered_again() {
+        let track = LastTrackInfo {
+            title: Some("{artist} {remaining}".to_string()),
+            artist: Some("{title}".to_string()),
+            ..track()
+        };
+        assert_eq!(render("{title} - {artist}", &track), "{artist} {remaining} - {title}");
+    }
+
+    #[test]
+    fn other_braces_are_left_alone() {
+        assert_eq!(render("{{title}} {unknown} {title", &track()), "{Song} {unknown} {title");
+        assert_eq!(render("}{title}{", &track()), "}Song{");
+    }
+
+    #[test]
+    fn unfilled_templates_render_to_nothing() {
+        assert_eq!(render_if_filled("by {artist}", &track()).as_deref(), Some("by Artist"));
+        assert_eq!(render_if_filled("by {artist}", &LastTrackInfo::default()), None);
+        assert_eq!(render_if_filled("ytune", &LastTrackInfo::default()).as_deref(), Some("ytune"));
+    }
+}
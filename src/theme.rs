@@ -0,0 +1,111 @@
+use std::{thread, time::Duration};
+
+use wry::webview::Url;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    fn as_query_value(self) -> &'static str {
+        match self {
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+}
+
+/// Returns `url` with its `theme` query parameter set to `theme`, keeping
+/// every other parameter as is.
+pub fn with_theme(url: &Url, theme: Theme) -> Url {
+    let mut themed = url.clone();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "theme")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    themed
+        .query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair("theme", theme.as_query_value());
+    themed
+}
+
+/// Polls the OS color scheme and calls `on_change` whenever it differs from
+/// the last one seen. Polling keeps this independent of platform event APIs.
+pub fn watch<F>(initial: Option<Theme>, on_change: F)
+where
+    F: Fn(Theme) + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut current = initial;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let detected = system_theme();
+            if detected.is_some() && detected != current {
+                current = detected;
+                if let Some(theme) = detected {
+                    log::info!("System theme changed to {:?}", theme);
+                    on_change(theme);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(target_os = "windows")]
+pub fn system_theme() -> Option<Theme> {
+    use windows::{
+        core::w,
+        Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD},
+    };
+
+    let mut value: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+            w!("AppsUseLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut value as *mut u32 as *mut std::ffi::c_void),
+            Some(&mut size),
+        )
+    };
+
+    result
+        .ok()
+        .map(|_| if value == 0 { Theme::Dark } else { Theme::Light })
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn system_theme() -> Option<Theme> {
+    // GNOME 42+ exposes the preference as color-scheme; older desktops only
+    // hint at it through the GTK theme name.
+    let color_scheme = std::process::Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "color-scheme"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
+    match color_scheme.as_deref() {
+        Some("'prefer-dark'") => return Some(Theme::Dark),
+        Some("'prefer-light'") => return Some(Theme::Light),
+        _ => {}
+    }
+
+    std::env::var("GTK_THEME").ok().map(|gtk_theme| {
+        if gtk_theme.to_ascii_lowercase().contains("dark") {
+            Theme::Dark
+        } else {
+            Theme::Light
+        }
+    })
+}
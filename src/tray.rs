@@ -0,0 +1,340 @@
+use std::sync::mpsc::Receiver;
+
+use wry::application::{
+    error::OsError,
+    event_loop::EventLoopWindowTarget,
+    menu::{ContextMenu, CustomMenuItem, MenuId, MenuItem, MenuItemAttributes},
+    system_tray::{SystemTray, SystemTrayBuilder},
+    window::Icon,
+};
+
+use crate::{
+    events::{TrackEvent, TrackEventKind},
+    history::PlayRecord,
+    LastTrackInfo,
+};
+
+/// How many plays the "Recently played" submenu lists.
+pub const RECENT_PLAYS_LIMIT: usize = 10;
+const RECENT_LABEL_MAX_CHARS: usize = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrayAction {
+    ShowWindow,
+    /// Index into the recent plays passed to `set_recent_plays`.
+    PlayRecent(usize),
+    ToggleFocusMode,
+    ToggleAutostart,
+    CopyTrack,
+    ShareCard,
+    CopyMusicLink,
+    CopyYouTubeLink,
+    RefreshNowPlaying,
+    ShowStats,
+    ExportHistory,
+    ShowDiagnostics,
+    ConnectLastFm,
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LastFmStatus {
+    /// No API credentials, so the menu item is left out.
+    Unavailable,
+    Disconnected,
+    Authorizing,
+    Connected,
+    /// Last.fm stopped taking the session key; scrobbles wait in the queue.
+    Expired,
+}
+
+impl LastFmStatus {
+    fn label(self) -> &'static str {
+        match self {
+            LastFmStatus::Unavailable | LastFmStatus::Disconnected => "Connect Last.fm…",
+            LastFmStatus::Authorizing => "Waiting for Last.fm authorization…",
+            LastFmStatus::Connected => "Last.fm connected",
+            LastFmStatus::Expired => "Reconnect Last.fm (session expired)…",
+        }
+    }
+
+    /// Whether clicking the item starts the authorization.
+    fn can_connect(self) -> bool {
+        matches!(self, LastFmStatus::Disconnected | LastFmStatus::Expired)
+    }
+}
+
+/// A "Recently played" entry: the menu label and the track to go back to.
+#[derive(Clone, Debug)]
+pub struct RecentPlay {
+    pub label: String,
+    pub video_id: String,
+}
+
+impl RecentPlay {
+    /// "Title — Artist", cut short for the menu. Plays without a video ID
+    /// can't be navigated to and give `None`.
+    pub fn from_record(record: &PlayRecord) -> Option<Self> {
+        Self::new(record.video_id.as_deref(), record.title.as_deref(), record.artist.as_deref())
+    }
+
+    pub fn from_track(track: &LastTrackInfo) -> Option<Self> {
+        Self::new(track.video_id.as_deref(), track.title.as_deref(), track.artist.as_deref())
+    }
+
+    fn new(video_id: Option<&str>, title: Option<&str>, artist: Option<&str>) -> Option<Self> {
+        let video_id = video_id.filter(|id| !id.is_empty())?.to_string();
+        let title = title.unwrap_or("Unknown title");
+        let label = match artist {
+            Some(artist) => format!("{} — {}", title, artist),
+            None => title.to_string(),
+        };
+        Some(RecentPlay {
+            label: truncate(&label, RECENT_LABEL_MAX_CHARS),
+            video_id,
+        })
+    }
+}
+
+/// Keeps the recent plays of this session in memory, for when the history
+/// database is off. `on_change` gets the list, newest first, whenever a
+/// track starts. Returns when the event bus is closed.
+pub fn run_session_recents(events: Receiver<TrackEvent>, on_change: impl Fn(Vec<RecentPlay>)) {
+    let mut recent_plays: Vec<RecentPlay> = Vec::new();
+
+    for event in events {
+        if event.kind != TrackEventKind::Started {
+            continue;
+        }
+        let Some(play) = RecentPlay::from_track(&event.track) else {
+            continue;
+        };
+        recent_plays.retain(|seen| seen.video_id != play.video_id);
+        recent_plays.insert(0, play);
+        recent_plays.truncate(RECENT_PLAYS_LIMIT);
+        on_change(recent_plays.clone());
+    }
+}
+
+pub struct Tray {
+    // Dropping the tray removes the icon, so it is kept alive here.
+    system_tray: SystemTray,
+    actions: Vec<(MenuId, TrayAction)>,
+    focus_mode_item: CustomMenuItem,
+    autostart_item: Option<CustomMenuItem>,
+    lastfm_item: Option<CustomMenuItem>,
+    link_items: Vec<CustomMenuItem>,
+    // Kept so the menu can be rebuilt when the recent plays change.
+    focus_mode: bool,
+    /// `None` where starting with the system isn't supported.
+    autostart: Option<bool>,
+    track_link_available: bool,
+    lastfm_status: LastFmStatus,
+    recent_plays: Vec<RecentPlay>,
+}
+
+/// A freshly built menu and the handles the tray keeps into it.
+struct Menu {
+    menu: ContextMenu,
+    actions: Vec<(MenuId, TrayAction)>,
+    focus_mode_item: CustomMenuItem,
+    autostart_item: Option<CustomMenuItem>,
+    lastfm_item: Option<CustomMenuItem>,
+    link_items: Vec<CustomMenuItem>,
+}
+
+impl Tray {
+    pub fn build<T: 'static>(
+        event_loop: &EventLoopWindowTarget<T>,
+        icon: Icon,
+        focus_mode: bool,
+        autostart: Option<bool>,
+        lastfm_status: LastFmStatus,
+    ) -> Result<Self, OsError> {
+        let Menu {
+            menu,
+            actions,
+            focus_mode_item,
+            autostart_item,
+            lastfm_item,
+            link_items,
+        } = build_menu(focus_mode, autostart, lastfm_status, false, &[]);
+
+        let system_tray = SystemTrayBuilder::new(icon, Some(menu))
+            .with_tooltip("ytune")
+            .build(event_loop)?;
+
+        Ok(Tray {
+            system_tray,
+            actions,
+            focus_mode_item,
+            autostart_item,
+            lastfm_item,
+            link_items,
+            focus_mode,
+            autostart,
+            track_link_available: false,
+            lastfm_status,
+            recent_plays: Vec::new(),
+        })
+    }
+
+    pub fn action_for(&self, menu_id: MenuId) -> Option<TrayAction> {
+        self.actions
+            .iter()
+            .find(|(id, _)| *id == menu_id)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn recent_play(&self, index: usize) -> Option<&RecentPlay> {
+        self.recent_plays.get(index)
+    }
+
+    pub fn set_focus_mode(&mut self, enabled: bool) {
+        self.focus_mode = enabled;
+        self.focus_mode_item.set_selected(enabled);
+    }
+
+    pub fn set_autostart(&mut self, enabled: bool) {
+        if let Some(item) = self.autostart_item.as_mut() {
+            self.autostart = Some(enabled);
+            item.set_selected(enabled);
+        }
+    }
+
+    /// Items can't be inserted into an existing menu, so the whole menu is
+    /// rebuilt with the new submenu.
+    pub fn set_recent_plays(&mut self, recent_plays: Vec<RecentPlay>) {
+        let Menu {
+            menu,
+            actions,
+            focus_mode_item,
+            autostart_item,
+            lastfm_item,
+            link_items,
+        } = build_menu(
+            self.focus_mode,
+            self.autostart,
+            self.lastfm_status,
+            self.track_link_available,
+            &recent_plays,
+        );
+        self.system_tray.set_menu(&menu);
+        self.actions = actions;
+        self.focus_mode_item = focus_mode_item;
+        self.autostart_item = autostart_item;
+        self.link_items = link_items;
+        self.lastfm_item = lastfm_item;
+        self.recent_plays = recent_plays;
+    }
+
+    /// The copy-link items stay greyed out while no track is loaded.
+    pub fn set_track_link_available(&mut self, available: bool) {
+        self.track_link_available = available;
+        for item in &mut self.link_items {
+            item.set_enabled(available);
+        }
+    }
+
+    pub fn set_lastfm_status(&mut self, status: LastFmStatus) {
+        self.lastfm_status = status;
+        if let Some(item) = self.lastfm_item.as_mut() {
+            item.set_title(status.label());
+            item.set_enabled(status.can_connect());
+        }
+    }
+}
+
+fn build_menu(
+    focus_mode: bool,
+    autostart: Option<bool>,
+    lastfm_status: LastFmStatus,
+    track_link_available: bool,
+    recent_plays: &[RecentPlay],
+) -> Menu {
+    let mut menu = ContextMenu::new();
+    let mut actions = Vec::new();
+
+    // Linux trays don't report clicks on the icon, so showing needs a menu item.
+    let show_item = menu.add_item(MenuItemAttributes::new("Show ytune"));
+    actions.push((show_item.id(), TrayAction::ShowWindow));
+    menu.add_native_item(MenuItem::Separator);
+
+    if !recent_plays.is_empty() {
+        let mut recent_menu = ContextMenu::new();
+        for (index, play) in recent_plays.iter().enumerate() {
+            let item = recent_menu.add_item(MenuItemAttributes::new(&play.label));
+            actions.push((item.id(), TrayAction::PlayRecent(index)));
+        }
+        menu.add_submenu("Recently played", true, recent_menu);
+    }
+
+    let focus_mode_item =
+        menu.add_item(MenuItemAttributes::new("Focus mode").with_selected(focus_mode));
+    actions.push((focus_mode_item.clone().id(), TrayAction::ToggleFocusMode));
+
+    let autostart_item = autostart.map(|enabled| {
+        let item = menu.add_item(MenuItemAttributes::new("Start with system").with_selected(enabled));
+        actions.push((item.clone().id(), TrayAction::ToggleAutostart));
+        item
+    });
+
+    let lastfm_item = (lastfm_status != LastFmStatus::Unavailable).then(|| {
+        let item = menu.add_item(
+            MenuItemAttributes::new(lastfm_status.label())
+                .with_enabled(lastfm_status.can_connect()),
+        );
+        actions.push((item.clone().id(), TrayAction::ConnectLastFm));
+        item
+    });
+
+    let copy_track_item = menu.add_item(MenuItemAttributes::new("Copy track info"));
+    actions.push((copy_track_item.id(), TrayAction::CopyTrack));
+
+    let share_card_item = menu.add_item(MenuItemAttributes::new("Save share card"));
+    actions.push((share_card_item.id(), TrayAction::ShareCard));
+
+    let mut link_items = Vec::new();
+    for (label, action) in [
+        ("Copy YouTube Music link", TrayAction::CopyMusicLink),
+        ("Copy YouTube link", TrayAction::CopyYouTubeLink),
+    ] {
+        let item = menu.add_item(MenuItemAttributes::new(label).with_enabled(track_link_available));
+        actions.push((item.clone().id(), action));
+        link_items.push(item);
+    }
+
+    let refresh_item = menu.add_item(MenuItemAttributes::new("Refresh now playing"));
+    actions.push((refresh_item.id(), TrayAction::RefreshNowPlaying));
+
+    let stats_item = menu.add_item(MenuItemAttributes::new("Listening stats"));
+    actions.push((stats_item.id(), TrayAction::ShowStats));
+
+    let export_item = menu.add_item(MenuItemAttributes::new("Export play history"));
+    actions.push((export_item.id(), TrayAction::ExportHistory));
+
+    let diagnostics_item = menu.add_item(MenuItemAttributes::new("Diagnostics"));
+    actions.push((diagnostics_item.id(), TrayAction::ShowDiagnostics));
+
+    menu.add_native_item(MenuItem::Separator);
+    let quit_item = menu.add_item(MenuItemAttributes::new("Quit ytune"));
+    actions.push((quit_item.id(), TrayAction::Quit));
+
+    Menu {
+        menu,
+        actions,
+        focus_mode_item,
+        autostart_item,
+        lastfm_item,
+        link_items,
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars - 1).collect();
+    truncated.push('…');
+    truncated
+}
@@ -0,0 +1,78 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often the event loop pings the page.
+pub const PING_INTERVAL: Duration = Duration::from_secs(15);
+/// Answered from the page by `__ytunePing`; evaluating a script still works
+/// while the window is hidden and the page's own timers are throttled.
+pub const PING_SCRIPT: &str = "window.__ytunePing && window.__ytunePing();";
+/// Unanswered pings before the page counts as crashed, doubled after each
+/// reload that didn't stick.
+const MISSED_PINGS: u32 = 3;
+const MAX_RELOADS: u32 = 5;
+/// A reload counts as having worked once the page keeps answering this long.
+const HEALTHY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+pub enum Check {
+    Ping,
+    /// The page stopped answering; this is reload `attempt` of `MAX_RELOADS`.
+    Reload { attempt: u32, max: u32 },
+    /// `MAX_RELOADS` reloads didn't bring it back. Returned once; pings go
+    /// on in case the page recovers by itself.
+    GiveUp,
+}
+
+/// Notices a crashed or hung renderer, which leaves a blank window and a
+/// frozen presence, by the page no longer answering pings.
+#[derive(Default)]
+pub struct PageWatchdog {
+    unanswered: Arc<AtomicU32>,
+    reloads: u32,
+    last_reload: Option<Instant>,
+    /// Whether the page hasn't answered since the last reload.
+    reloading: bool,
+}
+
+impl PageWatchdog {
+    /// For the IPC handler to reset on every answer.
+    pub fn answers(&self) -> Arc<AtomicU32> {
+        Arc::clone(&self.unanswered)
+    }
+
+    /// Called every `PING_INTERVAL` before pinging.
+    pub fn check(&mut self) -> Check {
+        let missed = self.unanswered.fetch_add(1, Ordering::SeqCst);
+        if missed == 0 {
+            if self.reloading {
+                log::info!("Page is answering again after the reload");
+                self.reloading = false;
+            }
+            if self.last_reload.is_some_and(|at| at.elapsed() >= HEALTHY_AFTER) {
+                self.reloads = 0;
+                self.last_reload = None;
+            }
+        }
+
+        let threshold = MISSED_PINGS << self.reloads.min(MAX_RELOADS - 1);
+        if missed < threshold {
+            return Check::Ping;
+        }
+        if self.reloads >= MAX_RELOADS {
+            return if missed == threshold { Check::GiveUp } else { Check::Ping };
+        }
+
+        self.reloads += 1;
+        self.last_reload = Some(Instant::now());
+        self.reloading = true;
+        self.unanswered.store(0, Ordering::SeqCst);
+        Check::Reload {
+            attempt: self.reloads,
+            max: MAX_RELOADS,
+        }
+    }
+}
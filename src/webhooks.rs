@@ -0,0 +1,119 @@
+use std::{sync::mpsc::Receiver, thread, time::Duration};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    api::types::{StreamEvent, StreamMessage},
+    config::WebhookConfig,
+    events::TrackEvent,
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Hex HMAC-SHA256 of the request body, keyed with `webhook.secret`.
+const SIGNATURE_HEADER: &str = "X-Ytune-Signature";
+
+/// POSTs the selected track events to every configured URL. Runs on its own
+/// thread, so slow or failing endpoints never hold up the event bus. Returns
+/// when the bus is closed.
+pub fn run_sender(config: WebhookConfig, events: Receiver<TrackEvent>) {
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+
+    for event in events {
+        if !config.events.contains(&event.kind) {
+            continue;
+        }
+
+        let body = payload(&event);
+        let signature = config.secret.as_deref().map(|secret| sign(secret, &body));
+
+        for url in &config.urls {
+            deliver(&agent, url, &body, signature.as_deref());
+        }
+    }
+}
+
+/// The same message `/api/events` sends for the event, so receivers can
+/// share a parser with it.
+fn payload(event: &TrackEvent) -> String {
+    let message = StreamMessage::new(StreamEvent::from(event.kind), event.timestamp, &event.track);
+    serde_json::to_string(&message).unwrap_or_default()
+}
+
+fn deliver(agent: &ureq::Agent, url: &str, body: &str, signature: Option<&str>) {
+    for attempt in 1..=MAX_ATTEMPTS {
+        let mut request = agent.post(url).set("Content-Type", "application/json");
+        if let Some(signature) = signature {
+            request = request.set(SIGNATURE_HEADER, &format!("sha256={}", signature));
+        }
+
+        match request.send_string(body) {
+            Ok(_) => return,
+            // The endpoint understood and refused the request; retrying won't help.
+            Err(ureq::Error::Status(status, _)) if (400..500).contains(&status) && status != 429 => {
+                log::warn!("Webhook {} rejected the event with status {}", url, status);
+                return;
+            }
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                log::debug!("Webhook {} failed (attempt {}): {}", url, attempt, e);
+                thread::sleep(RETRY_DELAY * attempt);
+            }
+            Err(e) => log::warn!("Webhook {} failed after {} attempts: {}", url, MAX_ATTEMPTS, e),
+        }
+    }
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{events::TrackEventKind, LastTrackInfo};
+
+    #[test]
+    fn payload_is_a_versioned_stream_message() {
+        let event = TrackEvent {
+            kind: TrackEventKind::Paused,
+            track: LastTrackInfo {
+                title: Some("Song".to_string()),
+                artist: Some("Artist".to_string()),
+                video_id: Some("abcdefghijk".to_string()),
+                duration_sec: Some(200),
+                position_sec: Some(42),
+                ..LastTrackInfo::default()
+            },
+            timestamp: 1_700_000_000,
+        };
+        let payload: serde_json::Value = serde_json::from_str(&payload(&event)).unwrap();
+        assert_eq!(payload["v"], 1);
+        assert_eq!(payload["event"], "paused");
+        assert_eq!(payload["timestamp"], 1_700_000_000u64);
+        assert_eq!(payload["track"], serde_json::to_value(&event.track).unwrap());
+        assert_eq!(payload["track"]["title"], "Song");
+        assert_eq!(payload["track"]["position_sec"], 42);
+        assert_eq!(payload["track"]["is_playing"], false);
+        assert_eq!(
+            payload.as_object().unwrap().keys().collect::<Vec<_>>(),
+            ["event", "timestamp", "track", "v"]
+        );
+    }
+
+    #[test]
+    fn signature_is_the_hex_hmac_of_the_body() {
+        // RFC 4231 test case 2.
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
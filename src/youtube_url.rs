@@ -0,0 +1,142 @@
+use wry::webview::Url;
+
+/// Hosts whose watch and playlist URLs `play_target` understands.
+const YOUTUBE_HOSTS: [&str; 4] = ["music.youtube.com", "www.youtube.com", "youtube.com", "m.youtube.com"];
+const VIDEO_ID_LENGTH: usize = 11;
+
+/// A page that plays what `/api/play-url` was given.
+#[derive(Debug)]
+pub struct PlayTarget {
+    pub url: Url,
+    /// The video that should end up playing, when the input names one.
+    pub video_id: Option<String>,
+}
+
+/// Asks the image CDN for album art `size` pixels square, by rewriting the
+/// `=w60-h60-l90-rj` or `=s120` style options on `googleusercontent.com` and
+/// `ggpht.com` URLs. Other options are kept. URLs without size options, like
+/// the fixed-size `i.ytimg.com` thumbnails, come back unchanged, as does
+/// everything when `size` is 0.
+pub fn resize_youtube_art_url(url: &str, size: u32) -> String {
+    // Thumbnails carry a signed query instead; changing them breaks the URL.
+    if size == 0 || url.contains('?') {
+        return url.to_string();
+    }
+    let Some((base, options)) = url.rsplit_once('=') else {
+        return url.to_string();
+    };
+
+    let mut resized = false;
+    let options: Vec<String> = options
+        .split('-')
+        .map(|option| match option.strip_prefix(['w', 'h', 's']) {
+            Some(digits) if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) => {
+                resized = true;
+                format!("{}{}", &option[..1], size)
+            }
+            _ => option.to_string(),
+        })
+        .collect();
+
+    if resized {
+        format!("{}={}", base, options.join("-"))
+    } else {
+        url.to_string()
+    }
+}
+
+/// Turns a bare video ID, or a YouTube or YouTube Music watch, playlist or
+/// album URL, into the page on `site_host` that plays it. Playlists go
+/// through the watch page so they start playing rather than just open.
+/// Anything else is an error saying what was expected.
+pub fn play_target(input: &str, site_host: &str) -> Result<PlayTarget, String> {
+    let input = input.trim();
+    if is_video_id(input) {
+        return Ok(watch_page(site_host, Some(input), None));
+    }
+
+    let url = Url::parse(input).map_err(|e| format!("Not a video ID or URL: {}", e))?;
+    if !matches!(url.scheme(), "https" | "http") {
+        return Err("Only http and https URLs can be played".to_string());
+    }
+    let query = |key: &str| {
+        url.query_pairs()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.into_owned())
+    };
+    let list = query("list").filter(|list| is_id(list));
+
+    match (url.host_str().unwrap_or_default(), url.path()) {
+        ("youtu.be", path) if is_video_id(&path[1..]) => Ok(watch_page(site_host, Some(&path[1..]), list.as_deref())),
+        (host, "/watch") if YOUTUBE_HOSTS.contains(&host) => {
+            let video_id = query("v").filter(|id| is_video_id(id));
+            if video_id.is_none() && list.is_none() {
+                return Err("The watch URL names no video or playlist".to_string());
+            }
+            Ok(watch_page(site_host, video_id.as_deref(), list.as_deref()))
+        }
+        (host, "/playlist") if YOUTUBE_HOSTS.contains(&host) => match list {
+            Some(list) => Ok(watch_page(site_host, None, Some(&list))),
+            None => Err("The playlist URL names no playlist".to_string()),
+        },
+        // Albums have no ID the watch page takes, so their page is opened.
+        ("music.youtube.com", path) if site_host == "music.youtube.com" => match path.strip_prefix("/browse/") {
+            Some(album) if album.starts_with("MPREb") && is_id(album) => Ok(PlayTarget {
+                url: Url::parse(&format!("https://{}/browse/{}", site_host, album)).expect("album URL is valid"),
+                video_id: None,
+            }),
+            _ => Err("Only watch, playlist and album URLs can be played".to_string()),
+        },
+        _ => Err("Only YouTube Music and YouTube watch, playlist and album URLs can be played".to_string()),
+    }
+}
+
+/// The bare `/watch?v=` link on `base_url` for a video ID or a watch URL,
+/// without the playlist, index and start time a shared link shouldn't
+/// carry. A clean link comes back as it was. None when no video is named.
+pub fn share_url(base_url: &str, video: &str) -> Option<String> {
+    let video = video.trim();
+    let video_id = if is_video_id(video) {
+        video.to_string()
+    } else {
+        let url = Url::parse(video).ok()?;
+        match (url.host_str().unwrap_or_default(), url.path()) {
+            ("youtu.be", path) => path[1..].to_string(),
+            (host, "/watch") if YOUTUBE_HOSTS.contains(&host) => {
+                url.query_pairs().find(|(name, _)| name == "v")?.1.into_owned()
+            }
+            _ => return None,
+        }
+    };
+    is_video_id(&video_id).then(|| format!("{}/watch?v={}", base_url, video_id))
+}
+
+fn watch_page(site_host: &str, video_id: Option<&str>, list: Option<&str>) -> PlayTarget {
+    let mut url = Url::parse(&format!("https://{}/watch", site_host)).expect("watch URL is valid");
+    {
+        let mut query = url.query_pairs_mut();
+        if let Some(video_id) = video_id {
+            query.append_pair("v", video_id);
+        }
+        if let Some(list) = list {
+            query.append_pair("list", list);
+        }
+    }
+    PlayTarget {
+        url,
+        video_id: video_id.map(str::to_string),
+    }
+}
+
+pub fn is_video_id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+pub fn is_video_id(text: &str) -> bool {
+    text.len() == VIDEO_ID_LENGTH && is_id(text)
+}
+
+/// Playlist and album IDs are made of the same characters, at other lengths.
+fn is_id(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(is_video_id_char)
+}
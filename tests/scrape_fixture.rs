@@ -0,0 +1,119 @@
+// Runs dev/scrape-fixture.html against src/js/init.js in a hidden webview and
+// fails when a case's trackUpdate differs from what it expects. A webview
+// needs a display; on a headless Linux machine run it under `xvfb-run`.
+// Without one it fails, unless YTUNE_SKIP_SCRAPE_FIXTURE=1 says to skip it.
+
+use std::{
+    process,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use wry::{
+    application::{
+        event::{Event, StartCause},
+        event_loop::{ControlFlow, EventLoop},
+        platform::run_return::EventLoopExtRunReturn,
+        window::WindowBuilder,
+    },
+    webview::WebViewBuilder,
+};
+
+const FIXTURE_HTML: &str = include_str!("../dev/scrape-fixture.html");
+const INIT_JS: &str = include_str!("../src/js/init.js");
+/// The fixture loads the script from disk; the test inlines it instead.
+const INIT_SCRIPT_TAG: &str = r#"<script src="../src/js/init.js"></script>"#;
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Set to 1 to skip the test on purpose where no display can be had.
+const SKIP_VAR: &str = "YTUNE_SKIP_SCRAPE_FIXTURE";
+
+/// What the fixture page posts once every case has run.
+#[derive(Debug, Deserialize)]
+struct Report {
+    total: usize,
+    failed: Vec<FailedCase>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FailedCase {
+    name: String,
+    problems: Vec<String>,
+}
+
+fn main() {
+    if !has_display() {
+        if std::env::var(SKIP_VAR).is_ok_and(|value| value == "1") {
+            println!("scrape_fixture: skipped, {}=1 and no display", SKIP_VAR);
+            return;
+        }
+        eprintln!(
+            "scrape_fixture: no display to open a webview on; run it under xvfb-run, or set {}=1 to skip it",
+            SKIP_VAR
+        );
+        process::exit(1);
+    }
+    assert!(FIXTURE_HTML.contains(INIT_SCRIPT_TAG), "the fixture no longer loads src/js/init.js");
+    let html = FIXTURE_HTML.replace(INIT_SCRIPT_TAG, &format!("<script>\n{}\n</script>", INIT_JS));
+
+    let mut event_loop = EventLoop::<String>::with_user_event();
+    let proxy = event_loop.create_proxy();
+    let window = WindowBuilder::new()
+        .with_title("ytune scrape fixture")
+        .with_visible(false)
+        .build(&event_loop)
+        .expect("failed to create the fixture window");
+    let _webview = WebViewBuilder::new(window)
+        .and_then(|builder| builder.with_html(html))
+        .and_then(|builder| {
+            builder
+                .with_ipc_handler(move |_, message| {
+                    let _ = proxy.send_event(message);
+                })
+                .build()
+        })
+        .expect("failed to create the fixture webview");
+
+    let deadline = Instant::now() + TIMEOUT;
+    let mut message = None;
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::WaitUntil(deadline);
+        match event {
+            Event::UserEvent(report) => {
+                message = Some(report);
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => *control_flow = ControlFlow::Exit,
+            _ => {}
+        }
+    });
+
+    let Some(message) = message else {
+        eprintln!("scrape_fixture: the fixture page reported nothing within {:?}", TIMEOUT);
+        process::exit(1);
+    };
+    let report: Report = serde_json::from_str(&message).expect("the fixture page sent an unreadable report");
+    for case in &report.failed {
+        eprintln!("FAIL {}", case.name);
+        for problem in &case.problems {
+            eprintln!("    {}", problem);
+        }
+    }
+    println!(
+        "scrape_fixture: {} of {} cases passed",
+        report.total - report.failed.len(),
+        report.total
+    );
+    if !report.failed.is_empty() {
+        process::exit(1);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn has_display() -> bool {
+    std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn has_display() -> bool {
+    true
+}